@@ -0,0 +1,494 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Streaming the contents of a temporary directory as an archive.
+
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+use super::{Error, TempDir};
+use walk::{relative, walk};
+
+/// The archive formats `TempDir::archive_to` can write.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    /// A POSIX ustar archive, with GNU long name records for paths of more
+    /// than 100 bytes.
+    Tar,
+    /// A zip archive with uncompressed entries. Archives of 4 GiB or more, or
+    /// with more than 65535 entries, are not supported.
+    Zip,
+}
+
+impl TempDir {
+
+    /// Writes the contents of the temporary directory to `writer` as an
+    /// archive in `format`, reading every file straight from the directory,
+    /// so no copy of the tree is ever made on disk. Entries are named
+    /// relative to the directory, with `/` separators, and are written in
+    /// sorted order. Only regular files, directories and symbolic links are
+    /// included; symbolic links are stored as links.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::net::TcpStream;
+    /// use tempdir::{ArchiveFormat, TempDir};
+    ///
+    /// let results = TempDir::new("results").unwrap();
+    /// // ... produce results ...
+    /// let stream = TcpStream::connect("collector:9000").unwrap();
+    /// results.archive_to(stream, ArchiveFormat::Tar).unwrap();
+    /// ```
+    pub fn archive_to<W: Write>(&self, writer: W, format: ArchiveFormat) -> io::Result<()> {
+        let mut items = Vec::new();
+        for entry in try!(walk(self.path())).into_iter() {
+            let name = match relative(self.path(), &entry.path).to_str() {
+                Some(name) => name.replace("\\", "/"),
+                None => {
+                    return Err(Error::NonUnicodeName(entry.path.clone()).into_io_error())
+                }
+            };
+            let file_type = entry.metadata.file_type();
+            let kind = if file_type.is_dir() {
+                Kind::Dir
+            } else if file_type.is_file() {
+                Kind::File
+            } else if file_type.is_symlink() {
+                let target = try!(fs::read_link(&entry.path));
+                Kind::Symlink(target.to_string_lossy().replace("\\", "/"))
+            } else {
+                continue;
+            };
+            items.push(Item {
+                size: if kind == Kind::File { entry.metadata.len() } else { 0 },
+                mode: mode(&entry.metadata, &kind),
+                mtime: entry.metadata.modified().ok()
+                                    .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+                                    .map(|d| d.as_secs())
+                                    .unwrap_or(0),
+                name: name,
+                kind: kind,
+                path: entry.path,
+            });
+        }
+        let mut writer = Counting { inner: writer, written: 0 };
+        match format {
+            ArchiveFormat::Tar => tar::write(&mut writer, &items),
+            ArchiveFormat::Zip => zip::write(&mut writer, &items),
+        }
+    }
+}
+
+#[derive(PartialEq)]
+enum Kind {
+    Dir,
+    File,
+    Symlink(String),
+}
+
+/// An entry to archive.
+struct Item {
+    name: String,
+    kind: Kind,
+    mode: u32,
+    mtime: u64,
+    size: u64,
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+fn mode(metadata: &Metadata, _kind: &Kind) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn mode(metadata: &Metadata, kind: &Kind) -> u32 {
+    match *kind {
+        Kind::Dir | Kind::Symlink(..) => 0o755,
+        Kind::File if metadata.permissions().readonly() => 0o444,
+        Kind::File => 0o644,
+    }
+}
+
+/// Copies the file of `item` to `writer`, failing if it no longer has the
+/// size that was recorded for it.
+fn copy_file<W: Write>(item: &Item, writer: &mut W) -> io::Result<()> {
+    let copied = try!(io::copy(&mut try!(File::open(&item.path)).take(item.size), writer));
+    if copied != item.size {
+        return Err(Error::Changed(item.path.clone()).into_io_error());
+    }
+    Ok(())
+}
+
+/// A writer that knows how much has been written through it.
+struct Counting<W> {
+    inner: W,
+    written: u64,
+}
+
+impl<W: Write> Write for Counting<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = try!(self.inner.write(buf));
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+mod tar {
+    use std::io::{self, Write};
+
+    use super::{Counting, Item, Kind, copy_file};
+
+    const BLOCK: usize = 512;
+
+    pub fn write<W: Write>(writer: &mut Counting<W>, items: &[Item]) -> io::Result<()> {
+        for item in items.iter() {
+            let mut name = item.name.clone();
+            if item.kind == Kind::Dir {
+                name.push('/');
+            }
+            if name.len() > 100 {
+                try!(long_name(writer, b'L', &name));
+            }
+            let (flag, target) = match item.kind {
+                Kind::Dir => (b'5', ""),
+                Kind::File => (b'0', ""),
+                Kind::Symlink(ref target) => (b'2', &target[..]),
+            };
+            if target.len() > 100 {
+                try!(long_name(writer, b'K', target));
+            }
+            try!(header(writer, &name, item.mode, item.size, item.mtime, flag, target));
+            if item.kind == Kind::File {
+                try!(copy_file(item, writer));
+                try!(pad(writer, item.size));
+            }
+        }
+        // The end of the archive is marked by two empty blocks.
+        try!(writer.write_all(&[0; BLOCK]));
+        try!(writer.write_all(&[0; BLOCK]));
+        writer.flush()
+    }
+
+    /// Writes a GNU record holding a name or link target too long for the
+    /// header that follows it.
+    fn long_name<W: Write>(writer: &mut W, flag: u8, name: &str) -> io::Result<()> {
+        let size = name.len() as u64 + 1;
+        try!(header(writer, "././@LongLink", 0, size, 0, flag, ""));
+        try!(writer.write_all(name.as_bytes()));
+        try!(writer.write_all(&[0]));
+        pad(writer, size)
+    }
+
+    fn header<W: Write>(writer: &mut W, name: &str, mode: u32, size: u64, mtime: u64,
+                        flag: u8, target: &str) -> io::Result<()> {
+        if size >= 1 << 33 {
+            let detail = format!("{} is too large for a tar header", name);
+            return Err(Error::TooLarge(detail).into_io_error());
+        }
+        let mut block = [0u8; BLOCK];
+        field(&mut block[0..100], name.as_bytes());
+        octal(&mut block[100..108], mode as u64);
+        octal(&mut block[108..116], 0);
+        octal(&mut block[116..124], 0);
+        octal(&mut block[124..136], size);
+        octal(&mut block[136..148], mtime);
+        block[156] = flag;
+        field(&mut block[157..257], target.as_bytes());
+        field(&mut block[257..263], b"ustar\0");
+        field(&mut block[263..265], b"00");
+        // The checksum is computed with its own field filled with spaces.
+        field(&mut block[148..156], b"        ");
+        let sum = block.iter().fold(0u64, |sum, &b| sum + b as u64);
+        field(&mut block[148..156], format!("{:06o}\0 ", sum).as_bytes());
+        writer.write_all(&block)
+    }
+
+    /// Copies as much of `value` as fits into `field`.
+    fn field(field: &mut [u8], value: &[u8]) {
+        for (dst, &src) in field.iter_mut().zip(value.iter()) {
+            *dst = src;
+        }
+    }
+
+    fn octal(dst: &mut [u8], value: u64) {
+        let width = dst.len() - 1;
+        field(dst, format!("{:01$o}\0", value, width).as_bytes());
+    }
+
+    /// Pads data of `size` bytes to a whole number of blocks.
+    fn pad<W: Write>(writer: &mut W, size: u64) -> io::Result<()> {
+        let rest = (size % BLOCK as u64) as usize;
+        if rest == 0 {
+            return Ok(());
+        }
+        writer.write_all(&[0; BLOCK][rest..])
+    }
+}
+
+mod zip {
+    use std::io::{self, Write};
+
+    use super::{Counting, Item, Kind, copy_file};
+
+    const S_IFDIR: u32 = 0o040000;
+    const S_IFREG: u32 = 0o100000;
+    const S_IFLNK: u32 = 0o120000;
+    /// The checksum and sizes follow the data in a data descriptor.
+    const FLAG_DESCRIPTOR: u16 = 0x0008;
+    /// Names are UTF-8.
+    const FLAG_UTF8: u16 = 0x0800;
+    /// Made by: Unix, so that the mode is stored. Zip specification 2.0.
+    const MADE_BY: u16 = (3 << 8) | 20;
+    const VERSION: u16 = 20;
+
+    pub fn write<W: Write>(writer: &mut Counting<W>, items: &[Item]) -> io::Result<()> {
+        if items.len() > 0xffff {
+            return Err(too_large());
+        }
+        let mut central = Vec::new();
+        for item in items.iter() {
+            let offset = writer.written;
+            let mut name = item.name.clone();
+            // Files are read only once, so their checksum is not known until
+            // the data has been written, and goes in a data descriptor.
+            let (kind, data_crc, size, flags) = match item.kind {
+                Kind::Dir => {
+                    name.push('/');
+                    (S_IFDIR, 0, 0, FLAG_UTF8)
+                }
+                Kind::File => (S_IFREG, 0, item.size, FLAG_UTF8 | FLAG_DESCRIPTOR),
+                Kind::Symlink(ref target) => {
+                    (S_IFLNK, crc32(0, target.as_bytes()), target.len() as u64, FLAG_UTF8)
+                }
+            };
+            if size > 0xffffffff || offset > 0xffffffff {
+                return Err(too_large());
+            }
+            let (time, date) = dos_time(item.mtime);
+
+            let mut header = Vec::new();
+            u32_le(&mut header, 0x04034b50);
+            u16_le(&mut header, VERSION);
+            u16_le(&mut header, flags);
+            u16_le(&mut header, 0);
+            u16_le(&mut header, time);
+            u16_le(&mut header, date);
+            let (header_crc, header_size) = if flags & FLAG_DESCRIPTOR != 0 {
+                (0, 0)
+            } else {
+                (data_crc, size as u32)
+            };
+            u32_le(&mut header, header_crc);
+            u32_le(&mut header, header_size);
+            u32_le(&mut header, header_size);
+            u16_le(&mut header, name.len() as u16);
+            u16_le(&mut header, 0);
+            header.extend(name.as_bytes().iter().cloned());
+            try!(writer.write_all(&header));
+            let data_crc = match item.kind {
+                Kind::File => {
+                    let crc = {
+                        let mut checked = Checksummed { inner: &mut *writer, crc: 0 };
+                        try!(copy_file(item, &mut checked));
+                        checked.crc
+                    };
+                    let mut descriptor = Vec::new();
+                    u32_le(&mut descriptor, 0x08074b50);
+                    u32_le(&mut descriptor, crc);
+                    u32_le(&mut descriptor, size as u32);
+                    u32_le(&mut descriptor, size as u32);
+                    try!(writer.write_all(&descriptor));
+                    crc
+                }
+                Kind::Symlink(ref target) => {
+                    try!(writer.write_all(target.as_bytes()));
+                    data_crc
+                }
+                Kind::Dir => data_crc,
+            };
+
+            u32_le(&mut central, 0x02014b50);
+            u16_le(&mut central, MADE_BY);
+            u16_le(&mut central, VERSION);
+            u16_le(&mut central, flags);
+            u16_le(&mut central, 0);
+            u16_le(&mut central, time);
+            u16_le(&mut central, date);
+            u32_le(&mut central, data_crc);
+            u32_le(&mut central, size as u32);
+            u32_le(&mut central, size as u32);
+            u16_le(&mut central, name.len() as u16);
+            u16_le(&mut central, 0);
+            u16_le(&mut central, 0);
+            u16_le(&mut central, 0);
+            u16_le(&mut central, 0);
+            // The Unix mode goes in the upper half of the external
+            // attributes; 0x10 is the MS-DOS directory attribute.
+            let dos = if item.kind == Kind::Dir { 0x10 } else { 0 };
+            u32_le(&mut central, ((kind | item.mode) << 16) | dos);
+            u32_le(&mut central, offset as u32);
+            central.extend(name.as_bytes().iter().cloned());
+        }
+
+        let central_offset = writer.written;
+        if central_offset > 0xffffffff || central.len() as u64 > 0xffffffff {
+            return Err(too_large());
+        }
+        let mut end = Vec::new();
+        u32_le(&mut end, 0x06054b50);
+        u16_le(&mut end, 0);
+        u16_le(&mut end, 0);
+        u16_le(&mut end, items.len() as u16);
+        u16_le(&mut end, items.len() as u16);
+        u32_le(&mut end, central.len() as u32);
+        u32_le(&mut end, central_offset as u32);
+        u16_le(&mut end, 0);
+        try!(writer.write_all(&central));
+        try!(writer.write_all(&end));
+        writer.flush()
+    }
+
+    fn too_large() -> io::Error {
+        Error::TooLarge("too large for a zip archive without zip64".to_string()).into_io_error()
+    }
+
+    /// A writer computing the CRC-32 of what is written through it.
+    struct Checksummed<W> {
+        inner: W,
+        crc: u32,
+    }
+
+    impl<W: Write> Write for Checksummed<W> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let n = try!(self.inner.write(buf));
+            self.crc = crc32(self.crc, &buf[..n]);
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    /// Continues the CRC-32 `crc` over `bytes`.
+    pub fn crc32(crc: u32, bytes: &[u8]) -> u32 {
+        let mut crc = !crc;
+        for &b in bytes.iter() {
+            crc ^= b as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+            }
+        }
+        !crc
+    }
+
+    /// Converts seconds since the Unix epoch to an MS-DOS time and date,
+    /// which cannot represent anything before 1980.
+    fn dos_time(secs: u64) -> (u16, u16) {
+        const DOS_EPOCH: u64 = 315532800;
+        if secs < DOS_EPOCH {
+            return (0, (1 << 5) | 1);
+        }
+        let days = (secs / 86400) as i64;
+        let rem = secs % 86400;
+        let (year, month, day) = civil(days);
+        // Seven bits of years.
+        let year = if year > 2107 { 2107 } else { year };
+        let time = ((rem / 3600) << 11) | (((rem % 3600) / 60) << 5) | ((rem % 60) / 2);
+        let date = (((year - 1980) as u64) << 9) | ((month as u64) << 5) | day as u64;
+        (time as u16, date as u16)
+    }
+
+    /// Returns the year, month and day of `days` days after 1970-01-01.
+    fn civil(days: i64) -> (i64, u32, u32) {
+        let z = days + 719468;
+        let era = z / 146097;
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+        (year, month, day)
+    }
+
+    fn u16_le(buf: &mut Vec<u8>, value: u16) {
+        buf.push(value as u8);
+        buf.push((value >> 8) as u8);
+    }
+
+    fn u32_le(buf: &mut Vec<u8>, value: u32) {
+        u16_le(buf, value as u16);
+        u16_le(buf, (value >> 16) as u16);
+    }
+
+    #[cfg(test)]
+    mod test {
+
+        use super::{civil, crc32};
+
+        #[test]
+        fn test_zip_helpers() {
+            assert_eq!(crc32(0, b"123456789"), 0xcbf43926);
+            assert_eq!(civil(0), (1970, 1, 1));
+            assert_eq!(civil(11016), (2000, 2, 29));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs;
+
+    use super::super::*;
+
+    #[test]
+    fn test_archive_to_tar() {
+        let temp_dir = TempDir::new("test_archive_to_tar").unwrap();
+        fs::create_dir(&temp_dir.path().join("d")).unwrap();
+        temp_dir.write("d/file", b"hello").unwrap();
+        let mut tar = Vec::new();
+        temp_dir.archive_to(&mut tar, ArchiveFormat::Tar).unwrap();
+        // A directory header, a file header, one data block and the end.
+        assert_eq!(tar.len(), 5 * 512);
+        assert_eq!(&tar[0..2], b"d/");
+        assert_eq!(&tar[257..262], b"ustar");
+        assert_eq!(&tar[512..518], b"d/file");
+        assert_eq!(&tar[1024..1029], b"hello");
+    }
+
+    #[test]
+    fn test_archive_to_zip() {
+        let temp_dir = TempDir::new("test_archive_to_zip").unwrap();
+        temp_dir.write("file", b"hello").unwrap();
+        let mut zip = Vec::new();
+        temp_dir.archive_to(&mut zip, ArchiveFormat::Zip).unwrap();
+        assert_eq!(&zip[0..4], b"PK\x03\x04");
+        assert_eq!(&zip[30..34], b"file");
+        assert_eq!(&zip[34..39], b"hello");
+        // The file is streamed, with its checksum and sizes after the data.
+        assert_eq!(&zip[6..8], &[0x08, 0x08]);
+        assert_eq!(&zip[14..26], &[0; 12]);
+        assert_eq!(&zip[39..43], b"PK\x07\x08");
+        assert_eq!(&zip[43..55], &[0x86, 0xa6, 0x10, 0x36, 5, 0, 0, 0, 5, 0, 0, 0]);
+        assert_eq!(&zip[55..59], b"PK\x01\x02");
+        assert_eq!(&zip[zip.len() - 22..zip.len() - 18], b"PK\x05\x06");
+    }
+}
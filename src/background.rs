@@ -0,0 +1,107 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The process-wide queue behind `Cleanup::Background`.
+
+use libc;
+use rand::{self, Rng};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Condvar, Mutex, Once, ONCE_INIT};
+use std::thread;
+
+use identity::{self, Identity};
+
+/// The prefix of the names directories are renamed to while they wait for
+/// removal.
+pub const DELETING_PREFIX: &'static str = ".deleting-";
+
+lazy_static! {
+    static ref QUEUE: Mutex<Sender<(PathBuf, Option<Identity>)>> = Mutex::new(start());
+    /// How many queued directories have not been removed yet.
+    static ref PENDING: (Mutex<usize>, Condvar) = (Mutex::new(0), Condvar::new());
+}
+
+static AT_EXIT: Once = ONCE_INIT;
+
+fn start() -> Sender<(PathBuf, Option<Identity>)> {
+    let (tx, rx) = mpsc::channel::<(PathBuf, Option<Identity>)>();
+    thread::Builder::new().name("tempdir-cleanup".to_string()).spawn(move || {
+        for (path, identity) in rx.iter() {
+            let result = match identity {
+                Some(ref identity) => identity::remove_dir_all(&path, identity),
+                None => fs::remove_dir_all(&path),
+            };
+            if let Err(e) = result {
+                let _ = writeln!(&mut io::stderr(),
+                                 "tempdir: background removal of {} failed: {}",
+                                 path.display(), e);
+            }
+            let &(ref lock, ref cvar) = &*PENDING;
+            *lock.lock().unwrap() -= 1;
+            cvar.notify_all();
+        }
+    }).unwrap();
+    tx
+}
+
+extern "C" fn flush_at_exit() {
+    flush();
+}
+
+/// Renames `path` out of the way and queues it for removal on the cleanup
+/// thread. If it cannot be renamed, it is removed right away instead.
+pub fn remove(path: &Path, identity: Option<Identity>) -> io::Result<()> {
+    let suffix: String = rand::thread_rng().gen_ascii_chars().take(8).collect();
+    let aside = path.with_file_name(&format!("{}{}", DELETING_PREFIX, suffix));
+    if fs::rename(path, &aside).is_err() {
+        return match identity {
+            Some(ref identity) => identity::remove_dir_all(path, identity),
+            None => fs::remove_dir_all(path),
+        };
+    }
+    AT_EXIT.call_once(|| unsafe { libc::atexit(flush_at_exit); });
+    *PENDING.0.lock().unwrap() += 1;
+    QUEUE.lock().unwrap().send((aside, identity)).unwrap();
+    Ok(())
+}
+
+/// Blocks until every queued directory has been removed. Registered to run
+/// when the process exits.
+pub fn flush() {
+    let &(ref lock, ref cvar) = &*PENDING;
+    let mut pending = lock.lock().unwrap();
+    while *pending > 0 {
+        pending = cvar.wait(pending).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs::{self, File, PathExt};
+
+    use super::super::*;
+
+    #[test]
+    fn test_background_cleanup() {
+        let base = TempDir::new("test_background_cleanup").unwrap();
+        let mut temp_dir = TempDir::new_in(base.path(), "dir").unwrap();
+        temp_dir.set_cleanup(Cleanup::Background);
+        let path = temp_dir.path().to_path_buf();
+        fs::create_dir(&path.join("a")).unwrap();
+        File::create(&path.join("a/b")).unwrap();
+
+        temp_dir.close().unwrap();
+        assert!(!path.exists());
+        flush_cleanup();
+        assert_eq!(fs::read_dir(base.path()).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,224 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Creation and removal of temporary directories off the calling thread, for
+//! use from event loops and async code. The operations run on a small pool
+//! of threads owned by this crate and hand their results back through a
+//! `Blocking` handle, which can wake whatever is waiting on it, so they work
+//! with any executor.
+
+use std::io;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use super::{Error, TempDir};
+
+/// How many threads run blocking operations.
+const POOL_SIZE: usize = 4;
+
+/// A job for the pool. It is only ever called once.
+type Job = Box<FnMut() + Send>;
+
+lazy_static! {
+    static ref POOL: Mutex<Sender<Job>> = Mutex::new(start_pool());
+}
+
+fn start_pool() -> Sender<Job> {
+    let (tx, rx) = mpsc::channel::<Job>();
+    let rx = Arc::new(Mutex::new(rx));
+    for _ in 0..POOL_SIZE {
+        start_worker(rx.clone());
+    }
+    tx
+}
+
+fn start_worker(rx: Arc<Mutex<Receiver<Job>>>) {
+    thread::Builder::new().name("tempdir-blocking".to_string())
+                          .spawn(move || work(rx))
+                          .unwrap();
+}
+
+/// Starts a replacement for a worker taken down by a panicking job.
+struct Respawn(Arc<Mutex<Receiver<Job>>>);
+
+impl Drop for Respawn {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            start_worker(self.0.clone());
+        }
+    }
+}
+
+fn work(rx: Arc<Mutex<Receiver<Job>>>) {
+    let _respawn = Respawn(rx.clone());
+    loop {
+        let mut job = match rx.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+        job();
+    }
+}
+
+struct State<T> {
+    result: Option<io::Result<T>>,
+    notify: Option<Box<Fn() + Send>>,
+}
+
+struct Inner<T> {
+    state: Mutex<State<T>>,
+    ready: Condvar,
+}
+
+impl<T> Inner<T> {
+    fn complete(&self, result: io::Result<T>) {
+        let notify = {
+            let mut state = self.state.lock().unwrap();
+            state.result = Some(result);
+            self.ready.notify_all();
+            state.notify.take()
+        };
+        // Not called with the lock held, so that it may check on the
+        // operation.
+        if let Some(notify) = notify {
+            notify();
+        }
+    }
+}
+
+/// Completes an operation with a `Panicked` error if it unwinds instead of
+/// returning.
+struct Finish<T: Send>(Arc<Inner<T>>);
+
+impl<T: Send> Drop for Finish<T> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.0.complete(Err(Error::Panicked.into_io_error()));
+        }
+    }
+}
+
+/// A blocking operation running on the pool, whose result is collected with
+/// `wait`.
+///
+/// An executor or event loop that must not block registers a callback with
+/// `on_ready`, e.g. to wake the task waiting on the operation, and calls
+/// `wait` once `is_ready` returns true.
+pub struct Blocking<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T: Send + 'static> Blocking<T> {
+
+    /// Runs `f` on the pool.
+    fn spawn<F>(f: F) -> Blocking<T>
+        where F: FnOnce() -> io::Result<T> + Send + 'static
+    {
+        let inner = Arc::new(Inner {
+            state: Mutex::new(State { result: None, notify: None }),
+            ready: Condvar::new(),
+        });
+        let shared = inner.clone();
+        let mut f = Some(f);
+        let job: Job = Box::new(move || {
+            let finish = Finish(shared.clone());
+            let result = (f.take().unwrap())();
+            finish.0.complete(result);
+        });
+        POOL.lock().unwrap().send(job).unwrap();
+        Blocking { inner: inner }
+    }
+
+    /// Returns whether the operation has finished, so that `wait` returns
+    /// right away.
+    pub fn is_ready(&self) -> bool {
+        self.inner.state.lock().unwrap().result.is_some()
+    }
+
+    /// Registers `f` to be called once the operation has finished, on the
+    /// thread that ran it, or right away if it has finished already.
+    /// Replaces any callback registered before.
+    pub fn on_ready<F>(&self, f: F) where F: Fn() + Send + 'static {
+        {
+            let mut state = self.inner.state.lock().unwrap();
+            if state.result.is_none() {
+                state.notify = Some(Box::new(f));
+                return;
+            }
+        }
+        f();
+    }
+
+    /// Blocks until the operation has finished and returns its result.
+    pub fn wait(self) -> io::Result<T> {
+        let mut state = self.inner.state.lock().unwrap();
+        loop {
+            if let Some(result) = state.result.take() {
+                return result;
+            }
+            state = self.inner.ready.wait(state).unwrap();
+        }
+    }
+}
+
+impl TempDir {
+
+    /// Creates a temporary directory like `TempDir::new`, on a background
+    /// thread.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use tempdir::TempDir;
+    ///
+    /// let pending = TempDir::new_async("server");
+    /// // Do something else in the meantime.
+    /// let temp_dir = pending.wait().unwrap();
+    /// temp_dir.close_async().wait().unwrap();
+    /// ```
+    pub fn new_async(prefix: &str) -> Blocking<TempDir> {
+        let prefix = prefix.to_string();
+        Blocking::spawn(move || Ok(try!(TempDir::new(&prefix))))
+    }
+
+    /// Closes and removes the temporary directory like `close`, on a
+    /// background thread, so that removing a large tree does not block the
+    /// executor.
+    ///
+    /// Dropping a `TempDir` still removes it synchronously; call this to
+    /// clean up from async code instead.
+    pub fn close_async(self) -> Blocking<()> {
+        Blocking::spawn(move || Ok(try!(self.close())))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs::PathExt;
+    use std::sync::mpsc;
+
+    use super::super::*;
+
+    #[test]
+    fn test_async_create_and_close() {
+        let temp_dir = TempDir::new_async("test_async_create_and_close").wait().unwrap();
+        let path = temp_dir.path().to_path_buf();
+        temp_dir.create_file("a").unwrap();
+        assert!(path.exists());
+
+        let closing = temp_dir.close_async();
+        let (tx, rx) = mpsc::channel();
+        closing.on_ready(move || tx.send(()).unwrap());
+        rx.recv().unwrap();
+        assert!(closing.is_ready());
+        closing.wait().unwrap();
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,1046 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use rand::{self, Rng, SeedableRng, XorShiftRng};
+use std::cell::Cell;
+use std::env;
+use std::ffi::{AsOsStr, OsStr, OsString};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use config;
+use error::Operation;
+use fallback::{self, Substitution};
+use longpath;
+use faults;
+use minimal;
+use namelen;
+use ram;
+use report::CreationReport;
+use resolve::{Candidate, Source, diagnose};
+use space;
+use throttle;
+use user;
+use super::{Cleanup, Error, OwnerRecord, RetentionPolicy, TempDir};
+use super::{NUM_RAND_CHARS, NUM_RETRIES, temp_dir};
+
+/// Where the random part of names comes from.
+#[derive(Clone)]
+enum Names {
+    /// The thread-local generator, seeded from the OS.
+    Random,
+    /// A generator seeded by the user, shared between clones of the builder.
+    Seeded(Arc<Mutex<XorShiftRng>>),
+    /// The all-zero seed, which cannot seed a generator; creation fails.
+    ZeroSeed,
+    /// A user-supplied function of the attempt number.
+    Custom(Arc<Fn(u32) -> String + Send + Sync>),
+}
+
+impl fmt::Debug for Names {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Names::Random => write!(f, "Random"),
+            Names::Seeded(..) => write!(f, "Seeded"),
+            Names::ZeroSeed => write!(f, "ZeroSeed"),
+            Names::Custom(..) => write!(f, "Custom"),
+        }
+    }
+}
+
+/// A builder for `TempDir`s with non-default settings.
+///
+/// # Examples
+///
+/// ```no_run
+/// use tempdir::Builder;
+///
+/// // Create a uniquely named directory that is never deleted.
+/// let dir = Builder::new().prefix("scaffold").disable_cleanup(true).tempdir().unwrap();
+/// println!("created {}", dir.path().display());
+/// ```
+#[derive(Clone, Debug)]
+pub struct Builder {
+    prefix: OsString,
+    separator: OsString,
+    suffix: OsString,
+    rand_bytes: usize,
+    retention: RetentionPolicy,
+    cleanup: Cleanup,
+    per_user: bool,
+    minimal_syscalls: bool,
+    mode: u32,
+    retries: u32,
+    retry_delay: Duration,
+    force_cleanup: bool,
+    names: Names,
+    ascii_names: bool,
+    record_owner: bool,
+    owner_names: bool,
+    canonicalize: bool,
+    read_only_fallback: bool,
+    fallback_dirs: Option<Vec<PathBuf>>,
+    long_paths: bool,
+    prefer_ram: bool,
+    free_space: Option<u64>,
+}
+
+impl Builder {
+
+    /// Creates a builder with the default settings: names made of an empty
+    /// prefix and 12 random characters, mode 0700 on Unix, and recursive
+    /// removal on drop, subject to any `Config` overrides installed on the
+    /// current thread.
+    pub fn new() -> Builder {
+        Builder {
+            prefix: OsString::new(),
+            separator: OsString::from_str("."),
+            suffix: OsString::new(),
+            rand_bytes: NUM_RAND_CHARS,
+            retention: config::retention().unwrap_or(RetentionPolicy::Never),
+            cleanup: Cleanup::Recursive,
+            per_user: false,
+            minimal_syscalls: false,
+            mode: 0o700,
+            retries: 0,
+            retry_delay: Duration::from_millis(0),
+            force_cleanup: false,
+            names: Names::Random,
+            ascii_names: false,
+            record_owner: false,
+            owner_names: false,
+            canonicalize: false,
+            read_only_fallback: false,
+            fallback_dirs: None,
+            long_paths: false,
+            prefer_ram: false,
+            free_space: None,
+        }
+    }
+
+    /// Sets the prefix of the directory name.
+    pub fn prefix<P: ?Sized>(&mut self, prefix: &P) -> &mut Builder
+        where P: AsOsStr
+    {
+        self.prefix = prefix.as_os_str().to_os_string();
+        self
+    }
+
+    /// Sets the separator placed between a non-empty prefix and the random
+    /// characters. Defaults to `"."`.
+    pub fn separator<S: ?Sized>(&mut self, separator: &S) -> &mut Builder
+        where S: AsOsStr
+    {
+        self.separator = separator.as_os_str().to_os_string();
+        self
+    }
+
+    /// Sets the suffix appended after the random characters, e.g. `".work"`.
+    pub fn suffix<S: ?Sized>(&mut self, suffix: &S) -> &mut Builder
+        where S: AsOsStr
+    {
+        self.suffix = suffix.as_os_str().to_os_string();
+        self
+    }
+
+    /// If `ascii` is true, every non-ASCII character of the generated names
+    /// (from the prefix, separator, suffix or a custom name function) is
+    /// replaced by `_`, so that the names are the same on file systems that
+    /// normalize Unicode, like HFS+ and APFS, as everywhere else.
+    pub fn ascii_names(&mut self, ascii: bool) -> &mut Builder {
+        self.ascii_names = ascii;
+        self
+    }
+
+    /// If `owner` is true, names are made of the prefix, the id of the
+    /// creating process, the creation time in seconds since the Unix epoch
+    /// and the random characters, e.g. `build.4242.1425168000.x8Hq2Lw0aBcD`,
+    /// so that it is obvious who left a directory behind and when. `reap`
+    /// uses the process id to recognize orphans.
+    pub fn owner_names(&mut self, owner: bool) -> &mut Builder {
+        self.owner_names = owner;
+        self
+    }
+
+    /// Sets the number of random characters in the name. Fewer characters
+    /// make names easier for an attacker to guess and collisions more likely.
+    pub fn rand_bytes(&mut self, rand_bytes: usize) -> &mut Builder {
+        self.rand_bytes = rand_bytes;
+        self
+    }
+
+    /// Draws the random characters of names from a generator seeded with
+    /// `seed` instead of from the OS, so that the sequence of names is
+    /// reproducible. Clones of the builder share the generator.
+    ///
+    /// Seeded names are predictable, so they should only be used in
+    /// directories other users cannot write to. The seed must not be all
+    /// zeroes, or creating directories fails with `Error::ZeroSeed`.
+    pub fn seed(&mut self, seed: [u32; 4]) -> &mut Builder {
+        self.names = if seed == [0; 4] {
+            Names::ZeroSeed
+        } else {
+            Names::Seeded(Arc::new(Mutex::new(SeedableRng::from_seed(seed))))
+        };
+        self
+    }
+
+    /// Uses `f` instead of random characters in names. It is called with the
+    /// number of the attempt, starting at zero, so that it can pick another
+    /// name if the previous one was taken; if it returns the same name twice
+    /// in a row, creation gives up.
+    ///
+    /// ```no_run
+    /// use tempdir::Builder;
+    ///
+    /// let dir = Builder::new().name_fn(|n| format!("fixture-{}", n)).tempdir().unwrap();
+    /// ```
+    pub fn name_fn<F>(&mut self, f: F) -> &mut Builder
+        where F: Fn(u32) -> String + Send + Sync + 'static
+    {
+        self.names = Names::Custom(Arc::new(f));
+        self
+    }
+
+    /// Sets the retention policy of the created directory.
+    pub fn retention(&mut self, policy: RetentionPolicy) -> &mut Builder {
+        self.retention = policy;
+        self
+    }
+
+    /// Sets how the created directory is removed.
+    pub fn cleanup(&mut self, cleanup: Cleanup) -> &mut Builder {
+        self.cleanup = cleanup;
+        self
+    }
+
+    /// If `disable` is true, the created directory is never deleted: the
+    /// `TempDir` only provides unique naming and creation. This is a
+    /// shorthand for `retention(RetentionPolicy::Always)`; if `disable` is
+    /// false, a retention policy of `Always` is reset to `Never` and any
+    /// other policy is left alone.
+    pub fn disable_cleanup(&mut self, disable: bool) -> &mut Builder {
+        if disable {
+            self.retention = RetentionPolicy::Always;
+        } else if self.retention == RetentionPolicy::Always {
+            self.retention = RetentionPolicy::Never;
+        }
+        self
+    }
+
+    /// Makes removal of the created directory, on drop or `close`, retry up
+    /// to `retries` more times, waiting `delay` between attempts, if it
+    /// fails. This rides out e.g. virus scanners and indexing services on
+    /// Windows briefly holding files open.
+    pub fn cleanup_retries(&mut self, retries: u32, delay: Duration) -> &mut Builder {
+        self.retries = retries;
+        self.retry_delay = delay;
+        self
+    }
+
+    /// If `force` is true and removing the created directory fails, e.g.
+    /// because a test left read-only files in it on Windows, the read-only
+    /// attributes (on Unix, the owner's permissions) of everything inside
+    /// are reset and removal is attempted again.
+    pub fn force_cleanup(&mut self, force: bool) -> &mut Builder {
+        self.force_cleanup = force;
+        self
+    }
+
+    /// If `keep` is true, the created directory is kept, and its path printed
+    /// to stderr, if the thread is panicking when it is dropped, so that a
+    /// failing test leaves its evidence behind. This is a shorthand for
+    /// `retention(RetentionPolicy::OnFailure)`; if `keep` is false, a
+    /// retention policy of `OnFailure` is reset to `Never` and any other
+    /// policy is left alone.
+    pub fn keep_on_panic(&mut self, keep: bool) -> &mut Builder {
+        if keep {
+            self.retention = RetentionPolicy::OnFailure;
+        } else if self.retention == RetentionPolicy::OnFailure {
+            self.retention = RetentionPolicy::Never;
+        }
+        self
+    }
+
+    /// If `per_user` is true, directories are created inside a private
+    /// per-user directory (`user-$UID`, mode 0700 on Unix) below the base
+    /// directory instead of directly inside it, which keeps other users of a
+    /// shared `/tmp` from observing or interfering with them.
+    pub fn per_user(&mut self, per_user: bool) -> &mut Builder {
+        self.per_user = per_user;
+        self
+    }
+
+    /// If `minimal` is true, the directory is named, created and removed
+    /// using only the small, fixed set of system calls listed in
+    /// `minimal::SYSCALLS`, so that it works inside tightly seccomp-filtered
+    /// sandboxes. `minimal::init` must have been called first.
+    pub fn minimal_syscalls(&mut self, minimal: bool) -> &mut Builder {
+        self.minimal_syscalls = minimal;
+        if minimal {
+            self.cleanup = Cleanup::MinimalSyscalls;
+        } else if self.cleanup == Cleanup::MinimalSyscalls {
+            self.cleanup = Cleanup::Recursive;
+        }
+        self
+    }
+
+    /// If `record` is true, an `OwnerRecord` naming the creating process is
+    /// written to a `.tempdir-owner` file inside the created directory, so
+    /// that `reap`, or cleanup tools outside of Rust, can tell whether the
+    /// directory was left behind by a process that has since died.
+    pub fn record_owner(&mut self, record: bool) -> &mut Builder {
+        self.record_owner = record;
+        self
+    }
+
+    /// If `canonicalize` is true, the created directory's path is resolved
+    /// with `fs::canonicalize` before it is handed out, so that it compares
+    /// equal to paths the code under test canonicalized itself, e.g.
+    /// `/private/tmp/...` rather than `/tmp/...` on OS X. On Windows, the
+    /// path is then in `\\?\` form.
+    pub fn canonicalize(&mut self, canonicalize: bool) -> &mut Builder {
+        self.canonicalize = canonicalize;
+        self
+    }
+
+    /// If `fallback` is true and nothing can be created in the base
+    /// directory because it is read-only, as is common in locked-down
+    /// containers, the directory is created in the first writable one of the
+    /// `fallback_dirs` instead of failing. `TempDir::substitution` records
+    /// where it ended up and why.
+    pub fn read_only_fallback(&mut self, fallback: bool) -> &mut Builder {
+        self.read_only_fallback = fallback;
+        self
+    }
+
+    /// Sets the base directories `read_only_fallback` tries, in order.
+    /// Defaults to the directories named by the environment variables
+    /// `temp_dir` consults, the platform default, `std::env::temp_dir()`
+    /// and, on Unix, `/var/tmp` and `/dev/shm`.
+    pub fn fallback_dirs(&mut self, dirs: Vec<PathBuf>) -> &mut Builder {
+        self.fallback_dirs = Some(dirs);
+        self
+    }
+
+    /// If `long` is true, the created directory's path is in Windows'
+    /// extended-length `\\?\` form, so that trees nested deeper than the
+    /// usual 260 characters can be created inside of it through `path()`.
+    /// Removal always uses that form internally, whatever this is set to.
+    /// Ignored on other platforms.
+    pub fn long_paths(&mut self, long: bool) -> &mut Builder {
+        self.long_paths = long;
+        self
+    }
+
+    /// If `ram` is true, `tempdir` creates the directory on a memory-backed
+    /// file system, so that its contents never touch the disk. On Linux,
+    /// `/dev/shm`, `XDG_RUNTIME_DIR` and `temp_dir()` are tried in order,
+    /// and the first that `statfs` reports as tmpfs or ramfs and that can be
+    /// written to is used; if there is none, or on other platforms,
+    /// `temp_dir()` is used as usual. Has no effect on `tempdir_in`.
+    pub fn prefer_ram(&mut self, ram: bool) -> &mut Builder {
+        self.prefer_ram = ram;
+        self
+    }
+
+    /// Requires the file system the directory is created on to have at
+    /// least `bytes` of free space, as reported by `statvfs` on Unix and
+    /// `GetDiskFreeSpaceEx` on Windows. If it has less, creation fails with
+    /// an `Other` error giving both amounts, before anything is created.
+    pub fn require_free_space(&mut self, bytes: u64) -> &mut Builder {
+        self.free_space = Some(bytes);
+        self
+    }
+
+    /// Sets the permissions the directory is created with on Unix. The
+    /// default of `0o700` keeps other users out; more permissive modes are
+    /// applied exactly, regardless of the process umask (except in minimal
+    /// syscall mode, where the umask still applies). Ignored on other
+    /// platforms.
+    pub fn mode(&mut self, mode: u32) -> &mut Builder {
+        self.mode = mode;
+        self
+    }
+
+    /// Resolves and validates the base directory, seeds the random name
+    /// generator and, with `per_user`, creates the per-user parent
+    /// directory, all of which the first `tempdir` call would otherwise do
+    /// lazily.
+    ///
+    /// Returns the directory new temporary directories will be created in.
+    pub fn prewarm(&self) -> io::Result<PathBuf> {
+        let mut base = self.default_base();
+        if base.is_relative() {
+            base = try!(env::current_dir()).join(&base);
+        }
+        if !try!(fs::metadata(&base)).is_dir() {
+            return Err(Error::NotADirectory(base).into_io_error());
+        }
+        if let Names::ZeroSeed = self.names {
+            return Err(Error::ZeroSeed.into_io_error());
+        }
+        if self.minimal_syscalls {
+            try!(minimal::init());
+        } else {
+            rand::thread_rng().gen::<u32>();
+        }
+        if self.per_user {
+            base = try!(user::user_dir(&base));
+        }
+        Ok(base)
+    }
+
+    /// Creates the directory inside of `temp_dir()`, or of a memory-backed
+    /// directory with `prefer_ram`.
+    pub fn tempdir(&self) -> Result<TempDir, Error> {
+        self.tempdir_in(&self.default_base())
+    }
+
+    /// Returns the directory `tempdir` creates directories in.
+    fn default_base(&self) -> PathBuf {
+        let ram = if self.prefer_ram { ram::ram_dir() } else { None };
+        ram.unwrap_or_else(temp_dir)
+    }
+
+    /// Creates the directory inside of `tmpdir`.
+    pub fn tempdir_in(&self, tmpdir: &Path) -> Result<TempDir, Error> {
+        self.tempdir_in_counting(tmpdir, &Cell::new(0))
+    }
+
+    /// Creates the directory inside of `temp_dir()` like `tempdir`, and also
+    /// returns a report of how that went, for logging and monitoring.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use tempdir::Builder;
+    ///
+    /// let (dir, report) = Builder::new().prefix("job").tempdir_with_report().unwrap();
+    /// println!("{}", report);
+    /// ```
+    pub fn tempdir_with_report(&self) -> Result<(TempDir, CreationReport), Error> {
+        if let Some(ram) = if self.prefer_ram { ram::ram_dir() } else { None } {
+            return self.tempdir_in_reporting(&ram, None, Vec::new());
+        }
+        let diagnosis = diagnose();
+        let rejected = diagnosis.candidates.into_iter()
+                                           .filter(|c| c.rejection.is_some())
+                                           .collect();
+        self.tempdir_in_reporting(&diagnosis.path, Some(diagnosis.source), rejected)
+    }
+
+    /// Creates the directory inside of `tmpdir` like `tempdir_in`, and also
+    /// returns a report of how that went.
+    pub fn tempdir_in_with_report(&self, tmpdir: &Path)
+                                  -> Result<(TempDir, CreationReport), Error> {
+        self.tempdir_in_reporting(tmpdir, None, Vec::new())
+    }
+
+    fn tempdir_in_reporting(&self, tmpdir: &Path, source: Option<Source>,
+                            rejected: Vec<Candidate>)
+                            -> Result<(TempDir, CreationReport), Error> {
+        let start = Instant::now();
+        let attempts = Cell::new(0);
+        let dir = try!(self.tempdir_in_counting(tmpdir, &attempts));
+        let report = CreationReport {
+            base: dir.path().parent().map_or_else(|| PathBuf::new(""), |p| p.to_path_buf()),
+            source: source,
+            rejected: rejected,
+            substitution: dir.substitution().cloned(),
+            attempts: attempts.get(),
+            elapsed: start.elapsed(),
+            mode: if cfg!(unix) { Some(self.mode) } else { None },
+        };
+        Ok((dir, report))
+    }
+
+    /// Creates the directory inside of `tmpdir`, falling back to another
+    /// base directory if asked to, and counts the names tried. Errors are
+    /// reported as `Error::Failed`.
+    fn tempdir_in_counting(&self, tmpdir: &Path, attempts: &Cell<u32>)
+                           -> Result<TempDir, Error> {
+        if let Names::ZeroSeed = self.names {
+            return Err(Error::ZeroSeed);
+        }
+        self.tempdir_in_fallback(tmpdir, attempts).map_err(|e| {
+            Error::Failed(Operation::Create, tmpdir.to_path_buf(), attempts.get(), e)
+        })
+    }
+
+    fn tempdir_in_fallback(&self, tmpdir: &Path, attempts: &Cell<u32>) -> io::Result<TempDir> {
+        let err = match self.tempdir_in_exactly(tmpdir, attempts) {
+            Err(e) if self.read_only_fallback && fallback::is_read_only(&e) => e,
+            result => return result,
+        };
+        let candidates = match self.fallback_dirs {
+            Some(ref dirs) => dirs.clone(),
+            None => fallback::candidates(tmpdir),
+        };
+        for candidate in candidates.into_iter() {
+            if let Ok(mut dir) = self.tempdir_in_exactly(&candidate, attempts) {
+                dir.substitution = Some(Substitution {
+                    requested: tmpdir.to_path_buf(),
+                    used: candidate,
+                    reason: err.to_string(),
+                });
+                return Ok(dir);
+            }
+        }
+        Err(err)
+    }
+
+    /// Creates the directory inside of `tmpdir`, without falling back.
+    fn tempdir_in_exactly(&self, tmpdir: &Path, attempts: &Cell<u32>) -> io::Result<TempDir> {
+        if self.uses_mkdtemp() {
+            attempts.set(attempts.get() + 1);
+            return self.tempdir_mkdtemp(tmpdir);
+        }
+        let minimal = self.minimal_syscalls;
+        let mode = self.mode;
+        self.create_in(tmpdir, |path| {
+            attempts.set(attempts.get() + 1);
+            let created = if minimal {
+                minimal::create_dir(&path, mode)
+            } else {
+                create_dir(&path, mode)
+            };
+            created.and_then(|_| self.wrap(path))
+        })
+    }
+
+    /// Wraps the freshly created directory at `path` with this builder's
+    /// settings, and records its owner if asked to.
+    fn wrap(&self, path: PathBuf) -> io::Result<TempDir> {
+        let mut dir = if self.minimal_syscalls {
+            TempDir::with_identity(path, None)
+        } else {
+            TempDir::with_path(path)
+        };
+        dir.set_retention(self.retention);
+        dir.cleanup = self.cleanup;
+        dir.retries = self.retries;
+        dir.retry_delay = self.retry_delay;
+        dir.force_cleanup = self.force_cleanup;
+        dir.update_registration();
+        if self.canonicalize {
+            let canonical = try!(fs::canonicalize(dir.path()));
+            dir.set_path(canonical);
+        }
+        if self.record_owner {
+            let keep = self.retention == RetentionPolicy::Always;
+            try!(OwnerRecord::current(keep).write_to(dir.path()));
+        }
+        Ok(dir)
+    }
+
+    /// Returns whether directories can be created with `mkdtemp(3)`, which
+    /// picks the last six random characters itself and so cannot add a
+    /// suffix or use a custom name source.
+    #[cfg(all(unix, feature = "mkdtemp"))]
+    fn uses_mkdtemp(&self) -> bool {
+        let random = match self.names { Names::Random => true, _ => false };
+        random && !self.minimal_syscalls && self.rand_bytes >= 6 &&
+            self.suffix.as_os_str() == OsStr::from_str("")
+    }
+
+    #[cfg(not(all(unix, feature = "mkdtemp")))]
+    fn uses_mkdtemp(&self) -> bool {
+        false
+    }
+
+    #[cfg(all(unix, feature = "mkdtemp"))]
+    fn tempdir_mkdtemp(&self, tmpdir: &Path) -> io::Result<TempDir> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (base, prefix) = try!(self.base_and_prefix(tmpdir));
+        try!(self.check_free_space(&base));
+        try!(faults::check_create());
+        let _permit = throttle::acquire();
+
+        let rand: String = rand::thread_rng().gen_ascii_chars().take(self.rand_bytes - 6).collect();
+        let template = format!("{}XXXXXX", rand);
+        let leaf = try!(self.leaf(&base, &prefix, &template, namelen::name_max(&base)));
+        let path = try!(mkdtemp(&base.join(&leaf)));
+        // mkdtemp always uses 0700.
+        if self.mode != 0o700 {
+            try!(fs::set_permissions(&path, fs::Permissions::from_mode(self.mode)));
+        }
+        self.wrap(path)
+    }
+
+    #[cfg(not(all(unix, feature = "mkdtemp")))]
+    fn tempdir_mkdtemp(&self, _tmpdir: &Path) -> io::Result<TempDir> {
+        unreachable!()
+    }
+
+    /// Returns a path inside of `tmpdir`, named according to this builder,
+    /// which does not exist yet.
+    ///
+    /// Nothing is created, so another process may claim the name before it
+    /// is used; prefer `create_in` when the entry can be created here.
+    pub fn unique_path_in(&self, tmpdir: &Path) -> io::Result<PathBuf> {
+        self.create_in(tmpdir, |path| {
+            match fs::symlink_metadata(&path) {
+                Ok(_) => Err(Error::Taken(path).into_io_error()),
+                Err(_) => Ok(path),
+            }
+        })
+    }
+
+    /// Picks unused random names inside of `tmpdir`, named according to this
+    /// builder, and calls `create` with them until it succeeds or fails with
+    /// an error other than `PathAlreadyExists`. This is the collision
+    /// avoidance behind `tempdir_in`, for creating other kinds of entries
+    /// (sockets, FIFOs, names handed to external tools) exclusively.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs;
+    /// use std::path::Path;
+    /// use tempdir::Builder;
+    ///
+    /// // Create a uniquely named, empty lock directory.
+    /// let lock = Builder::new().prefix("lock").create_in(Path::new("/tmp"), |path| {
+    ///     fs::create_dir(&path).map(|_| path)
+    /// }).unwrap();
+    /// println!("created {}", lock.display());
+    /// ```
+    pub fn create_in<R, F>(&self, tmpdir: &Path, mut create: F) -> io::Result<R>
+        where F: FnMut(PathBuf) -> io::Result<R>
+    {
+        let (base, prefix) = try!(self.base_and_prefix(tmpdir));
+        let tmpdir = &*base;
+        // `pathconf` is not among the minimal system calls, so in that mode
+        // the name is only shortened once the file system has rejected it.
+        let mut limit = if self.minimal_syscalls { None } else { namelen::name_max(tmpdir) };
+        try!(self.check_free_space(tmpdir));
+
+        try!(faults::check_create());
+        let _permit = throttle::acquire();
+
+        let mut previous = None;
+        for attempt in 0..NUM_RETRIES {
+            let rand: String = match self.names {
+                Names::Custom(ref f) => {
+                    let name = f(attempt);
+                    if previous.as_ref() == Some(&name) {
+                        break;
+                    }
+                    previous = Some(name.clone());
+                    name
+                }
+                Names::Seeded(ref rng) => {
+                    rng.lock().unwrap().gen_ascii_chars().take(self.rand_bytes).collect()
+                }
+                Names::ZeroSeed => return Err(Error::ZeroSeed.into_io_error()),
+                Names::Random if self.minimal_syscalls => {
+                    try!(minimal::rand_chars(self.rand_bytes))
+                }
+                Names::Random => {
+                    rand::thread_rng().gen_ascii_chars().take(self.rand_bytes).collect()
+                }
+            };
+            let leaf = try!(self.leaf(tmpdir, &prefix, &rand, limit));
+            let path: PathBuf = tmpdir.join(&leaf);
+            match create(path) {
+                Ok(r) => return Ok(r),
+                Err(ref e) if self.minimal_syscalls && namelen::is_too_long(e) &&
+                              namelen::common_limit_below(leaf.len()).is_some() => {
+                    limit = namelen::common_limit_below(leaf.len());
+                    // The same custom name is to be tried again, shortened.
+                    previous = None;
+                }
+                // Without random characters every retry would pick the
+                // same name again.
+                Err(ref e) if e.kind() == io::ErrorKind::PathAlreadyExists &&
+                              (self.rand_bytes > 0 || previous.is_some()) => (),
+                Err(ref e) if e.kind() == io::ErrorKind::PathAlreadyExists => break,
+                Err(e) => return Err(e)
+            }
+        }
+
+        Err(Error::Exhausted(tmpdir.to_path_buf(), collisions(tmpdir, prefix.as_os_str()))
+                  .into_io_error())
+    }
+}
+
+impl Builder {
+
+    /// Fails if `base` has less free space than `require_free_space` asks
+    /// for.
+    fn check_free_space(&self, base: &Path) -> io::Result<()> {
+        let required = match self.free_space {
+            Some(required) => required,
+            None => return Ok(()),
+        };
+        let available = try!(space::available(base));
+        if available < required {
+            return Err(Error::NoSpace(base.to_path_buf(), required, available).into_io_error());
+        }
+        Ok(())
+    }
+
+    /// Returns the absolute directory new entries are created in for
+    /// `tmpdir`, which is the per-user directory below it with `per_user`,
+    /// and the prefix including any thread-wide prefix from `Config`.
+    fn base_and_prefix(&self, tmpdir: &Path) -> io::Result<(PathBuf, OsString)> {
+        let mut base = tmpdir.to_path_buf();
+        if base.is_relative() {
+            base = try!(env::current_dir()).join(&base);
+        }
+        if self.long_paths {
+            base = longpath::extended(&base);
+        }
+        if self.per_user {
+            base = try!(user::user_dir(&base));
+        }
+
+        let prefix = match config::prefix() {
+            Some(ref outer) if self.prefix.as_os_str() != OsStr::from_str("") => {
+                let mut s = outer.clone();
+                s.push_os_str(OsStr::from_str("."));
+                s.push_os_str(self.prefix.as_os_str());
+                s
+            }
+            Some(outer) => outer,
+            None => self.prefix.clone(),
+        };
+        Ok((base, prefix))
+    }
+
+    /// Builds the name of an entry in `dir` from `prefix`, the separator,
+    /// the random part `rand` and the suffix. If the name would be longer
+    /// than `limit` bytes, the prefix is shortened to fit.
+    fn leaf(&self, dir: &Path, prefix: &OsStr, rand: &str, limit: Option<usize>)
+            -> io::Result<OsString> {
+        let prefix = self.restrict(prefix);
+        // If we're given an empty string for a prefix, then creating a
+        // directory starting with "." would lead to it being
+        // semi-invisible on some systems, so the separator is omitted.
+        let separator = if prefix.as_os_str() == OsStr::from_str("") {
+            OsString::new()
+        } else {
+            self.restrict(self.separator.as_os_str())
+        };
+        let rand = if self.owner_names {
+            let created = SystemTime::now().duration_since(UNIX_EPOCH)
+                                           .map(|d| d.as_secs())
+                                           .unwrap_or(0);
+            OsString::from_string(format!("{}.{}.{}", process::id(), created, rand))
+        } else {
+            OsString::from_str(rand)
+        };
+        let rand = self.restrict(rand.as_os_str());
+        let suffix = self.restrict(self.suffix.as_os_str());
+        let prefix = match limit {
+            Some(limit) => {
+                let rest = separator.len() + rand.len() + suffix.len();
+                try!(namelen::fit(dir, prefix.as_os_str(), rest, limit))
+            }
+            None => prefix,
+        };
+
+        let mut leaf = prefix;
+        leaf.push_os_str(separator.as_os_str());
+        leaf.push_os_str(rand.as_os_str());
+        leaf.push_os_str(suffix.as_os_str());
+        Ok(leaf)
+    }
+
+    /// Applies `ascii_names` to part of a generated name.
+    fn restrict(&self, part: &OsStr) -> OsString {
+        if !self.ascii_names {
+            return part.to_os_string();
+        }
+        let ascii: String = part.to_string_lossy().chars()
+                                .map(|c| if c.is_ascii() { c } else { '_' })
+                                .collect();
+        OsString::from_str(&ascii)
+    }
+}
+
+/// Creates a template-named directory with `mkdtemp(3)`, returning its path.
+#[cfg(all(unix, feature = "mkdtemp"))]
+fn mkdtemp(template: &Path) -> io::Result<PathBuf> {
+    use libc;
+    use std::os::unix::OsStrExt;
+
+    let mut bytes = template.as_os_str().as_bytes().to_vec();
+    if bytes.contains(&0) {
+        return Err(Error::nul_byte(template));
+    }
+    bytes.push(0);
+    if unsafe { libc::mkdtemp(bytes.as_mut_ptr() as *mut libc::c_char) }.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    bytes.pop();
+    Ok(PathBuf::new(<OsStr as OsStrExt>::from_bytes(&bytes)))
+}
+
+/// Creates a directory with permissions `mode`. It is never more permissive
+/// than `mode` while being created, and is set to exactly `mode` afterwards.
+#[cfg(unix)]
+pub fn create_dir(path: &Path, mode: u32) -> io::Result<()> {
+    use libc;
+    use std::ffi::CString;
+    use std::os::unix::OsStrExt;
+    use std::os::unix::fs::PermissionsExt;
+
+    let c_path = try!(CString::new(path.as_os_str().as_bytes()).map_err(|_| {
+        Error::nul_byte(path)
+    }));
+    if unsafe { libc::mkdir(c_path.as_ptr(), mode as libc::mode_t) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+pub fn create_dir(path: &Path, _mode: u32) -> io::Result<()> {
+    fs::create_dir(path)
+}
+
+/// How many colliding names to list when creation gives up.
+const NUM_SAMPLE_NAMES: usize = 5;
+
+/// Describes the entries of `tmpdir` whose names start with `prefix`, so that
+/// e.g. a runaway job filling the directory with the same prefix is easy to
+/// spot when creation runs out of retries.
+fn collisions(tmpdir: &Path, prefix: &OsStr) -> String {
+    let prefix = prefix.to_string_lossy().into_owned();
+    let mut count = 0;
+    let mut sample = Vec::new();
+    if let Ok(entries) = fs::read_dir(tmpdir) {
+        for entry in entries {
+            let name = match entry {
+                Ok(entry) => match entry.path().file_name() {
+                    Some(name) => name.to_string_lossy().into_owned(),
+                    None => continue,
+                },
+                Err(_) => continue,
+            };
+            if name.starts_with(&prefix) {
+                count += 1;
+                if sample.len() < NUM_SAMPLE_NAMES {
+                    sample.push(name);
+                }
+            }
+        }
+    }
+    sample.sort();
+    format!("{} entries in {} start with {:?}, e.g. {}",
+            count, tmpdir.display(), prefix, sample.connect(", "))
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs::{self, PathExt};
+    use std::io;
+
+    use super::super::*;
+    use testing::lock_globals;
+
+    #[test]
+    fn test_builder_disable_cleanup() {
+        let temp_dir = Builder::new().prefix("test_builder_disable_cleanup")
+                                     .disable_cleanup(true)
+                                     .tempdir()
+                                     .unwrap();
+        let path = temp_dir.path().to_path_buf();
+        drop(temp_dir);
+        assert!(path.exists());
+        fs::remove_dir(&path).unwrap();
+    }
+
+    #[test]
+    fn test_builder_disable_cleanup_false() {
+        let mut builder = Builder::new();
+        builder.retention(RetentionPolicy::OnFailure).disable_cleanup(false);
+        assert_eq!(builder.retention, RetentionPolicy::OnFailure);
+        builder.disable_cleanup(true).disable_cleanup(false);
+        assert_eq!(builder.retention, RetentionPolicy::Never);
+    }
+
+    #[test]
+    fn test_builder_keep_on_panic_false() {
+        let mut builder = Builder::new();
+        builder.disable_cleanup(true).keep_on_panic(false);
+        assert_eq!(builder.retention, RetentionPolicy::Always);
+        builder.keep_on_panic(true).keep_on_panic(false);
+        assert_eq!(builder.retention, RetentionPolicy::Never);
+    }
+
+    #[test]
+    fn test_builder_naming() {
+        let temp_dir = Builder::new().prefix("test_builder_naming")
+                                     .separator("-")
+                                     .suffix(".work")
+                                     .rand_bytes(16)
+                                     .tempdir()
+                                     .unwrap();
+        let name = temp_dir.path().file_name().unwrap().to_str().unwrap().to_string();
+        assert!(name.starts_with("test_builder_naming-"));
+        assert!(name.ends_with(".work"));
+        assert_eq!(name.len(), "test_builder_naming-".len() + 16 + ".work".len());
+    }
+
+    #[test]
+    fn test_builder_exhausted() {
+        let base = TempDir::new("test_builder_exhausted").unwrap();
+        fs::create_dir(&base.path().join("taken.x")).unwrap();
+        let err = Builder::new().prefix("taken")
+                                .separator(".")
+                                .rand_bytes(0)
+                                .suffix("x")
+                                .tempdir_in(base.path())
+                                .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PathAlreadyExists);
+        assert!(err.to_string().contains("1 entries"));
+        assert!(err.to_string().contains("taken.x"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_builder_mode() {
+        use std::os::unix::fs::MetadataExt;
+
+        let private = TempDir::new("test_builder_mode").unwrap();
+        let metadata = fs::metadata(private.path()).unwrap();
+        assert_eq!(metadata.mode() & 0o777, 0o700);
+
+        let shared = Builder::new().prefix("test_builder_mode").mode(0o755).tempdir().unwrap();
+        let metadata = fs::metadata(shared.path()).unwrap();
+        assert_eq!(metadata.mode() & 0o777, 0o755);
+    }
+
+    #[test]
+    fn test_builder_prewarm() {
+        let _globals = lock_globals();
+        let base = prewarm().unwrap();
+        let temp_dir = TempDir::new("test_builder_prewarm").unwrap();
+        assert_eq!(temp_dir.path().parent(), Some(&*base));
+    }
+
+    #[test]
+    fn test_builder_unique_path_in() {
+        let base = TempDir::new("test_builder_unique_path_in").unwrap();
+        let path = unique_path_in(base.path(), "sock", ".sock").unwrap();
+        assert_eq!(path.parent(), Some(base.path()));
+        assert!(!path.exists());
+        let name = path.file_name().unwrap().to_str().unwrap().to_string();
+        assert!(name.starts_with("sock.") && name.ends_with(".sock"));
+    }
+
+    #[test]
+    fn test_builder_seed() {
+        let base = TempDir::new("test_builder_seed").unwrap();
+        let a = Builder::new().seed([1, 2, 3, 4]).tempdir_in(base.path()).unwrap();
+        let b = Builder::new().seed([1, 2, 3, 4]).tempdir_in(base.path()).unwrap();
+        // `b` got the second name of the sequence, since the first was taken.
+        assert!(a.path() != b.path());
+
+        let name = a.path().file_name().unwrap().to_str().unwrap().to_string();
+        drop(a);
+        let c = Builder::new().seed([1, 2, 3, 4]).tempdir_in(base.path()).unwrap();
+        assert_eq!(c.path().file_name().unwrap().to_str(), Some(&name[..]));
+    }
+
+    #[test]
+    fn test_builder_zero_seed() {
+        let base = TempDir::new("test_builder_zero_seed").unwrap();
+        match Builder::new().seed([0; 4]).tempdir_in(base.path()) {
+            Err(Error::ZeroSeed) => {}
+            _ => panic!("expected ZeroSeed"),
+        }
+        let err = Builder::new().seed([0; 4]).prewarm().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(fs::read_dir(base.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_builder_name_fn() {
+        let base = TempDir::new("test_builder_name_fn").unwrap();
+        let mut builder = Builder::new();
+        builder.name_fn(|n| format!("fixture-{}", n));
+        let a = builder.tempdir_in(base.path()).unwrap();
+        let b = builder.tempdir_in(base.path()).unwrap();
+        assert_eq!(a.path(), &*base.path().join("fixture-0"));
+        assert_eq!(b.path(), &*base.path().join("fixture-1"));
+
+        builder.name_fn(|_| "fixed".to_string());
+        let _fixed = builder.tempdir_in(base.path()).unwrap();
+        assert!(builder.tempdir_in(base.path()).is_err());
+    }
+
+    #[cfg(all(unix, feature = "mkdtemp"))]
+    #[test]
+    fn test_builder_mkdtemp() {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp_dir = Builder::new().prefix("test_builder_mkdtemp").mode(0o750).tempdir().unwrap();
+        let name = temp_dir.path().file_name().unwrap().to_str().unwrap().to_string();
+        assert!(name.starts_with("test_builder_mkdtemp."));
+        assert_eq!(name.len(), "test_builder_mkdtemp.".len() + 12);
+        let metadata = fs::metadata(temp_dir.path()).unwrap();
+        assert_eq!(metadata.mode() & 0o777, 0o750);
+    }
+
+    #[test]
+    fn test_builder_per_user() {
+        let base = TempDir::new("test_builder_per_user").unwrap();
+        let temp_dir = Builder::new().per_user(true).tempdir_in(base.path()).unwrap();
+        let user_dir = temp_dir.path().parent().unwrap();
+        assert_eq!(user_dir.parent(), Some(base.path()));
+        assert!(user_dir.file_name().unwrap().to_str().unwrap().starts_with("user-"));
+    }
+
+    #[test]
+    fn test_builder_ascii_names() {
+        let base = TempDir::new("test_builder_ascii_names").unwrap();
+        let temp_dir = Builder::new().prefix("caf\u{e9}").ascii_names(true)
+                                     .tempdir_in(base.path())
+                                     .unwrap();
+        let name = temp_dir.path().file_name().unwrap().to_str().unwrap().to_string();
+        assert!(name.starts_with("caf_."));
+    }
+
+    #[test]
+    fn test_builder_canonicalize() {
+        let temp_dir = Builder::new().prefix("test_builder_canonicalize")
+                                     .canonicalize(true)
+                                     .tempdir()
+                                     .unwrap();
+        assert_eq!(fs::canonicalize(temp_dir.path()).unwrap(), temp_dir.path());
+        assert_eq!(temp_dir.canonicalize().unwrap(), temp_dir.path());
+    }
+
+    #[test]
+    fn test_builder_owner_names() {
+        use std::process;
+
+        let temp_dir = Builder::new().prefix("test_builder_owner_names")
+                                     .owner_names(true)
+                                     .tempdir()
+                                     .unwrap();
+        let name = temp_dir.path().file_name().unwrap().to_str().unwrap().to_string();
+        let parts: Vec<&str> = name.split('.').collect();
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[0], "test_builder_owner_names");
+        assert_eq!(parts[1], &process::id().to_string()[..]);
+        assert!(parts[2].parse::<u64>().unwrap() > 0);
+        assert_eq!(parts[3].len(), 12);
+    }
+}
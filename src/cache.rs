@@ -0,0 +1,223 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fs::{self, File, PathExt};
+use std::hash::{Hasher, SipHasher};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use super::{Error, TempDir};
+use walk::{relative, walk};
+
+/// The name of the manifest inside each cached tree.
+const MANIFEST_FILE: &'static str = ".tempdir-manifest";
+
+/// A persistent cache of fixture trees, reused across test runs.
+///
+/// Each fixture is identified by a description of its content, typically the
+/// inputs and version of the code building it, and is materialized once
+/// under the cache root in a directory named after the hash of that
+/// description. A manifest of every file and its checksum is recorded inside
+/// it and checked before each reuse, so an entry that was accidentally
+/// corrupted, e.g. by an interrupted write, is rebuilt rather than silently
+/// used. The checksums are not cryptographic and do not detect deliberate
+/// tampering.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use std::path::Path;
+/// use tempdir::FixtureCache;
+///
+/// let cache = FixtureCache::new(Path::new("target/fixture-cache"));
+/// let view = cache.view(b"corpus v3", |dir| {
+///     File::create(&dir.join("big.dat")).map(|_| ())
+/// }).unwrap();
+/// // `view` is a TempDir of hard links into the cache.
+/// ```
+pub struct FixtureCache {
+    root: PathBuf,
+}
+
+impl FixtureCache {
+
+    /// Creates a cache rooted at `root`, which is created on first use.
+    pub fn new(root: &Path) -> FixtureCache {
+        FixtureCache { root: root.to_path_buf() }
+    }
+
+    /// The root directory of the cache.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the cached tree for `content`, building it with `build` if it
+    /// is missing or fails verification.
+    ///
+    /// `build` populates a fresh directory, which is only moved into place
+    /// once it succeeds. Cached files are made read-only.
+    pub fn materialize<F>(&self, content: &[u8], build: F) -> io::Result<PathBuf>
+        where F: FnOnce(&Path) -> io::Result<()>
+    {
+        let key = format!("{:016x}", hash(content));
+        let dir = self.root.join(&key);
+
+        if dir.exists() {
+            if let Ok(true) = verify(&dir) {
+                return Ok(dir);
+            }
+            try!(remove_cached(&dir));
+        }
+
+        try!(fs::create_dir_all(&self.root));
+        let staging = try!(TempDir::new_in(&self.root, &format!("{}.building", key)));
+        try!(build(staging.path()));
+        // The manifest travels with the tree, so whichever process wins the
+        // rename below also wins with its manifest.
+        let listing = try!(list(staging.path()));
+        let manifest = staging.path().join(MANIFEST_FILE);
+        try!(try!(File::create(&manifest)).write_all(listing.as_bytes()));
+        try!(make_readonly(staging.path()));
+        match fs::rename(staging.path(), &dir) {
+            Ok(()) => { staging.into_inner(); }
+            // Another process won the race; use its copy if it verifies.
+            Err(_) if dir.exists() => {
+                let _ = remove_cached(staging.path());
+                staging.into_inner();
+                if !try!(verify(&dir)) {
+                    return Err(Error::Corrupted(dir).into_io_error());
+                }
+            }
+            Err(e) => return Err(e),
+        }
+        Ok(dir)
+    }
+
+    /// Returns a fresh `TempDir` whose contents mirror the cached tree for
+    /// `content`, building it first if necessary.
+    ///
+    /// Files in the view are hard links to the read-only cached files (or
+    /// copies, where hard links are not possible), which makes views cheap to
+    /// create. Tests should replace rather than modify files in a view.
+    pub fn view<F>(&self, content: &[u8], build: F) -> io::Result<TempDir>
+        where F: FnOnce(&Path) -> io::Result<()>
+    {
+        let source = try!(self.materialize(content, build));
+        let view = try!(TempDir::new("fixture-view"));
+        for entry in try!(walk(&source)).iter() {
+            let rel = relative(&source, &entry.path);
+            if rel == Path::new(MANIFEST_FILE) {
+                continue;
+            }
+            let dest = view.path().join(rel);
+            if entry.metadata.is_dir() {
+                try!(fs::create_dir(&dest));
+            } else if fs::hard_link(&entry.path, &dest).is_err() {
+                try!(fs::copy(&entry.path, &dest));
+            }
+        }
+        Ok(view)
+    }
+}
+
+/// A fast checksum, good enough to notice accidental corruption.
+fn hash(bytes: &[u8]) -> u64 {
+    let mut hasher = SipHasher::new_with_keys(0, 0);
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+/// Lists every entry below `dir` but the manifest, one per line, with a
+/// checksum for files.
+fn list(dir: &Path) -> io::Result<String> {
+    let mut listing = String::new();
+    for entry in try!(walk(dir)).iter() {
+        let rel = relative(dir, &entry.path);
+        if rel == Path::new(MANIFEST_FILE) {
+            continue;
+        }
+        if entry.metadata.is_dir() {
+            listing.push_str(&format!("d {}\n", rel.display()));
+        } else {
+            let mut contents = Vec::new();
+            try!(try!(File::open(&entry.path)).read_to_end(&mut contents));
+            listing.push_str(&format!("f {:016x} {}\n", hash(&contents), rel.display()));
+        }
+    }
+    Ok(listing)
+}
+
+fn verify(dir: &Path) -> io::Result<bool> {
+    let mut expected = String::new();
+    try!(try!(File::open(&dir.join(MANIFEST_FILE))).read_to_string(&mut expected));
+    Ok(try!(list(dir)) == expected)
+}
+
+fn make_readonly(dir: &Path) -> io::Result<()> {
+    for entry in try!(walk(dir)).iter() {
+        if entry.metadata.is_file() {
+            let mut perms = entry.metadata.permissions();
+            perms.set_readonly(true);
+            try!(fs::set_permissions(&entry.path, perms));
+        }
+    }
+    Ok(())
+}
+
+/// Removes a cached tree, whose files are read-only.
+fn remove_cached(dir: &Path) -> io::Result<()> {
+    for entry in try!(walk(dir)).iter() {
+        if entry.metadata.is_file() {
+            let mut perms = entry.metadata.permissions();
+            perms.set_readonly(false);
+            let _ = fs::set_permissions(&entry.path, perms);
+        }
+    }
+    fs::remove_dir_all(dir)
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::cell::Cell;
+    use std::fs::{self, File, PathExt};
+    use std::io::Write;
+
+    use super::super::*;
+
+    #[test]
+    fn test_fixture_cache_reuse() {
+        let root = TempDir::new("test_fixture_cache_reuse").unwrap();
+        let cache = FixtureCache::new(root.path());
+        let builds = Cell::new(0);
+        let build = |dir: &::std::path::Path| {
+            builds.set(builds.get() + 1);
+            File::create(&dir.join("data")).map(|_| ())
+        };
+
+        let first = cache.materialize(b"fixture", &build).unwrap();
+        let second = cache.materialize(b"fixture", &build).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(builds.get(), 1);
+
+        let view = cache.view(b"fixture", &build).unwrap();
+        assert!(view.path().join("data").exists());
+        assert!(!view.path().join(super::MANIFEST_FILE).exists());
+        assert_eq!(builds.get(), 1);
+
+        // A corrupted entry is rebuilt.
+        let data = first.join("data");
+        let mut perms = fs::metadata(&data).unwrap().permissions();
+        perms.set_readonly(false);
+        fs::set_permissions(&data, perms).unwrap();
+        write!(File::create(&data).unwrap(), "garbage").unwrap();
+        cache.materialize(b"fixture", &build).unwrap();
+        assert_eq!(builds.get(), 2);
+    }
+}
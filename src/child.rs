@@ -0,0 +1,90 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::ffi::AsOsStr;
+use std::io;
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+use super::{Builder, Error, TempDir};
+
+/// A uniquely named subdirectory of a `TempDir`, created by
+/// `TempDir::subdir`, which is removed when dropped and cannot outlive its
+/// parent.
+///
+/// It dereferences to a `TempDir`, so everything available on a `TempDir`
+/// (including `subdir`, for deeper trees) is available on it too.
+///
+/// # Examples
+///
+/// ```no_run
+/// use tempdir::TempDir;
+///
+/// let root = TempDir::new("fixture").unwrap();
+/// let src = root.subdir("src").unwrap();
+/// let nested = src.subdir("module").unwrap();
+/// println!("created {}", nested.path().display());
+/// ```
+pub struct ScopedDir<'a> {
+    dir: TempDir,
+    marker: PhantomData<&'a TempDir>,
+}
+
+impl<'a> ScopedDir<'a> {
+
+    /// Close and remove the subdirectory, reporting any error.
+    pub fn close(self) -> Result<(), Error> {
+        self.dir.close()
+    }
+}
+
+impl<'a> Deref for ScopedDir<'a> {
+    type Target = TempDir;
+
+    fn deref(&self) -> &TempDir {
+        &self.dir
+    }
+}
+
+impl TempDir {
+
+    /// Creates a uniquely named subdirectory whose name will have the prefix
+    /// `prefix`. It inherits this directory's retention policy and cleanup
+    /// strategy, and is removed when the returned wrapper is destroyed.
+    pub fn subdir<P: ?Sized>(&self, prefix: &P) -> io::Result<ScopedDir>
+        where P: AsOsStr
+    {
+        let dir = try!(Builder::new().prefix(prefix)
+                                     .retention(self.retention)
+                                     .cleanup(self.cleanup)
+                                     .tempdir_in(self.path()));
+        Ok(ScopedDir { dir: dir, marker: PhantomData })
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs::PathExt;
+
+    use super::super::*;
+
+    #[test]
+    fn test_subdir() {
+        let root = TempDir::new("test_subdir").unwrap();
+        let child = root.subdir("child").unwrap();
+        let grandchild = child.subdir("grandchild").unwrap();
+        assert_eq!(child.path().parent(), Some(root.path()));
+        assert_eq!(grandchild.path().parent(), Some(child.path()));
+
+        let path = grandchild.path().to_path_buf();
+        drop(grandchild);
+        assert!(!path.exists());
+        assert!(child.path().exists());
+    }
+}
@@ -0,0 +1,280 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use background;
+use journal::{Action, Recorder};
+use minimal;
+use parallel;
+use walk;
+
+/// How a `TempDir` removes its directory when it is dropped or closed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cleanup {
+    /// Remove the directory and everything inside of it. This is the default.
+    ///
+    /// If a filter is registered with `TempDir::set_cleanup_filter`, entries
+    /// are removed one at a time like `Sorted`, so that the filter can be
+    /// consulted for each of them.
+    Recursive,
+    /// Only remove the directory if it is empty. Leftover entries cause
+    /// `close` to fail, and are reported on standard error when dropped, so
+    /// the guard catches unexpected files rather than silently destroying
+    /// them.
+    NonRecursive,
+    /// Remove the directory and everything inside of it using only the system
+    /// calls listed in `minimal::SYSCALLS`. Set by
+    /// `Builder::minimal_syscalls`.
+    MinimalSyscalls,
+    /// Remove the directory and everything inside of it one entry at a time,
+    /// in a deterministic order: the entries of each directory are removed
+    /// sorted by name, and a directory's contents before the directory
+    /// itself. Each removed path is passed to the callback registered with
+    /// `TempDir::on_remove`.
+    Sorted,
+    /// Rename the directory out of the way to a `.deleting-` name next to
+    /// it, which is instant, and remove it recursively on a process-wide
+    /// cleanup thread, so that dropping a huge tree does not block. Call
+    /// `flush_cleanup` to wait for the removals; pending ones are also
+    /// finished when the process exits normally.
+    ///
+    /// Errors during the removal itself are reported on standard error and
+    /// cannot be observed through `close`. If a cleanup filter is registered,
+    /// the directory is removed synchronously like `Sorted`.
+    Background,
+    /// Remove the directory and everything inside of it, unlinking files
+    /// from the given number of threads at once; see
+    /// `remove_dir_all_parallel`. For trees of many thousands of small
+    /// files. If a cleanup filter is registered, entries are removed one at
+    /// a time like `Sorted`.
+    Parallel(usize),
+}
+
+/// What a cleanup filter decides to do with an entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Disposition {
+    /// Leave the entry (and, for a directory, everything in it) in place.
+    Keep,
+    /// Remove the entry.
+    Delete,
+}
+
+type ErrorHandler = Arc<Fn(&Path, io::Error) + Send + Sync>;
+
+/// A cleanup filter, shared with the exit cleanup registry.
+pub type Filter = Arc<Fn(&Path) -> Disposition + Send + Sync>;
+
+lazy_static! {
+    static ref ERROR_HANDLER: Mutex<Option<ErrorHandler>> = Mutex::new(None);
+}
+
+/// Installs a callback invoked, for the whole process, with the path and
+/// the error whenever a `TempDir` fails to remove its directory on drop, so
+/// that such failures can be logged or counted instead of going unnoticed.
+/// Replaces any callback installed before.
+///
+/// A handler installed on the `TempDir` itself with
+/// `TempDir::set_cleanup_error_handler` takes precedence. Errors returned
+/// by `close` are not passed to either.
+///
+/// # Examples
+///
+/// ```no_run
+/// use tempdir::set_global_cleanup_error_handler;
+///
+/// set_global_cleanup_error_handler(|path, err| {
+///     println!("leaked {}: {}", path.display(), err);
+/// });
+/// ```
+pub fn set_global_cleanup_error_handler<F>(f: F)
+    where F: Fn(&Path, io::Error) + Send + Sync + 'static
+{
+    *ERROR_HANDLER.lock().unwrap() = Some(Arc::new(f));
+}
+
+/// Callbacks consulted while a directory is removed entry by entry.
+pub struct Hooks {
+    pub on_remove: Option<Box<Fn(&Path) + Send + Sync>>,
+    pub filter: Option<Filter>,
+    pub on_error: Option<Box<Fn(&Path, io::Error) + Send + Sync>>,
+    pub journal: Option<Recorder>,
+}
+
+impl Hooks {
+    pub fn new() -> Hooks {
+        Hooks { on_remove: None, filter: None, on_error: None, journal: None }
+    }
+
+    /// Passes a failure to remove `path` on drop to the handler installed on
+    /// the directory or, failing that, the global one. Gives the error back
+    /// if there is neither.
+    pub fn failed(&self, path: &Path, err: io::Error) -> Option<io::Error> {
+        if let Some(ref f) = self.on_error {
+            f(path, err);
+            return None;
+        }
+        // Not called with the lock held, so that the handler may replace
+        // itself.
+        let global = ERROR_HANDLER.lock().unwrap().clone();
+        match global {
+            Some(f) => {
+                f(path, err);
+                None
+            }
+            None => Some(err),
+        }
+    }
+
+    pub fn keep(&self, rel: &Path) -> bool {
+        match self.filter {
+            Some(ref f) => f(rel) == Disposition::Keep,
+            None => false,
+        }
+    }
+
+    pub fn removed(&self, path: &Path) {
+        if let Some(ref recorder) = self.journal {
+            let _ = recorder.record(Action::Remove, path, Ok::<(), io::Error>(()));
+        }
+        if let Some(ref f) = self.on_remove {
+            f(path);
+        }
+    }
+}
+
+impl Default for Cleanup {
+    fn default() -> Cleanup {
+        Cleanup::Recursive
+    }
+}
+
+impl Cleanup {
+
+    /// Removes `path` using this strategy.
+    pub fn remove(&self, path: &Path) -> io::Result<()> {
+        self.remove_with(path, &Hooks::new())
+    }
+
+    /// Removes `path` using this strategy, consulting `hooks`.
+    pub fn remove_with(&self, path: &Path, hooks: &Hooks) -> io::Result<()> {
+        match *self {
+            Cleanup::Recursive if hooks.filter.is_none() => fs::remove_dir_all(path),
+            Cleanup::Background if hooks.filter.is_none() => background::remove(path, None),
+            Cleanup::Parallel(n) if hooks.filter.is_none() => {
+                parallel::remove_dir_all_parallel(path, n)
+            }
+            Cleanup::Recursive | Cleanup::Sorted | Cleanup::Background | Cleanup::Parallel(_) => {
+                remove_sorted(path, path, hooks).map(|_| ())
+            }
+            Cleanup::NonRecursive => fs::remove_dir(path),
+            Cleanup::MinimalSyscalls => minimal::remove_dir_all(path),
+        }
+    }
+
+    /// Removes everything inside of `path` using this strategy, consulting
+    /// `hooks`, but keeps `path` itself. With `NonRecursive` only files and
+    /// empty directories are removed.
+    pub fn clear_with(&self, path: &Path, hooks: &Hooks) -> io::Result<()> {
+        match *self {
+            Cleanup::Recursive | Cleanup::Background if hooks.filter.is_none() => {
+                clear(path, fs::remove_dir_all)
+            }
+            Cleanup::Parallel(n) if hooks.filter.is_none() => parallel::clear_parallel(path, n),
+            Cleanup::Recursive | Cleanup::Sorted | Cleanup::Background | Cleanup::Parallel(_) => {
+                clear_sorted(path, path, hooks).map(|_| ())
+            }
+            Cleanup::NonRecursive => clear(path, fs::remove_dir),
+            Cleanup::MinimalSyscalls => clear(path, minimal::remove_dir_all),
+        }
+    }
+}
+
+/// Removes every entry of `dir`, using `remove_dir` for directories.
+fn clear<F>(dir: &Path, remove_dir: F) -> io::Result<()>
+    where F: Fn(&Path) -> io::Result<()>
+{
+    for child in try!(fs::read_dir(dir)) {
+        let child = try!(child).path();
+        if try!(fs::symlink_metadata(&child)).is_dir() {
+            try!(remove_dir(&child));
+        } else {
+            try!(fs::remove_file(&child));
+        }
+    }
+    Ok(())
+}
+
+/// Removes `dir`, which is below `root`, one entry at a time in sorted
+/// order. Returns whether `dir` itself was removed, which is not the case if
+/// the filter kept anything inside of it.
+fn remove_sorted(root: &Path, dir: &Path, hooks: &Hooks) -> io::Result<bool> {
+    if !try!(clear_sorted(root, dir, hooks)) {
+        return Ok(false);
+    }
+    try!(fs::remove_dir(dir));
+    hooks.removed(dir);
+    Ok(true)
+}
+
+/// Removes the contents of `dir` like `remove_sorted`, but not `dir` itself.
+/// Returns whether `dir` is now empty.
+fn clear_sorted(root: &Path, dir: &Path, hooks: &Hooks) -> io::Result<bool> {
+    let mut children = Vec::new();
+    for child in try!(fs::read_dir(dir)) {
+        children.push(try!(child).path());
+    }
+    children.sort();
+    let mut kept = false;
+    for child in children.iter() {
+        if hooks.keep(walk::relative(root, child)) {
+            kept = true;
+        } else if try!(fs::symlink_metadata(child)).is_dir() {
+            kept |= !try!(remove_sorted(root, child, hooks));
+        } else {
+            try!(fs::remove_file(child));
+            hooks.removed(child);
+        }
+    }
+    Ok(!kept)
+}
+
+/// Clears the read-only attribute of `path` and everything below it (on Unix,
+/// grants the owner read and write access, plus search access to
+/// directories), so that a failed removal can be retried. Symbolic links are
+/// not followed.
+pub fn make_writable(path: &Path) -> io::Result<()> {
+    let metadata = try!(fs::symlink_metadata(path));
+    if !metadata.is_dir() && !metadata.is_file() {
+        return Ok(());
+    }
+    try!(fs::set_permissions(path, writable(metadata.permissions(), metadata.is_dir())));
+    if metadata.is_dir() {
+        for child in try!(fs::read_dir(path)) {
+            try!(make_writable(&try!(child).path()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn writable(permissions: fs::Permissions, is_dir: bool) -> fs::Permissions {
+    use std::os::unix::fs::PermissionsExt;
+
+    let owner = if is_dir { 0o700 } else { 0o600 };
+    fs::Permissions::from_mode(permissions.mode() | owner)
+}
+
+#[cfg(not(unix))]
+fn writable(mut permissions: fs::Permissions, _is_dir: bool) -> fs::Permissions {
+    permissions.set_readonly(false);
+    permissions
+}
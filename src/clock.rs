@@ -0,0 +1,71 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A source of the current time, consulted by everything in this crate that
+/// ages directories (retention policies and reaping), so that it can be
+/// replaced by a `MockClock` in tests or by a custom source in embedders.
+pub trait Clock: Send + Sync {
+
+    /// Returns the current time.
+    fn now(&self) -> SystemTime;
+
+    /// Returns how much time has passed since `then`, or zero if `then` is
+    /// in the future.
+    fn since(&self, then: SystemTime) -> Duration {
+        self.now().duration_since(then).unwrap_or(Duration::new(0, 0))
+    }
+}
+
+/// The system's wall clock. This is the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock which only moves when told to.
+#[derive(Debug)]
+pub struct MockClock {
+    now: Mutex<SystemTime>,
+}
+
+impl MockClock {
+
+    /// Creates a clock stopped at `now`.
+    pub fn new(now: SystemTime) -> MockClock {
+        MockClock { now: Mutex::new(now) }
+    }
+
+    /// Creates a clock stopped at the Unix epoch.
+    pub fn epoch() -> MockClock {
+        MockClock::new(UNIX_EPOCH)
+    }
+
+    /// Moves the clock forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now = *now + by;
+    }
+
+    /// Sets the clock to `now`.
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap() = now;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap()
+    }
+}
@@ -0,0 +1,138 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Scoped, per-thread overrides of the crate defaults.
+
+use std::cell::RefCell;
+use std::ffi::{AsOsStr, OsString};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use super::RetentionPolicy;
+
+/// A set of overrides for the crate defaults, installed for the current
+/// thread with `apply`.
+///
+/// Every `TempDir` created on the thread while the returned guard is alive
+/// picks the overrides up, including those created by library code that has
+/// no idea it is being configured:
+///
+/// * `root` replaces the directory returned by `temp_dir()`.
+/// * `prefix` is prepended to the prefix of every directory name.
+/// * `retention` becomes the initial retention policy.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+/// use tempdir::{Config, RetentionPolicy, TempDir};
+///
+/// let _guard = Config::new().root(Path::new("/scratch"))
+///                           .retention(RetentionPolicy::OnFailure)
+///                           .apply();
+/// // Created as /scratch/foo.XXXXXXXXXXXX, kept if the test fails.
+/// let dir = TempDir::new("foo").unwrap();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    root: Option<PathBuf>,
+    prefix: Option<OsString>,
+    retention: Option<RetentionPolicy>,
+}
+
+thread_local!(static CURRENT: RefCell<Config> = RefCell::new(Config::new()));
+
+/// Restores the previous defaults of the thread when dropped.
+pub struct ConfigGuard {
+    previous: Config,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Config {
+
+    /// Creates an empty set of overrides.
+    pub fn new() -> Config {
+        Config { root: None, prefix: None, retention: None }
+    }
+
+    /// Overrides the base directory.
+    pub fn root(&mut self, root: &Path) -> &mut Config {
+        self.root = Some(root.to_path_buf());
+        self
+    }
+
+    /// Overrides the prefix prepended to directory names.
+    pub fn prefix<P: ?Sized>(&mut self, prefix: &P) -> &mut Config
+        where P: AsOsStr
+    {
+        self.prefix = Some(prefix.as_os_str().to_os_string());
+        self
+    }
+
+    /// Overrides the initial retention policy.
+    pub fn retention(&mut self, policy: RetentionPolicy) -> &mut Config {
+        self.retention = Some(policy);
+        self
+    }
+
+    /// Installs the overrides on the current thread until the returned guard
+    /// is dropped. Settings left unset here keep the value of any enclosing
+    /// guard.
+    pub fn apply(&self) -> ConfigGuard {
+        let previous = CURRENT.with(|current| {
+            let mut current = current.borrow_mut();
+            let previous = current.clone();
+            if self.root.is_some() { current.root = self.root.clone(); }
+            if self.prefix.is_some() { current.prefix = self.prefix.clone(); }
+            if self.retention.is_some() { current.retention = self.retention; }
+            previous
+        });
+        ConfigGuard { previous: previous, _not_send: PhantomData }
+    }
+}
+
+impl Drop for ConfigGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.clone();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// The overridden base directory of the current thread, if any.
+pub fn root() -> Option<PathBuf> {
+    CURRENT.with(|current| current.borrow().root.clone())
+}
+
+/// The overridden prefix of the current thread, if any.
+pub fn prefix() -> Option<OsString> {
+    CURRENT.with(|current| current.borrow().prefix.clone())
+}
+
+/// The overridden retention policy of the current thread, if any.
+pub fn retention() -> Option<RetentionPolicy> {
+    CURRENT.with(|current| current.borrow().retention)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::super::*;
+
+    #[test]
+    fn test_config_guard() {
+        let base = TempDir::new("test_config_guard").unwrap();
+        {
+            let _guard = Config::new().root(base.path()).prefix("harness").apply();
+            let temp_dir = TempDir::new("inner").unwrap();
+            assert_eq!(temp_dir.path().parent(), Some(base.path()));
+            let name = temp_dir.path().file_name().unwrap().to_str().unwrap().to_string();
+            assert!(name.starts_with("harness.inner."));
+        }
+        assert!(temp_dir() != base.path().to_path_buf());
+    }
+}
@@ -0,0 +1,133 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fs;
+use std::io;
+use std::path::{AsPath, Path, PathBuf};
+
+use journal;
+use super::{Action, Error, TempDir};
+
+/// What `TempDir::copy_from_with` does with symbolic links in the source.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Symlinks {
+    /// Recreate the links themselves, with their targets unchanged.
+    Preserve,
+    /// Copy whatever the links point to. Links that form a cycle are an
+    /// error.
+    Follow,
+}
+
+impl TempDir {
+
+    /// Recursively copies the contents of the directory `source` into the
+    /// temporary directory, preserving permissions and symbolic links.
+    /// Existing files with the same names are overwritten.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use tempdir::TempDir;
+    ///
+    /// let temp_dir = TempDir::new("fixture").unwrap();
+    /// temp_dir.copy_from("tests/fixtures/project").unwrap();
+    /// ```
+    pub fn copy_from<P: ?Sized>(&self, source: &P) -> io::Result<()> where P: AsPath {
+        self.copy_from_with(source, Symlinks::Preserve)
+    }
+
+    /// Like `copy_from`, with `symlinks` deciding how symbolic links are
+    /// copied.
+    pub fn copy_from_with<P: ?Sized>(&self, source: &P, symlinks: Symlinks) -> io::Result<()>
+        where P: AsPath
+    {
+        let mut ancestors = Vec::new();
+        let result = copy_contents(source.as_path(), self.path(), symlinks, &mut ancestors);
+        journal::record(self, Action::Copy, self.path(), result)
+    }
+}
+
+/// Copies the contents of `src` into the existing directory `dst`.
+/// `ancestors` holds the canonical paths of the directories being copied,
+/// to detect cycles when following links.
+fn copy_contents(src: &Path, dst: &Path, symlinks: Symlinks, ancestors: &mut Vec<PathBuf>)
+                 -> io::Result<()> {
+    if symlinks == Symlinks::Follow {
+        let canonical = try!(fs::canonicalize(src));
+        if ancestors.contains(&canonical) {
+            return Err(Error::SymlinkCycle(src.to_path_buf()).into_io_error());
+        }
+        ancestors.push(canonical);
+    }
+    for entry in try!(fs::read_dir(src)) {
+        let from = try!(entry).path();
+        let to = dst.join(from.file_name().unwrap());
+        let metadata = match symlinks {
+            Symlinks::Preserve => try!(fs::symlink_metadata(&from)),
+            Symlinks::Follow => try!(fs::metadata(&from)),
+        };
+        if metadata.file_type().is_symlink() {
+            let _ = fs::remove_file(&to);
+            try!(fs::soft_link(&try!(fs::read_link(&from)), &to));
+        } else if metadata.is_dir() {
+            if !fs::symlink_metadata(&to).map(|m| m.is_dir()).unwrap_or(false) {
+                try!(fs::create_dir(&to));
+            }
+            try!(copy_contents(&from, &to, symlinks, ancestors));
+            // Only now, so that read-only directories can be filled first.
+            try!(fs::set_permissions(&to, metadata.permissions()));
+        } else {
+            // This copies the permissions too.
+            try!(fs::copy(&from, &to));
+        }
+    }
+    if symlinks == Symlinks::Follow {
+        ancestors.pop();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs::{self, PathExt};
+    use std::path::Path;
+
+    use super::super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_from() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let source = TempDir::new("test_copy_from").unwrap();
+        let src = source.path();
+        fs::create_dir(&src.join("sub")).unwrap();
+        source.write("sub/file", b"contents").unwrap();
+        fs::set_permissions(&src.join("sub/file"), fs::Permissions::from_mode(0o640)).unwrap();
+        fs::soft_link("sub/file", &src.join("link")).unwrap();
+
+        let preserved = TempDir::new("test_copy_from").unwrap();
+        preserved.copy_from(src).unwrap();
+        let dst = preserved.path();
+        assert_eq!(fs::read_link(&dst.join("link")).unwrap(), Path::new("sub/file"));
+        let mode = fs::metadata(&dst.join("sub/file")).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+
+        let followed = TempDir::new("test_copy_from").unwrap();
+        followed.copy_from_with(src, Symlinks::Follow).unwrap();
+        let link = followed.path().join("link");
+        assert!(!fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert!(link.is_file());
+
+        // A link back up the tree would be copied forever.
+        fs::soft_link("..", &src.join("sub/up")).unwrap();
+        let looped = TempDir::new("test_copy_from").unwrap();
+        assert!(looped.copy_from_with(src, Symlinks::Follow).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Operations relative to an open directory handle, which keep working on
+//! the same directory even if its path is renamed or swapped for a symbolic
+//! link in the meantime.
+
+use libc;
+use std::ffi::{AsOsStr, CString};
+use std::fs::File;
+use std::io;
+use std::os::unix::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::path::{Component, Path, PathBuf};
+
+use identity;
+use journal::{self, Action, Recorder};
+use super::{Error, TempDir};
+
+/// An open handle to a directory, through which entries are created, opened
+/// and removed with `openat(2)` and friends.
+///
+/// Names passed to its methods must be a single path component; symbolic
+/// links are never followed.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::io::Write;
+/// use tempdir::TempDir;
+///
+/// let temp_dir = TempDir::new("private").unwrap();
+/// let handle = temp_dir.open_handle().unwrap();
+/// let sub = handle.create_subdir("data").unwrap();
+/// sub.create_file("secret").unwrap().write_all(b"hunter2").unwrap();
+/// ```
+pub struct DirHandle {
+    dir: File,
+    /// The path the directory was opened at, for the journal.
+    path: PathBuf,
+    journal: Option<Recorder>,
+}
+
+impl DirHandle {
+
+    /// Opens the directory at `path`, refusing a symbolic link.
+    pub fn open(path: &Path) -> io::Result<DirHandle> {
+        let c_path = try!(cstring(path));
+        let fd = unsafe {
+            libc::open(c_path.as_ptr(),
+                       libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC)
+        };
+        DirHandle::from_fd(fd, path.to_path_buf(), None)
+    }
+
+    fn from_fd(fd: RawFd, path: PathBuf, journal: Option<Recorder>) -> io::Result<DirHandle> {
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(DirHandle { dir: unsafe { File::from_raw_fd(fd) }, path: path, journal: journal })
+    }
+
+    /// Opens the existing file `name` for reading.
+    pub fn open_file(&self, name: &str) -> io::Result<File> {
+        self.openat(name, libc::O_RDONLY, 0)
+    }
+
+    /// Creates the file `name` for writing. It must not exist yet.
+    pub fn create_file(&self, name: &str) -> io::Result<File> {
+        self.openat(name, libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL, 0o600)
+    }
+
+    /// Creates the directory `name` and returns a handle to it.
+    pub fn create_subdir(&self, name: &str) -> io::Result<DirHandle> {
+        let c_name = try!(component(name));
+        if unsafe { libc::mkdirat(self.dir.as_raw_fd(), c_name.as_ptr(), 0o700) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        self.open_subdir(name)
+    }
+
+    /// Opens a handle to the existing directory `name`.
+    pub fn open_subdir(&self, name: &str) -> io::Result<DirHandle> {
+        let c_name = try!(component(name));
+        let fd = unsafe {
+            libc::openat(self.dir.as_raw_fd(), c_name.as_ptr(),
+                         libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC)
+        };
+        DirHandle::from_fd(fd, self.path.join(name), self.journal.clone())
+    }
+
+    /// Removes the file, symbolic link or empty directory `name`.
+    pub fn remove(&self, name: &str) -> io::Result<()> {
+        let result = self.remove_entry(name);
+        match self.journal {
+            Some(ref recorder) => recorder.record(Action::Remove, &self.path.join(name), result),
+            None => result,
+        }
+    }
+
+    fn remove_entry(&self, name: &str) -> io::Result<()> {
+        let c_name = try!(component(name));
+        let fd = self.dir.as_raw_fd();
+        if unsafe { libc::unlinkat(fd, c_name.as_ptr(), 0) } == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        // Linux reports EISDIR for directories, other systems EPERM.
+        match err.raw_os_error() {
+            Some(libc::EISDIR) | Some(libc::EPERM) => (),
+            _ => return Err(err),
+        }
+        if unsafe { libc::unlinkat(fd, c_name.as_ptr(), libc::AT_REMOVEDIR) } == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    fn openat(&self, name: &str, flags: libc::c_int, mode: libc::c_int) -> io::Result<File> {
+        let c_name = try!(component(name));
+        let fd = unsafe {
+            libc::openat(self.dir.as_raw_fd(), c_name.as_ptr(),
+                         flags | libc::O_NOFOLLOW | libc::O_CLOEXEC, mode)
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+}
+
+impl AsRawFd for DirHandle {
+    fn as_raw_fd(&self) -> RawFd {
+        self.dir.as_raw_fd()
+    }
+}
+
+fn cstring(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes()).map_err(|_| Error::nul_byte(path))
+}
+
+/// Checks that `name` is a single, normal path component.
+fn component(name: &str) -> io::Result<CString> {
+    let path = Path::new(name);
+    let mut components = path.components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => cstring(path),
+        _ => Err(Error::Escapes(path.to_path_buf()).into_io_error()),
+    }
+}
+
+impl TempDir {
+
+    /// Opens a handle to the temporary directory, for operating on its
+    /// contents without going through its path again.
+    ///
+    /// The opened directory is checked against the one that was created, so
+    /// a directory swapped in at the same path in the meantime is refused
+    /// with a `Swapped` error. Handles are only available on Unix.
+    pub fn open_handle(&self) -> io::Result<DirHandle> {
+        let mut handle = try!(DirHandle::open(self.path()));
+        handle.journal = journal::recorder(self);
+        if let Some(identity) = self.identity.as_ref() {
+            if let Some(anomaly) = try!(identity.check_open(&handle.dir)) {
+                return Err(identity::swapped(self.path(), &anomaly));
+            }
+        }
+        Ok(handle)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs;
+    use std::io::{Read, Write};
+
+    use super::super::*;
+
+    #[test]
+    fn test_dir_handle() {
+        let temp_dir = TempDir::new("test_dir_handle").unwrap();
+        let handle = temp_dir.open_handle().unwrap();
+        let sub = handle.create_subdir("sub").unwrap();
+        sub.create_file("a").unwrap().write_all(b"hello").unwrap();
+        assert!(sub.create_file("a").is_err());
+        assert!(handle.open_file("sub/a").is_err());
+
+        // The handle keeps referring to the directory after a rename.
+        fs::rename(&temp_dir.path().join("sub"), &temp_dir.path().join("moved")).unwrap();
+        let mut s = String::new();
+        sub.open_file("a").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "hello");
+
+        sub.remove("a").unwrap();
+        handle.remove("moved").unwrap();
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_open_handle_refuses_swapped_dir() {
+        let base = TempDir::new("test_open_handle_refuses_swapped_dir").unwrap();
+        let temp_dir = TempDir::new_in(base.path(), "swapped").unwrap();
+        let path = temp_dir.path().to_path_buf();
+        fs::rename(&path, &base.path().join("original")).unwrap();
+        fs::create_dir(&path).unwrap();
+        assert!(temp_dir.open_handle().is_err());
+
+        fs::remove_dir(&path).unwrap();
+        fs::rename(&base.path().join("original"), &path).unwrap();
+        assert!(temp_dir.open_handle().is_ok());
+    }
+}
@@ -0,0 +1,139 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Support for `doctest_dir!` and `tempdir_test!`.
+
+use registry;
+use super::{Builder, TempDir};
+
+/// Creates a temporary directory for a runnable example, which is removed
+/// when the example finishes, panics or calls `std::process::exit`.
+///
+/// The directory is named after the calling module and line, so leftovers
+/// from a killed run are easy to attribute. Creation failures panic, since
+/// an example has nothing better to do with them.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use] extern crate tempdir;
+///
+/// # fn main() {
+/// use std::fs::File;
+///
+/// let dir = doctest_dir!();
+/// File::create(&dir.path().join("config.toml")).unwrap();
+/// # }
+/// ```
+#[macro_export]
+macro_rules! doctest_dir {
+    () => ($crate::doctest::dir(module_path!(), line!()));
+    ($name:expr) => ($crate::doctest::dir($name, line!()));
+}
+
+/// Defines tests, written as `fn name(dir: &Path) { ... }`, which are each
+/// given the path of a fresh temporary directory named after the test.
+///
+/// The directory is removed when the test returns, and kept, with its path
+/// printed to stderr, if the test panics. The tests are marked `#[test]`
+/// already; other attributes, like `#[should_panic]`, go before `fn`. A
+/// test may return a value, e.g. a `Result`.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use] extern crate tempdir;
+///
+/// use std::fs::File;
+/// use std::path::Path;
+///
+/// tempdir_test! {
+///     fn writes_config(dir: &Path) {
+///         File::create(&dir.join("config.toml")).unwrap();
+///     }
+/// }
+/// # fn main() {}
+/// ```
+#[macro_export]
+macro_rules! tempdir_test {
+    () => ();
+    ($(#[$attr:meta])* fn $name:ident($dir:ident: &Path) -> $ret:ty $body:block
+     $($rest:tt)*) => (
+        #[test]
+        $(#[$attr])*
+        fn $name() -> $ret {
+            let __tempdir = $crate::doctest::test_dir(concat!(module_path!(), "::",
+                                                              stringify!($name)));
+            let $dir: &::std::path::Path = __tempdir.path();
+            $body
+        }
+        tempdir_test!($($rest)*);
+    );
+    ($(#[$attr:meta])* fn $name:ident($dir:ident: &Path) $body:block $($rest:tt)*) => (
+        #[test]
+        $(#[$attr])*
+        fn $name() {
+            let __tempdir = $crate::doctest::test_dir(concat!(module_path!(), "::",
+                                                              stringify!($name)));
+            let $dir: &::std::path::Path = __tempdir.path();
+            $body
+        }
+        tempdir_test!($($rest)*);
+    );
+}
+
+/// Creates the directory for `doctest_dir!` invoked in `module` at `line`.
+pub fn dir(module: &str, line: u32) -> TempDir {
+    registry::register_atexit_cleanup();
+    let prefix = format!("doctest-{}-{}", sanitize(module), line);
+    match TempDir::new(&prefix) {
+        Ok(dir) => dir,
+        Err(e) => panic!("doctest_dir!: could not create a temporary directory: {}", e),
+    }
+}
+
+/// Creates the directory for the `tempdir_test!` test `test`, given as its
+/// full path, which is kept if the test panics.
+pub fn test_dir(test: &str) -> TempDir {
+    match Builder::new().prefix(&sanitize(test)).keep_on_panic(true).tempdir() {
+        Ok(dir) => dir,
+        Err(e) => panic!("tempdir_test!: could not create a temporary directory: {}", e),
+    }
+}
+
+/// Replaces the characters of `name` which may not be safe in file names.
+fn sanitize(name: &str) -> String {
+    name.chars().map(|c| if c.is_alphanumeric() || c == '-' { c } else { '_' }).collect()
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs::PathExt;
+
+    use super::super::RetentionPolicy;
+
+    #[test]
+    fn test_doctest_dir() {
+        let path = {
+            let dir = doctest_dir!();
+            let name = dir.path().file_name().unwrap().to_str().unwrap().to_string();
+            assert!(name.starts_with("doctest-tempdir__doctest__test-"));
+            dir.path().to_path_buf()
+        };
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_test_dir() {
+        let dir = super::test_dir("tempdir::doctest::test::test_test_dir");
+        let name = dir.path().file_name().unwrap().to_str().unwrap().to_string();
+        assert!(name.starts_with("tempdir__doctest__test__test_test_dir"));
+        assert_eq!(dir.retention(), RetentionPolicy::OnFailure);
+    }
+}
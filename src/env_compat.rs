@@ -0,0 +1,112 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A drop-in replacement for `std::env::temp_dir`, for code migrating to or
+//! from this crate.
+//!
+//! `temp_dir` here always returns what `std::env::temp_dir` does, while
+//! `diff` reports whether this crate's own `tempdir::temp_dir` would pick a
+//! different directory, and why. The two differ, for example, when
+//! `TMPDIR` is set to the empty string (which std returns as is, and this
+//! crate ignores), when `WINDIR` is set but `TMP`, `TEMP` and `USERPROFILE`
+//! are not, or when a `Config` override, resolver or strategy is installed.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use tempdir::env_compat;
+//!
+//! if let Some(divergence) = env_compat::diff() {
+//!     println!("migrating changes the temporary directory: {}", divergence);
+//! }
+//! ```
+
+use std::env;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+use resolve::{Source, temp_dir_source};
+
+/// Returns the temporary directory exactly as `std::env::temp_dir` does,
+/// whatever rules this crate is configured with.
+///
+/// On Unix, that is `TMPDIR` if it is set, even to the empty string, and
+/// `/tmp` otherwise. On Windows, it is whatever `GetTempPath` returns: the
+/// first of `TMP`, `TEMP` and `USERPROFILE` that is set, and the Windows
+/// directory otherwise, always with a trailing backslash.
+pub fn temp_dir() -> PathBuf {
+    env::temp_dir()
+}
+
+/// The directories `std::env::temp_dir` and this crate's `temp_dir` resolve
+/// to, when they differ.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    /// What `std::env::temp_dir` returns.
+    pub std: PathBuf,
+    /// What this crate's `temp_dir` returns.
+    pub resolved: PathBuf,
+    /// The rule that produced `resolved`.
+    pub source: Source,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "std uses {}, tempdir uses {} (source: {:?})", self.std.display(),
+               self.resolved.display(), self.source)
+    }
+}
+
+/// Returns where this crate's resolution would choose a different directory
+/// than `std::env::temp_dir`, or `None` if both agree.
+///
+/// Paths are compared as written, except that the trailing separator
+/// Windows adds is ignored.
+pub fn diff() -> Option<Divergence> {
+    let std = env::temp_dir();
+    let (resolved, source) = temp_dir_source();
+    if components(&std) == components(&resolved) {
+        None
+    } else {
+        Some(Divergence { std: std, resolved: resolved, source: source })
+    }
+}
+
+/// Returns the components of `path`, which leave out a trailing separator.
+fn components(path: &Path) -> Vec<Component> {
+    path.components().collect()
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::env;
+
+    use super::super::*;
+    use resolve::SavedResolution;
+
+    #[test]
+    fn test_env_compat() {
+        // Holds the lock on global state for the whole test.
+        let _saved = SavedResolution::new();
+        assert_eq!(env_compat::temp_dir(), env::temp_dir());
+
+        let root = env::temp_dir().join("test_env_compat");
+        {
+            let _guard = Config::new().root(&root).apply();
+            let divergence = env_compat::diff().unwrap();
+            assert_eq!(divergence.std, env::temp_dir());
+            assert_eq!(divergence.resolved, root);
+            assert_eq!(divergence.source, Source::Override);
+        }
+
+        // With the std rules, the two cannot disagree.
+        set_rules(Rules::Std);
+        assert_eq!(env_compat::diff(), None);
+    }
+}
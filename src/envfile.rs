@@ -0,0 +1,134 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::io;
+use std::path::AsPath;
+
+use super::{TempDir, TrackedFile};
+
+/// The variable every `EnvFile` starts with, holding the path of the
+/// temporary directory itself.
+pub const ROOT_VAR: &'static str = "TEMPDIR_ROOT";
+
+/// A `.env`-style description of the layout of a `TempDir`, for spawned
+/// processes (in any language) to discover where things are.
+///
+/// Variables are written one per line as `KEY="value"`, in the order they
+/// were added, starting with `TEMPDIR_ROOT`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use tempdir::TempDir;
+///
+/// let temp_dir = TempDir::new("sandbox").unwrap();
+/// let env = temp_dir.env_file()
+///                   .path("DATA_DIR", "data")
+///                   .path("CONTROL_SOCKET", "control.sock")
+///                   .port("HTTP_PORT", 8080)
+///                   .write(".env")
+///                   .unwrap();
+/// println!("layout written to {}", env.path().display());
+/// ```
+pub struct EnvFile<'a> {
+    dir: &'a TempDir,
+    vars: Vec<(String, String)>,
+}
+
+impl<'a> EnvFile<'a> {
+
+    /// Adds a variable with a literal value.
+    pub fn var(&mut self, key: &str, value: &str) -> &mut EnvFile<'a> {
+        self.vars.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Adds a variable holding the full path of `rel`, relative to the
+    /// temporary directory, e.g. a subdirectory or a socket.
+    pub fn path<P: ?Sized>(&mut self, key: &str, rel: &P) -> &mut EnvFile<'a>
+        where P: AsPath
+    {
+        let value = format!("{}", self.dir.path().join(rel.as_path()).display());
+        self.var(key, &value)
+    }
+
+    /// Adds a variable holding a port number.
+    pub fn port(&mut self, key: &str, port: u16) -> &mut EnvFile<'a> {
+        self.var(key, &port.to_string())
+    }
+
+    /// Returns the contents of the file.
+    pub fn contents(&self) -> String {
+        let mut contents = String::new();
+        for &(ref key, ref value) in self.vars.iter() {
+            contents.push_str(&format!("{}=\"{}\"\n", key, escape(value)));
+        }
+        contents
+    }
+
+    /// Writes the file to `rel`, relative to the temporary directory.
+    pub fn write<P: ?Sized>(&self, rel: &P) -> io::Result<TrackedFile<'a>>
+        where P: AsPath
+    {
+        self.dir.write(rel, self.contents().as_bytes())
+    }
+}
+
+/// Escapes `value` for use between double quotes.
+fn escape(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '$' => escaped.push_str("\\$"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl TempDir {
+
+    /// Starts a `.env`-style file describing the layout of the temporary
+    /// directory.
+    pub fn env_file(&self) -> EnvFile {
+        let mut env = EnvFile { dir: self, vars: Vec::new() };
+        let root = format!("{}", self.path().display());
+        env.var(ROOT_VAR, &root);
+        env
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs::File;
+    use std::io::Read;
+
+    use super::super::*;
+
+    #[test]
+    fn test_env_file() {
+        let temp_dir = TempDir::new("test_env_file").unwrap();
+        let file = temp_dir.env_file()
+                           .path("DATA_DIR", "data")
+                           .port("HTTP_PORT", 8080)
+                           .var("GREETING", "say \"hi\"")
+                           .write(".env")
+                           .unwrap();
+
+        let mut s = String::new();
+        File::open(file.path()).unwrap().read_to_string(&mut s).unwrap();
+        let data = temp_dir.path().join("data");
+        assert_eq!(s, format!("TEMPDIR_ROOT=\"{}\"\nDATA_DIR=\"{}\"\nHTTP_PORT=\"8080\"\n\
+                               GREETING=\"say \\\"hi\\\"\"\n",
+                              temp_dir.path().display(), data.display()));
+    }
+}
@@ -0,0 +1,412 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The failures specific to this crate, and how they are reported as
+/// `io::Error`s.
+///
+/// Creating, closing and persisting a `TempDir` return this error directly;
+/// everything else in this crate returns `io::Error`s, built from these
+/// variants with `into_io_error` (or `From`), using the following mapping,
+/// which is part of the stable interface:
+///
+/// | Variant            | `io::ErrorKind`     |
+/// |--------------------|---------------------|
+/// | `Exhausted`        | `PathAlreadyExists` |
+/// | `Taken`            | `PathAlreadyExists` |
+/// | `Escapes`          | `InvalidInput`      |
+/// | `NulByte`          | `InvalidInput`      |
+/// | `NotADirectory`    | `InvalidInput`      |
+/// | `Mismatch`         | `InvalidInput`      |
+/// | `ToolNotFound`     | `InvalidInput`      |
+/// | `NotInPath`        | `InvalidInput`      |
+/// | `NoSnapshot`       | `InvalidInput`      |
+/// | `NameTooLong`      | `InvalidInput`      |
+/// | `InvalidPrefix`    | `InvalidInput`      |
+/// | `ZeroSeed`         | `InvalidInput`      |
+/// | `NonUnicodeName`   | `InvalidInput`      |
+/// | `TooLarge`         | `InvalidInput`      |
+/// | `NoFileName`       | `InvalidInput`      |
+/// | `DependencyCycle`  | `InvalidInput`      |
+/// | `SymlinkCycle`     | `InvalidInput`      |
+/// | `InvalidManifest`  | `InvalidInput`      |
+/// | `Locked`           | `WouldBlock`        |
+/// | `NotPrivate`       | `PermissionDenied`  |
+/// | `Quarantined`      | `PermissionDenied`  |
+/// | `Swapped`          | `PermissionDenied`  |
+/// | `NoSpace`          | `Other`             |
+/// | `OutDirUnset`      | `Other`             |
+/// | `Gone`             | `Other`             |
+/// | `Panicked`         | `Other`             |
+/// | `Changed`          | `Other`             |
+/// | `Corrupted`        | `Other`             |
+/// | `ShortRead`        | `Other`             |
+/// | `Uninitialized`    | `Other`             |
+/// | `NoEntryTypes`     | `Other`             |
+/// | `Injected`         | the given kind      |
+/// | `NoSignalHandlers` | the given kind      |
+/// | `Failed`           | that of the cause   |
+/// | `Io`               | unchanged           |
+///
+/// The resulting error's description is this error's `description`, and its
+/// detail is the `Display` output.
+///
+/// Failures to create, clean up or persist a temporary directory are
+/// reported as `Failed`, which records the operation, the path and how many
+/// attempts were made, also available through `operation`, `path` and
+/// `attempts`, so that e.g. an `Exhausted` error says where names ran out.
+/// Converted to an `io::Error`, the same context is in its detail.
+#[derive(Debug)]
+pub enum Error {
+    /// No unused name could be found in the directory. The string lists
+    /// colliding entries.
+    Exhausted(PathBuf, String),
+    /// A name that was to be unused exists already.
+    Taken(PathBuf),
+    /// A path meant to be relative to a temporary directory would point
+    /// outside of it.
+    Escapes(PathBuf),
+    /// A path contains a nul byte and cannot be passed to the system.
+    NulByte(PathBuf),
+    /// A path that must be a directory is not.
+    NotADirectory(PathBuf),
+    /// The contents of a temporary directory or file are not what an
+    /// assertion expected. The string describes the difference.
+    Mismatch(String),
+    /// An executable could not be found on `PATH`.
+    ToolNotFound(String),
+    /// A path cannot be put into a `PATH`-style list, e.g. because it
+    /// contains the separator.
+    NotInPath(PathBuf),
+    /// Permissions were to be restored, but no snapshot of them was taken.
+    NoSnapshot(PathBuf),
+    /// Names generated for the directory cannot fit within the file name
+    /// length limit (in bytes) of its file system, even with the prefix
+    /// shortened.
+    NameTooLong(PathBuf, usize),
+    /// A `Prefix` was constructed from a string that cannot be used as one.
+    /// The string describes why.
+    InvalidPrefix(String),
+    /// A random generator was to be seeded with all zeroes, which it
+    /// cannot be.
+    ZeroSeed,
+    /// A name cannot be stored in an archive, as it is not valid UTF-8.
+    NonUnicodeName(PathBuf),
+    /// An archive cannot hold an entry or as many entries. The string says
+    /// what does not fit.
+    TooLarge(String),
+    /// A path that must name a file ends in `..` or is a root.
+    NoFileName(PathBuf),
+    /// A member of a `CleanupGroup` would depend on itself.
+    DependencyCycle,
+    /// Following symbolic links while copying leads back to a directory
+    /// being copied.
+    SymlinkCycle(PathBuf),
+    /// An owner manifest cannot be parsed. The string says why.
+    InvalidManifest(String),
+    /// A file is locked by someone else, and waiting was not asked for.
+    Locked,
+    /// A directory that must only be accessible by the current user is
+    /// not.
+    NotPrivate(PathBuf),
+    /// A temporary directory looked tampered with and was moved aside
+    /// instead of being removed. The string describes what was found.
+    Quarantined(String),
+    /// A temporary directory was no longer the one that was created, e.g.
+    /// because it was swapped for a symbolic link, so it was not removed.
+    /// The string describes what was found.
+    Swapped(String),
+    /// The file system holding the directory has less free space, in
+    /// bytes, than required: the first number is the space required, the
+    /// second the space available.
+    NoSpace(PathBuf, u64, u64),
+    /// `OUT_DIR` is not set, because we are not running as a build script.
+    OutDirUnset,
+    /// The directory has already been removed.
+    Gone(PathBuf),
+    /// An operation running on a background thread panicked.
+    Panicked,
+    /// A file changed size while it was being archived.
+    Changed(PathBuf),
+    /// A cached fixture does not match its manifest.
+    Corrupted(PathBuf),
+    /// A file ended before enough bytes could be read from it.
+    ShortRead(PathBuf),
+    /// Minimal syscall mode was used before `minimal::init`.
+    Uninitialized,
+    /// A directory listing does not say which entries are directories, so
+    /// it cannot be removed in minimal syscall mode.
+    NoEntryTypes,
+    /// A fault injected with the `faults` module made the operation fail
+    /// with an error of the given kind.
+    Injected(Operation, io::ErrorKind),
+    /// The signal handlers could not be installed, with an error of the
+    /// given kind. The string describes the cause.
+    NoSignalHandlers(io::ErrorKind, String),
+    /// The operation failed on the path after the given number of attempts,
+    /// because of the error.
+    Failed(Operation, PathBuf, u32, io::Error),
+    /// Any other I/O error.
+    Io(io::Error),
+}
+
+/// The operations whose failures are reported as `Error::Failed`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operation {
+    /// Creating a temporary directory in a base directory.
+    Create,
+    /// Removing a temporary directory.
+    Cleanup,
+    /// Moving a temporary directory to its final destination.
+    Persist,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Operation::Create => "create",
+            Operation::Cleanup => "cleanup",
+            Operation::Persist => "persist",
+        })
+    }
+}
+
+impl Error {
+
+    /// Returns the `io::ErrorKind` this error is reported as.
+    pub fn kind(&self) -> io::ErrorKind {
+        match *self {
+            Error::Exhausted(..) | Error::Taken(..) => io::ErrorKind::PathAlreadyExists,
+            Error::Escapes(..) | Error::NulByte(..) | Error::NotADirectory(..) |
+            Error::Mismatch(..) | Error::ToolNotFound(..) | Error::NotInPath(..) |
+            Error::NoSnapshot(..) | Error::NameTooLong(..) | Error::InvalidPrefix(..) |
+            Error::ZeroSeed | Error::NonUnicodeName(..) | Error::TooLarge(..) |
+            Error::NoFileName(..) | Error::DependencyCycle | Error::SymlinkCycle(..) |
+            Error::InvalidManifest(..) => {
+                io::ErrorKind::InvalidInput
+            }
+            Error::Locked => io::ErrorKind::WouldBlock,
+            Error::NotPrivate(..) | Error::Quarantined(..) | Error::Swapped(..) => {
+                io::ErrorKind::PermissionDenied
+            }
+            Error::NoSpace(..) | Error::OutDirUnset | Error::Gone(..) | Error::Panicked |
+            Error::Changed(..) | Error::Corrupted(..) | Error::ShortRead(..) |
+            Error::Uninitialized | Error::NoEntryTypes => {
+                io::ErrorKind::Other
+            }
+            Error::Injected(_, kind) | Error::NoSignalHandlers(kind, _) => kind,
+            Error::Failed(_, _, _, ref e) | Error::Io(ref e) => e.kind(),
+        }
+    }
+
+    /// Converts the error into an `io::Error` according to the mapping
+    /// above.
+    pub fn into_io_error(self) -> io::Error {
+        match self {
+            Error::Io(e) => e,
+            e => io::Error::new(e.kind(), e.message(), Some(e.to_string())),
+        }
+    }
+
+    fn message(&self) -> &'static str {
+        match *self {
+            Error::Exhausted(..) => "no unused name found",
+            Error::Taken(..) => "name is taken",
+            Error::Escapes(..) => "path escapes the temporary directory",
+            Error::NulByte(..) => "path contains a nul byte",
+            Error::NotADirectory(..) => "not a directory",
+            Error::Mismatch(..) => "contents do not match",
+            Error::ToolNotFound(..) => "executable not found on PATH",
+            Error::NotInPath(..) => "path cannot be used in PATH",
+            Error::NoSnapshot(..) => "no permission snapshot was taken",
+            Error::NameTooLong(..) => "generated name exceeds the file name length limit",
+            Error::InvalidPrefix(..) => "invalid temporary directory prefix",
+            Error::ZeroSeed => "seed must not be all zeroes",
+            Error::NonUnicodeName(..) => "cannot archive a non-UTF-8 name",
+            Error::TooLarge(..) => "too large for the archive format",
+            Error::NoFileName(..) => "path has no file name",
+            Error::DependencyCycle => "dependency cycle in cleanup group",
+            Error::SymlinkCycle(..) => "symbolic link cycle in copy source",
+            Error::InvalidManifest(..) => "invalid owner manifest",
+            Error::Locked => "file is locked",
+            Error::NotPrivate(..) => "per-user temporary directory is not private",
+            Error::Quarantined(..) => "temporary directory quarantined",
+            Error::Swapped(..) => "temporary directory was swapped; not removed",
+            Error::NoSpace(..) => "not enough free space",
+            Error::OutDirUnset => "OUT_DIR is not set; not running as a build script",
+            Error::Gone(..) => "directory was already removed",
+            Error::Panicked => "background operation panicked",
+            Error::Changed(..) => "file changed while being archived",
+            Error::Corrupted(..) => "cached fixture failed verification",
+            Error::ShortRead(..) => "short read",
+            Error::Uninitialized => "minimal syscall mode used before tempdir::minimal::init",
+            Error::NoEntryTypes => "filesystem does not report entry types",
+            Error::Injected(Operation::Create, _) => "injected creation fault",
+            Error::Injected(Operation::Cleanup, _) => "injected cleanup fault",
+            Error::Injected(Operation::Persist, _) => "injected persist fault",
+            Error::NoSignalHandlers(..) => "could not install signal handlers",
+            Error::Failed(Operation::Create, ..) => "failed to create temporary directory",
+            Error::Failed(Operation::Cleanup, ..) => "failed to remove temporary directory",
+            Error::Failed(Operation::Persist, ..) => "failed to persist temporary directory",
+            Error::Io(..) => "I/O error",
+        }
+    }
+
+    /// Shorthand for `Error::NulByte`, for the paths converted to C strings.
+    pub fn nul_byte(path: &Path) -> io::Error {
+        Error::NulByte(path.to_path_buf()).into_io_error()
+    }
+
+    /// Returns the operation that failed, for `Failed` errors.
+    pub fn operation(&self) -> Option<Operation> {
+        match *self {
+            Error::Failed(op, ..) => Some(op),
+            _ => None,
+        }
+    }
+
+    /// Returns the path the error is about, if it names one.
+    pub fn path(&self) -> Option<&Path> {
+        match *self {
+            Error::Exhausted(ref p, _) | Error::Escapes(ref p) | Error::NulByte(ref p) |
+            Error::NotADirectory(ref p) | Error::NotInPath(ref p) | Error::NoSnapshot(ref p) |
+            Error::NameTooLong(ref p, _) | Error::NotPrivate(ref p) | Error::NoSpace(ref p, ..) |
+            Error::Gone(ref p) | Error::Failed(_, ref p, ..) | Error::Taken(ref p) |
+            Error::NonUnicodeName(ref p) | Error::NoFileName(ref p) | Error::SymlinkCycle(ref p) |
+            Error::Changed(ref p) | Error::Corrupted(ref p) | Error::ShortRead(ref p) => Some(p),
+            _ => None,
+        }
+    }
+
+    /// Returns how many attempts were made before giving up, for `Failed`
+    /// errors. More than one means the retries were exhausted.
+    pub fn attempts(&self) -> Option<u32> {
+        match *self {
+            Error::Failed(_, _, attempts, _) => Some(attempts),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        err.into_io_error()
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Exhausted(ref dir, ref collisions) => {
+                write!(f, "no unused name found in {}: {}", dir.display(), collisions)
+            }
+            Error::Escapes(ref p) | Error::NulByte(ref p) | Error::NotADirectory(ref p) |
+            Error::NotPrivate(ref p) | Error::NotInPath(ref p) | Error::Gone(ref p) |
+            Error::NoSnapshot(ref p) | Error::Taken(ref p) | Error::NonUnicodeName(ref p) |
+            Error::NoFileName(ref p) | Error::SymlinkCycle(ref p) | Error::Changed(ref p) |
+            Error::Corrupted(ref p) | Error::ShortRead(ref p) => {
+                write!(f, "{}", p.display())
+            }
+            Error::NameTooLong(ref dir, limit) => {
+                write!(f, "{}: names are limited to {} bytes", dir.display(), limit)
+            }
+            Error::NoSpace(ref dir, required, available) => {
+                write!(f, "{}: {} bytes required, {} bytes available", dir.display(), required,
+                       available)
+            }
+            Error::ToolNotFound(ref name) => write!(f, "{}", name),
+            Error::Mismatch(ref detail) | Error::Quarantined(ref detail) |
+            Error::Swapped(ref detail) | Error::InvalidPrefix(ref detail) |
+            Error::TooLarge(ref detail) | Error::InvalidManifest(ref detail) |
+            Error::NoSignalHandlers(_, ref detail) => {
+                write!(f, "{}", detail)
+            }
+            Error::OutDirUnset => write!(f, "not running as a build script"),
+            Error::DependencyCycle | Error::Locked | Error::Uninitialized |
+            Error::NoEntryTypes | Error::Injected(..) => f.write_str(self.message()),
+            Error::ZeroSeed => write!(f, "the seed [0, 0, 0, 0] cannot be used"),
+            Error::Panicked => write!(f, "background operation panicked"),
+            Error::Failed(op, ref path, attempts, ref e) => {
+                try!(write!(f, "{} {}", op, path.display()));
+                if attempts > 1 {
+                    try!(write!(f, " (gave up after {} attempts)", attempts));
+                }
+                write!(f, ": {}", e)
+            }
+            Error::Io(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Io(ref e) => error::Error::description(e),
+            _ => self.message(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::io;
+    use std::path::PathBuf;
+
+    use super::super::*;
+
+    #[test]
+    fn test_into_io_error() {
+        let err = Error::Escapes(PathBuf::new("../x")).into_io_error();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(err.detail(), Some("../x".to_string()));
+
+        let err = TempDir::new("test_into_io_error").unwrap().create_file("/abs").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        assert_eq!(Error::Locked.into_io_error().kind(), io::ErrorKind::WouldBlock);
+        let err = Error::Injected(Operation::Cleanup, io::ErrorKind::PermissionDenied);
+        assert_eq!(err.into_io_error().kind(), io::ErrorKind::PermissionDenied);
+
+        let mut group = CleanupGroup::new();
+        let member = group.add_dir(TempDir::new("test_into_io_error").unwrap());
+        let err = group.depends_on(member, member).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(err.detail(), Some("dependency cycle in cleanup group".to_string()));
+    }
+
+    #[test]
+    fn test_failed_context() {
+        let base = TempDir::new("test_failed_context").unwrap();
+        let missing = base.path().join("missing");
+        let err = TempDir::new_in(&missing, "x").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(err.operation(), Some(Operation::Create));
+        assert_eq!(err.path(), Some(&*missing));
+        assert_eq!(err.attempts(), Some(1));
+        let err = io::Error::from(err);
+        let detail = err.detail().unwrap();
+        assert!(detail.starts_with("create ") && detail.contains("missing"));
+
+        let temp_dir = TempDir::new_in(base.path(), "src").unwrap();
+        let err = temp_dir.persist(&missing.join("dest")).unwrap_err();
+        assert_eq!(err.operation(), Some(Operation::Persist));
+        assert_eq!(err.path(), Some(&*missing.join("dest")));
+        assert!(err.to_string().starts_with("persist "));
+    }
+}
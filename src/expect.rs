@@ -0,0 +1,227 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, PathExt};
+use std::io;
+use std::path::{AsPath, Path, PathBuf};
+
+use unicode_normalization::UnicodeNormalization;
+
+use super::{Error, TempDir};
+use walk::{relative, walk};
+
+/// How paths are compared by `TempDir::close_expecting_with`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaseSensitivity {
+    /// Paths must match exactly.
+    Sensitive,
+    /// Paths which only differ in case match.
+    Insensitive,
+    /// Compare case-insensitively if the file system holding the temporary
+    /// directory is case-insensitive (as is usual on macOS and Windows), and
+    /// exactly otherwise.
+    Detect,
+}
+
+impl CaseSensitivity {
+
+    /// Resolves `Detect` by probing the file system at `dir`.
+    fn resolve(self, dir: &Path) -> io::Result<CaseSensitivity> {
+        if self != CaseSensitivity::Detect {
+            return Ok(self);
+        }
+        let probe = dir.join(".tempdir-CaseProbe");
+        try!(File::create(&probe));
+        let insensitive = dir.join(".tempdir-caseprobe").exists();
+        try!(fs::remove_file(&probe));
+        Ok(if insensitive { CaseSensitivity::Insensitive } else { CaseSensitivity::Sensitive })
+    }
+
+    /// Returns the key `path` is compared by.
+    fn key(self, path: &Path) -> PathBuf {
+        match self {
+            CaseSensitivity::Insensitive => {
+                PathBuf::new(&path.to_string_lossy().to_lowercase())
+            }
+            _ => path.to_path_buf(),
+        }
+    }
+}
+
+/// How Unicode in paths is compared by `TempDir::close_expecting_normalized`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Normalization {
+    /// Paths must consist of the same code points.
+    Exact,
+    /// Paths which are canonically equivalent match, and are reported in
+    /// NFC (composed) form.
+    Nfc,
+    /// Paths which are canonically equivalent match, and are reported in
+    /// NFD (decomposed) form, as HFS+ stores them.
+    Nfd,
+    /// Use `Nfd` if the file system holding the temporary directory
+    /// normalizes names (as HFS+ and APFS do), and `Exact` otherwise.
+    Detect,
+}
+
+impl Normalization {
+
+    /// Resolves `Detect` by probing the file system at `dir`.
+    fn resolve(self, dir: &Path) -> io::Result<Normalization> {
+        if self != Normalization::Detect {
+            return Ok(self);
+        }
+        let probe = dir.join(".tempdir-probe-\u{e9}");
+        try!(File::create(&probe));
+        let normalizing = dir.join(".tempdir-probe-e\u{301}").exists();
+        try!(fs::remove_file(&probe));
+        Ok(if normalizing { Normalization::Nfd } else { Normalization::Exact })
+    }
+
+    /// Returns `path` in this normal form.
+    fn apply(self, path: &Path) -> PathBuf {
+        match self {
+            Normalization::Nfc => {
+                PathBuf::new(&path.to_string_lossy().nfc().collect::<String>())
+            }
+            Normalization::Nfd => {
+                PathBuf::new(&path.to_string_lossy().nfd().collect::<String>())
+            }
+            _ => path.to_path_buf(),
+        }
+    }
+}
+
+impl TempDir {
+
+    /// Close and remove the temporary directory like `close`, after checking
+    /// that it contains exactly the entries listed in `manifest`.
+    ///
+    /// Entries are given relative to the root of the temporary directory.
+    /// Directories that are parents of a listed entry are expected
+    /// implicitly, so `&["a/b.txt"]` matches a tree containing the directory
+    /// `a` and the file `a/b.txt`.
+    ///
+    /// The directory is removed either way. If the contents do not match, an
+    /// `Error::Mismatch` listing the missing and unexpected entries is
+    /// returned.
+    pub fn close_expecting<P>(self, manifest: &[P]) -> Result<(), Error>
+        where P: AsPath
+    {
+        self.close_expecting_with(manifest, CaseSensitivity::Sensitive)
+    }
+
+    /// Like `close_expecting`, comparing paths according to `case`.
+    pub fn close_expecting_with<P>(self, manifest: &[P], case: CaseSensitivity)
+                                   -> Result<(), Error>
+        where P: AsPath
+    {
+        self.close_expecting_normalized(manifest, case, Normalization::Exact)
+    }
+
+    /// Like `close_expecting`, comparing paths according to `case` after
+    /// bringing them into the normal form `form`, so that a manifest with
+    /// non-ASCII names matches the same way on every platform.
+    pub fn close_expecting_normalized<P>(self, manifest: &[P], case: CaseSensitivity,
+                                         form: Normalization) -> Result<(), Error>
+        where P: AsPath
+    {
+        let resolved = case.resolve(self.path())
+                           .and_then(|case| form.resolve(self.path()).map(|form| (case, form)));
+        let (case, form) = match resolved {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                let _ = self.close();
+                return Err(Error::Io(e));
+            }
+        };
+
+        let mut expected = BTreeMap::new();
+        for entry in manifest.iter() {
+            let mut path = Some(entry.as_path());
+            while let Some(p) = path {
+                if p.as_os_str().len() == 0 { break; }
+                let normalized = form.apply(p);
+                expected.insert(case.key(&normalized), normalized);
+                path = p.parent();
+            }
+        }
+
+        let found: io::Result<BTreeMap<PathBuf, PathBuf>> = walk(self.path()).map(|entries| {
+            entries.iter()
+                   .map(|e| form.apply(relative(self.path(), &e.path)))
+                   .map(|p| (case.key(&p), p))
+                   .collect()
+        });
+        let closed = self.close();
+        let found = try!(found);
+        try!(closed);
+
+        let missing: Vec<_> = expected.iter().filter(|&(k, _)| !found.contains_key(k)).collect();
+        let unexpected: Vec<_> = found.iter().filter(|&(k, _)| !expected.contains_key(k)).collect();
+        if missing.is_empty() && unexpected.is_empty() {
+            return Ok(());
+        }
+
+        let mut detail = String::new();
+        for &(_, p) in missing.iter() {
+            detail.push_str(&format!("\n  missing: {}", p.display()));
+        }
+        for &(_, p) in unexpected.iter() {
+            detail.push_str(&format!("\n  unexpected: {}", p.display()));
+        }
+        Err(Error::Mismatch(format!("temporary directory contents do not match the manifest:{}",
+                                    detail)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs::{self, File};
+
+    use super::super::*;
+
+    #[test]
+    fn test_close_expecting() {
+        let temp_dir = TempDir::new("test_close_expecting").unwrap();
+        fs::create_dir(&temp_dir.path().join("a")).unwrap();
+        File::create(&temp_dir.path().join("a/b.txt")).unwrap();
+        temp_dir.close_expecting(&["a/b.txt"]).unwrap();
+    }
+
+    #[test]
+    fn test_close_expecting_mismatch() {
+        let temp_dir = TempDir::new("test_close_expecting_mismatch").unwrap();
+        File::create(&temp_dir.path().join("extra.txt")).unwrap();
+        match temp_dir.close_expecting(&["expected.txt"]) {
+            Err(Error::Mismatch(ref detail)) => {
+                assert!(detail.contains("missing: expected.txt"));
+                assert!(detail.contains("unexpected: extra.txt"));
+            }
+            _ => panic!("expected a mismatch"),
+        }
+    }
+
+    #[test]
+    fn test_close_expecting_case_insensitive() {
+        let temp_dir = TempDir::new("test_close_expecting_case_insensitive").unwrap();
+        File::create(&temp_dir.path().join("README.md")).unwrap();
+        temp_dir.close_expecting_with(&["readme.md"], CaseSensitivity::Insensitive).unwrap();
+    }
+
+    #[test]
+    fn test_close_expecting_normalized() {
+        let temp_dir = TempDir::new("test_close_expecting_normalized").unwrap();
+        // A decomposed name, as HFS+ would store it.
+        File::create(&temp_dir.path().join("cafe\u{301}.txt")).unwrap();
+        temp_dir.close_expecting_normalized(&["caf\u{e9}.txt"], CaseSensitivity::Sensitive,
+                                            Normalization::Nfc).unwrap();
+    }
+}
@@ -0,0 +1,117 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Falling back to another base directory when the requested one cannot be
+//! written to.
+
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use resolve::{VARS, default_dir};
+use super::TempDir;
+
+/// Records that a `TempDir` was created somewhere other than the base
+/// directory it was asked for, because of `Builder::read_only_fallback`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Substitution {
+    /// The base directory that was asked for.
+    pub requested: PathBuf,
+    /// The base directory the `TempDir` was created in instead.
+    pub used: PathBuf,
+    /// Why the requested base directory could not be used.
+    pub reason: String,
+}
+
+/// Returns whether `err` means that nothing can be created in a directory,
+/// as opposed to e.g. a name collision.
+pub fn is_read_only(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::PermissionDenied || is_read_only_fs(err)
+}
+
+#[cfg(unix)]
+fn is_read_only_fs(err: &io::Error) -> bool {
+    use libc;
+
+    err.raw_os_error() == Some(libc::EROFS)
+}
+
+#[cfg(not(unix))]
+fn is_read_only_fs(_err: &io::Error) -> bool {
+    false
+}
+
+/// Returns the base directories to try, in order, when `requested` cannot be
+/// written to: whatever the environment variables and the platform default
+/// name, then the usual alternatives.
+pub fn candidates(requested: &Path) -> Vec<PathBuf> {
+    let mut candidates: Vec<PathBuf> = Vec::new();
+    for var in VARS.iter() {
+        if let Some(value) = env::var_os(var) {
+            if !value.is_empty() {
+                candidates.push(PathBuf::new(&value));
+            }
+        }
+    }
+    candidates.push(default_dir());
+    candidates.push(env::temp_dir());
+    if cfg!(unix) {
+        candidates.push(PathBuf::new("/var/tmp"));
+        candidates.push(PathBuf::new("/dev/shm"));
+    }
+    let mut unique: Vec<PathBuf> = Vec::new();
+    for candidate in candidates.into_iter() {
+        if &*candidate != requested && !unique.contains(&candidate) {
+            unique.push(candidate);
+        }
+    }
+    unique
+}
+
+impl TempDir {
+
+    /// Returns where the directory was created instead of the base
+    /// directory it was asked for, if `Builder::read_only_fallback` had to
+    /// pick another one.
+    pub fn substitution(&self) -> Option<&Substitution> {
+        self.substitution.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs;
+
+    use super::super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_read_only_fallback() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let locked = TempDir::new("test_read_only_fallback").unwrap();
+        let writable = TempDir::new("test_read_only_fallback").unwrap();
+        fs::set_permissions(locked.path(), fs::Permissions::from_mode(0o500)).unwrap();
+        // Root can write anywhere, so there is nothing to fall back from.
+        if TempDir::new_in(locked.path(), "probe").is_ok() {
+            return;
+        }
+
+        assert!(Builder::new().tempdir_in(locked.path()).is_err());
+        let temp_dir = Builder::new().read_only_fallback(true)
+                                     .fallback_dirs(vec![writable.path().to_path_buf()])
+                                     .tempdir_in(locked.path())
+                                     .unwrap();
+        assert_eq!(temp_dir.path().parent(), Some(writable.path()));
+        let substitution = temp_dir.substitution().unwrap();
+        assert_eq!(substitution.requested, locked.path());
+        assert_eq!(substitution.used, writable.path());
+        fs::set_permissions(locked.path(), fs::Permissions::from_mode(0o700)).unwrap();
+    }
+}
@@ -0,0 +1,201 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, PathExt};
+use std::io;
+use std::path::{AsPath, Path, PathBuf};
+
+use super::{Error, TempDir};
+
+/// The subdirectory of the `TempDir` the farm is built in.
+const BIN_DIR: &'static str = "bin";
+
+/// A directory of links to selected executables, for running tests with a
+/// `PATH` that exposes exactly those tools.
+///
+/// On Unix each tool is a symbolic link to the real executable; on Windows
+/// it is a `.cmd` shim that forwards its arguments.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::process::Command;
+/// use tempdir::TempDir;
+///
+/// let temp_dir = TempDir::new("tools").unwrap();
+/// let path = temp_dir.bin_farm().find("git").link("cc", "/usr/bin/clang").build().unwrap();
+/// Command::new("make").env("PATH", &path).status().unwrap();
+/// ```
+pub struct BinFarm<'a> {
+    dir: &'a TempDir,
+    tools: Vec<(String, Option<PathBuf>)>,
+}
+
+impl<'a> BinFarm<'a> {
+
+    /// Adds the executable `name` as found on the current `PATH`. If it
+    /// cannot be found, `build` fails.
+    pub fn find(&mut self, name: &str) -> &mut BinFarm<'a> {
+        self.tools.push((name.to_string(), None));
+        self
+    }
+
+    /// Adds the executable at `target` under the name `name`.
+    pub fn link<P: ?Sized>(&mut self, name: &str, target: &P) -> &mut BinFarm<'a>
+        where P: AsPath
+    {
+        self.tools.push((name.to_string(), Some(target.as_path().to_path_buf())));
+        self
+    }
+
+    /// Creates the farm inside the temporary directory and returns a value
+    /// for the `PATH` environment variable which contains only the farm.
+    pub fn build(&self) -> io::Result<OsString> {
+        let bin = self.dir.path().join(BIN_DIR);
+        try!(fs::create_dir_all(&bin));
+        for &(ref name, ref target) in self.tools.iter() {
+            let target = match *target {
+                Some(ref target) => target.clone(),
+                None => try!(find_on_path(name).ok_or_else(|| {
+                    Error::ToolNotFound(name.clone()).into_io_error()
+                })),
+            };
+            // The link is resolved from `bin`, and the tool run from
+            // wherever, not from the current directory.
+            let target = if target.is_absolute() {
+                target
+            } else {
+                try!(env::current_dir()).join(&target)
+            };
+            try!(install(&bin, name, &target));
+        }
+        env::join_paths([bin.clone()].iter()).map_err(|_| Error::NotInPath(bin).into_io_error())
+    }
+}
+
+/// Returns the first executable named `name` on the current `PATH`.
+fn find_on_path(name: &str) -> Option<PathBuf> {
+    let path = match env::var_os("PATH") {
+        Some(path) => path,
+        None => return None,
+    };
+    for dir in env::split_paths(&path) {
+        for candidate in candidates(&dir, name).into_iter() {
+            if is_executable(&candidate) {
+                return Some(candidate);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    match fs::metadata(path) {
+        Ok(metadata) => metadata.is_file() && metadata.permissions().mode() & 0o111 != 0,
+        Err(_) => false,
+    }
+}
+
+#[cfg(windows)]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(unix)]
+fn candidates(dir: &Path, name: &str) -> Vec<PathBuf> {
+    vec![dir.join(name)]
+}
+
+#[cfg(windows)]
+fn candidates(dir: &Path, name: &str) -> Vec<PathBuf> {
+    vec![dir.join(&format!("{}.exe", name)), dir.join(&format!("{}.cmd", name)),
+         dir.join(&format!("{}.bat", name)), dir.join(name)]
+}
+
+#[cfg(unix)]
+fn install(bin: &Path, name: &str, target: &Path) -> io::Result<()> {
+    fs::soft_link(target, &bin.join(name))
+}
+
+#[cfg(windows)]
+fn install(bin: &Path, name: &str, target: &Path) -> io::Result<()> {
+    use std::fs::File;
+    use std::io::Write;
+
+    let mut shim = try!(File::create(&bin.join(&format!("{}.cmd", name))));
+    write!(shim, "@\"{}\" %*\r\n", target.display())
+}
+
+impl TempDir {
+
+    /// Starts a farm of links to executables in the `bin` subdirectory of
+    /// the temporary directory.
+    pub fn bin_farm(&self) -> BinFarm {
+        BinFarm { dir: self, tools: Vec::new() }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+
+    use std::env;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::find_on_path;
+    use super::super::*;
+    use testing::lock_globals;
+
+    #[test]
+    fn test_bin_farm() {
+        // Reads `PATH`, which other tests change.
+        let _globals = lock_globals();
+        let temp_dir = TempDir::new("test_bin_farm").unwrap();
+        let sh = find_on_path("sh").unwrap();
+        let path = temp_dir.bin_farm().find("sh").link("shell", &sh).build().unwrap();
+
+        let bin = temp_dir.path().join("bin");
+        assert_eq!(env::split_paths(&path).collect::<Vec<_>>(), vec![bin.clone()]);
+        assert_eq!(fs::read_link(&bin.join("sh")).unwrap(), sh);
+        assert_eq!(fs::read_link(&bin.join("shell")).unwrap(), sh);
+        assert!(temp_dir.bin_farm().find("no-such-tool-anywhere").build().is_err());
+    }
+
+    #[test]
+    fn test_bin_farm_relative_target() {
+        let temp_dir = TempDir::new("test_bin_farm_relative_target").unwrap();
+        temp_dir.bin_farm().link("tool", "relative/tool").build().unwrap();
+        assert_eq!(fs::read_link(&temp_dir.path().join("bin/tool")).unwrap(),
+                   env::current_dir().unwrap().join("relative/tool"));
+    }
+
+    #[test]
+    fn test_find_on_path_skips_non_executables() {
+        let _globals = lock_globals();
+        let temp_dir = TempDir::new("test_find_on_path_skips_non_executables").unwrap();
+        temp_dir.write("plain/tool", b"").unwrap();
+        temp_dir.write("exec/tool", b"").unwrap();
+        let exec = temp_dir.path().join("exec/tool");
+        fs::set_permissions(&exec, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let saved = env::var_os("PATH");
+        let dirs = vec![temp_dir.path().join("plain"), temp_dir.path().join("exec")];
+        env::set_var("PATH", &env::join_paths(dirs.iter()).unwrap());
+        let found = find_on_path("tool");
+        match saved {
+            Some(path) => env::set_var("PATH", &path),
+            None => env::remove_var("PATH"),
+        }
+        assert_eq!(found, Some(exec));
+    }
+}
@@ -0,0 +1,103 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Deterministic fault injection, for testing error handling around
+//! temporary directories.
+//!
+//! Faults are configured per thread, so tests running in parallel do not
+//! interfere with each other. Only directories created and removed on the
+//! configuring thread are affected.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use tempdir::{faults, TempDir};
+//!
+//! faults::fail_create_every(2);
+//! assert!(TempDir::new("a").is_ok());
+//! assert!(TempDir::new("b").is_err());
+//! faults::reset();
+//! ```
+
+use std::cell::Cell;
+use std::io;
+
+use error::{Error, Operation};
+
+thread_local!(static CREATE_EVERY: Cell<usize> = Cell::new(0));
+thread_local!(static CREATE_CALLS: Cell<usize> = Cell::new(0));
+thread_local!(static CLEANUP_ERROR: Cell<Option<io::ErrorKind>> = Cell::new(None));
+
+/// Makes every `n`th directory creation on this thread fail, counting from
+/// now. `0` disables the fault.
+pub fn fail_create_every(n: usize) {
+    CREATE_EVERY.with(|c| c.set(n));
+    CREATE_CALLS.with(|c| c.set(0));
+}
+
+/// Makes every cleanup on this thread fail with an error of the given kind,
+/// leaving the directory in place. `None` disables the fault.
+pub fn fail_cleanup_with(kind: Option<io::ErrorKind>) {
+    CLEANUP_ERROR.with(|c| c.set(kind));
+}
+
+/// Disables all faults on this thread.
+pub fn reset() {
+    fail_create_every(0);
+    fail_cleanup_with(None);
+}
+
+#[doc(hidden)]
+pub fn check_create() -> io::Result<()> {
+    let every = CREATE_EVERY.with(|c| c.get());
+    if every == 0 {
+        return Ok(());
+    }
+    let calls = CREATE_CALLS.with(|c| { c.set(c.get() + 1); c.get() });
+    if calls % every == 0 {
+        Err(Error::Injected(Operation::Create, io::ErrorKind::Other).into_io_error())
+    } else {
+        Ok(())
+    }
+}
+
+#[doc(hidden)]
+pub fn check_cleanup() -> io::Result<()> {
+    match CLEANUP_ERROR.with(|c| c.get()) {
+        Some(kind) => Err(Error::Injected(Operation::Cleanup, kind).into_io_error()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::io;
+
+    use super::super::*;
+
+    #[test]
+    fn test_fail_create_every() {
+        faults::fail_create_every(2);
+        assert!(TempDir::new("test_fail_create_every").is_ok());
+        assert!(TempDir::new("test_fail_create_every").is_err());
+        assert!(TempDir::new("test_fail_create_every").is_ok());
+        faults::reset();
+    }
+
+    #[test]
+    fn test_fail_cleanup_with() {
+        let temp_dir = TempDir::new("test_fail_cleanup_with").unwrap();
+        let path = temp_dir.path().to_path_buf();
+        faults::fail_cleanup_with(Some(io::ErrorKind::PermissionDenied));
+        let err = temp_dir.close().unwrap_err();
+        faults::reset();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        TempDir::from_path(&path).close().unwrap();
+    }
+}
@@ -0,0 +1,202 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::ffi::AsOsStr;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use super::{Builder, temp_dir};
+
+/// A uniquely named temporary file, removed when the wrapper is dropped.
+///
+/// The file is created exclusively, so an existing file (or a symbolic link
+/// planted by an attacker) is never opened in its place.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::io::Write;
+/// use tempdir::TempFile;
+///
+/// let mut file = TempFile::new("myprefix").unwrap();
+/// write!(file, "hello").unwrap();
+/// println!("wrote to {}", file.path().display());
+/// // the file is removed here
+/// ```
+pub struct TempFile {
+    // Both are only `None` once they have been handed out by `into_parts`.
+    file: Option<File>,
+    path: Option<PathBuf>,
+    detached: bool,
+}
+
+impl TempFile {
+
+    /// Attempts to create a temporary file inside of `os::tmpdir()` whose
+    /// name will have the prefix `prefix`. The file will be automatically
+    /// deleted once the returned wrapper is destroyed.
+    ///
+    /// If no file can be created, `Err` is returned.
+    pub fn new<P: ?Sized>(prefix: &P) -> io::Result<TempFile>
+        where P: AsOsStr
+    {
+        Builder::new().prefix(prefix).tempfile()
+    }
+
+    /// Attempts to create a temporary file inside of `tmpdir` whose name
+    /// will have the prefix `prefix`. The file will be automatically deleted
+    /// once the returned wrapper is destroyed.
+    ///
+    /// If no file can be created, `Err` is returned.
+    pub fn new_in<P: ?Sized>(tmpdir: &Path, prefix: &P) -> io::Result<TempFile>
+        where P: AsOsStr
+    {
+        Builder::new().prefix(prefix).tempfile_in(tmpdir)
+    }
+
+    /// Access the path of the temporary file.
+    pub fn path(&self) -> &Path {
+        self.path.as_ref().unwrap()
+    }
+
+    /// Access the open file handle.
+    pub fn file(&self) -> &File {
+        self.file.as_ref().unwrap()
+    }
+
+    /// Mutably access the open file handle.
+    pub fn file_mut(&mut self) -> &mut File {
+        self.file.as_mut().unwrap()
+    }
+
+    /// Unwrap the file handle and path from the wrapper. This prevents the
+    /// automatic deletion of the file.
+    pub fn into_parts(mut self) -> (File, PathBuf) {
+        self.detached = true;
+        (self.file.take().unwrap(), self.path.take().unwrap())
+    }
+
+    /// Close and remove the temporary file, reporting any error.
+    pub fn close(mut self) -> io::Result<()> {
+        self.detached = true;
+        fs::remove_file(self.path())
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.detached {
+            let _ = fs::remove_file(self.path());
+        }
+    }
+}
+
+impl Read for TempFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file_mut().read(buf)
+    }
+}
+
+impl Write for TempFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file_mut().flush()
+    }
+}
+
+impl Seek for TempFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.file_mut().seek(pos)
+    }
+}
+
+impl Builder {
+
+    /// Creates a temporary file inside of `temp_dir()`, named according to
+    /// this builder.
+    pub fn tempfile(&self) -> io::Result<TempFile> {
+        self.tempfile_in(&temp_dir())
+    }
+
+    /// Creates a temporary file inside of `tmpdir`, named according to this
+    /// builder. On Unix the file is only accessible to its owner (mode
+    /// 0600).
+    pub fn tempfile_in(&self, tmpdir: &Path) -> io::Result<TempFile> {
+        self.create_in(tmpdir, |path| {
+            let mut options = OpenOptions::new();
+            options.read(true).write(true).create_new(true);
+            private(&mut options);
+            options.open(&path).map(|file| {
+                TempFile { file: Some(file), path: Some(path), detached: false }
+            })
+        })
+    }
+}
+
+#[cfg(unix)]
+fn private(options: &mut OpenOptions) {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    options.mode(0o600);
+}
+
+#[cfg(not(unix))]
+fn private(_options: &mut OpenOptions) {}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs::{self, PathExt};
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    use super::super::*;
+
+    #[test]
+    fn test_tempfile_roundtrip() {
+        let mut file = TempFile::new("test_tempfile_roundtrip").unwrap();
+        let path = file.path().to_path_buf();
+        file.write_all(b"hello").unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        let mut s = String::new();
+        file.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "hello");
+
+        drop(file);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_tempfile_in_tempdir() {
+        let temp_dir = TempDir::new("test_tempfile_in_tempdir").unwrap();
+        let file = TempFile::new_in(temp_dir.path(), "file").unwrap();
+        assert_eq!(file.path().parent(), Some(temp_dir.path()));
+        file.close().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tempfile_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let file = TempFile::new("test_tempfile_is_private").unwrap();
+        let mode = fs::metadata(file.path()).unwrap().permissions().mode();
+        assert_eq!(mode & 0o077, 0);
+    }
+
+    #[test]
+    fn test_tempfile_into_parts() {
+        let file = TempFile::new("test_tempfile_into_parts").unwrap();
+        let (_, path) = file.into_parts();
+        assert!(path.exists());
+        fs::remove_file(&path).unwrap();
+    }
+}
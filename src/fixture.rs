@@ -0,0 +1,145 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use libc;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Once, ONCE_INIT};
+
+use super::TempDir;
+
+type Slot = Arc<Mutex<Option<Arc<PathBuf>>>>;
+
+lazy_static! {
+    static ref FIXTURES: Mutex<HashMap<String, Slot>> = Mutex::new(HashMap::new());
+}
+
+/// Process-wide fixtures that are expensive to build, built at most once and
+/// shared by every test that asks for them.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use tempdir::SharedFixture;
+///
+/// let corpus = SharedFixture::get_or_init("corpus", |dir| {
+///     // Runs once per process, no matter how many tests ask for "corpus".
+///     File::create(&dir.join("big.dat")).map(|_| ())
+/// }).unwrap();
+/// println!("corpus lives in {}", corpus.path().display());
+/// ```
+pub struct SharedFixture;
+
+/// A handle to a fixture built by `SharedFixture`, shared by every test
+/// that asked for it.
+///
+/// The handle only exposes the fixture's path. Nothing stops a test from
+/// writing to the tree, so tests sharing it must treat it as read-only. The
+/// directory is removed when the process exits normally.
+#[derive(Clone, Debug)]
+pub struct Fixture {
+    path: Arc<PathBuf>,
+}
+
+impl Fixture {
+
+    /// The path of the fixture directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl SharedFixture {
+
+    /// Returns the fixture named `key`, building it with `init` if this is
+    /// the first request for it in the process.
+    ///
+    /// `init` is given a fresh temporary directory to populate. Concurrent
+    /// callers asking for the same key wait for the first one to finish. If
+    /// `init` fails or panics, the partially built directory is removed, the
+    /// error or panic is passed on, and the next caller tries again.
+    pub fn get_or_init<F>(key: &str, init: F) -> io::Result<Fixture>
+        where F: FnOnce(&Path) -> io::Result<()>
+    {
+        // A panicking `init` leaves its slot empty, so a poisoned lock
+        // holds nothing half-built.
+        let slot = FIXTURES.lock().unwrap_or_else(|e| e.into_inner())
+                           .entry(key.to_string())
+                           .or_insert_with(|| Arc::new(Mutex::new(None)))
+                           .clone();
+        let mut slot = slot.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(ref path) = *slot {
+            return Ok(Fixture { path: path.clone() });
+        }
+
+        register_atexit();
+        let dir = try!(TempDir::new(&format!("fixture-{}", key)));
+        try!(init(dir.path()));
+        let path = Arc::new(dir.into_inner());
+        *slot = Some(path.clone());
+        Ok(Fixture { path: path })
+    }
+}
+
+fn register_atexit() {
+    static REGISTER: Once = ONCE_INIT;
+    REGISTER.call_once(|| unsafe { libc::atexit(remove_fixtures); });
+}
+
+extern "C" fn remove_fixtures() {
+    // Never block at exit: a thread may have died holding a lock.
+    if let Ok(fixtures) = FIXTURES.try_lock() {
+        for slot in fixtures.values() {
+            if let Ok(slot) = slot.try_lock() {
+                if let Some(ref path) = *slot {
+                    let _ = fs::remove_dir_all(&**path);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs::{File, PathExt};
+    use std::sync::atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
+    use std::thread;
+
+    use super::super::*;
+
+    #[test]
+    fn test_shared_fixture_built_once() {
+        static BUILDS: AtomicUsize = ATOMIC_USIZE_INIT;
+        let init = |dir: &::std::path::Path| {
+            BUILDS.fetch_add(1, Ordering::SeqCst);
+            File::create(&dir.join("data")).map(|_| ())
+        };
+        let a = SharedFixture::get_or_init("test_shared_fixture_built_once", &init).unwrap();
+        let b = SharedFixture::get_or_init("test_shared_fixture_built_once", &init).unwrap();
+        assert_eq!(a.path(), b.path());
+        assert!(a.path().join("data").exists());
+        assert_eq!(BUILDS.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_shared_fixture_retries_after_panic() {
+        let key = "test_shared_fixture_retries_after_panic";
+        let result = thread::spawn(move || {
+            SharedFixture::get_or_init(key, |_| panic!("init failed on purpose"))
+        }).join();
+        assert!(result.is_err());
+        let fixture = SharedFixture::get_or_init(key, |dir| {
+            File::create(&dir.join("data")).map(|_| ())
+        }).unwrap();
+        assert!(fixture.path().join("data").exists());
+    }
+}
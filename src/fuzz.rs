@@ -0,0 +1,177 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Randomized trees for fuzzing code that walks directories.
+
+use rand::{self, Rng, SeedableRng, XorShiftRng};
+use std::cmp;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+use super::{Error, TempDir};
+
+/// The shape of a randomized tree of directories and files.
+///
+/// The tree is generated from a seed, which `build` returns: building again
+/// with the same bounds and `seed` set to it reproduces the tree exactly,
+/// names and contents included.
+///
+/// # Examples
+///
+/// ```no_run
+/// use tempdir::{RandomTree, TempDir};
+///
+/// let dir = TempDir::new("fuzz").unwrap();
+/// let seed = dir.random_tree(RandomTree::new().max_depth(3).max_entries(50)).unwrap();
+/// println!("tree generated from seed {:?}", seed);
+/// ```
+#[derive(Clone, Debug)]
+pub struct RandomTree {
+    max_depth: usize,
+    max_entries: usize,
+    max_file_size: usize,
+    seed: Option<[u32; 4]>,
+}
+
+impl RandomTree {
+
+    /// Creates a tree of at most 4 levels of directories, 100 entries and
+    /// files of 4096 bytes, from a random seed.
+    pub fn new() -> RandomTree {
+        RandomTree { max_depth: 4, max_entries: 100, max_file_size: 4096, seed: None }
+    }
+
+    /// Sets how many levels of directories may be nested below the root. At
+    /// 0, only files are created.
+    pub fn max_depth(&mut self, depth: usize) -> &mut RandomTree {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Sets how many entries, directories and files together, may be
+    /// created in the whole tree.
+    pub fn max_entries(&mut self, entries: usize) -> &mut RandomTree {
+        self.max_entries = entries;
+        self
+    }
+
+    /// Sets the largest size in bytes of each file.
+    pub fn max_file_size(&mut self, bytes: usize) -> &mut RandomTree {
+        self.max_file_size = bytes;
+        self
+    }
+
+    /// Sets the seed the tree is generated from, to reproduce a tree
+    /// reported by an earlier `build`. The seed must not be all zeroes, or
+    /// `build` fails with `InvalidInput`.
+    pub fn seed(&mut self, seed: [u32; 4]) -> &mut RandomTree {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Generates the tree inside of the existing directory `dir`, returning
+    /// the seed it was generated from.
+    pub fn build(&self, dir: &Path) -> io::Result<[u32; 4]> {
+        let seed = match self.seed {
+            Some(seed) if seed == [0; 4] => return Err(Error::ZeroSeed.into_io_error()),
+            Some(seed) => seed,
+            None => random_seed(),
+        };
+        let mut rng: XorShiftRng = SeedableRng::from_seed(seed);
+        let mut remaining = self.max_entries;
+        try!(self.fill(&mut rng, dir, 0, &mut remaining));
+        Ok(seed)
+    }
+
+    fn fill(&self, rng: &mut XorShiftRng, dir: &Path, depth: usize,
+            remaining: &mut usize) -> io::Result<()> {
+        let count = rng.gen_range(0, cmp::min(*remaining, 8) + 1);
+        for i in 0..count {
+            if *remaining == 0 {
+                break;
+            }
+            *remaining -= 1;
+            let len = rng.gen_range(1, 9);
+            let name: String = rng.gen_ascii_chars().take(len).collect();
+            let path = dir.join(&format!("{}-{}", i, name));
+            if depth < self.max_depth && rng.gen() {
+                try!(fs::create_dir(&path));
+                try!(self.fill(rng, &path, depth + 1, remaining));
+            } else {
+                let mut contents = vec![0u8; rng.gen_range(0, self.max_file_size + 1)];
+                rng.fill_bytes(&mut contents);
+                try!(try!(File::create(&path)).write_all(&contents));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns a random seed that `XorShiftRng` accepts.
+fn random_seed() -> [u32; 4] {
+    let mut rng = rand::thread_rng();
+    loop {
+        let seed = [rng.gen(), rng.gen(), rng.gen(), rng.gen()];
+        if seed != [0; 4] {
+            return seed;
+        }
+    }
+}
+
+impl TempDir {
+
+    /// Generates `tree` inside of the directory, returning the seed it was
+    /// generated from.
+    pub fn random_tree(&self, tree: &RandomTree) -> io::Result<[u32; 4]> {
+        tree.build(self.path())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs::File;
+    use std::io::{self, Read};
+    use std::path::{Path, PathBuf};
+
+    use super::super::*;
+    use walk::{self, walk};
+
+    fn snapshot(root: &Path) -> Vec<(PathBuf, Vec<u8>)> {
+        walk(root).unwrap().into_iter().map(|entry| {
+            let mut contents = Vec::new();
+            if entry.metadata.is_file() {
+                File::open(&entry.path).unwrap().read_to_end(&mut contents).unwrap();
+            }
+            (walk::relative(root, &entry.path).to_path_buf(), contents)
+        }).collect()
+    }
+
+    #[test]
+    fn test_random_tree_reproducible() {
+        let mut tree = RandomTree::new();
+        tree.max_depth(2).max_entries(30).max_file_size(64);
+        let a = TempDir::new("test_random_tree_reproducible").unwrap();
+        let seed = a.random_tree(&tree).unwrap();
+        let b = TempDir::new("test_random_tree_reproducible").unwrap();
+        assert_eq!(b.random_tree(tree.seed(seed)).unwrap(), seed);
+
+        let entries = snapshot(a.path());
+        assert_eq!(entries, snapshot(b.path()));
+        assert!(entries.len() <= 30);
+        assert!(entries.iter().all(|&(ref p, ref c)| p.components().count() <= 3 && c.len() <= 64));
+    }
+
+    #[test]
+    fn test_random_tree_zero_seed() {
+        let dir = TempDir::new("test_random_tree_zero_seed").unwrap();
+        let err = dir.random_tree(RandomTree::new().seed([0; 4])).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
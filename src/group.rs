@@ -0,0 +1,199 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::io;
+use std::path::Path;
+
+use super::{Error, TempDir, TempFile};
+
+/// Identifies a member of a `CleanupGroup`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MemberId(usize);
+
+enum Resource {
+    Dir(TempDir),
+    File(TempFile),
+}
+
+impl Resource {
+    fn path(&self) -> &Path {
+        match *self {
+            Resource::Dir(ref dir) => dir.path(),
+            Resource::File(ref file) => file.path(),
+        }
+    }
+
+    fn close(self) -> io::Result<()> {
+        match self {
+            Resource::Dir(dir) => Ok(try!(dir.close())),
+            Resource::File(file) => file.close(),
+        }
+    }
+}
+
+struct Member {
+    resource: Option<Resource>,
+    /// The members this one has to be removed before.
+    before: Vec<usize>,
+}
+
+/// A set of temporary directories and files which are removed together, in
+/// an order that respects the dependencies declared between them.
+///
+/// If a member depends on another, e.g. because it holds a socket, a lock
+/// file or a mount point referring to it, it is removed first. Members
+/// without dependencies between them are removed in the reverse of the order
+/// in which they were added, like local variables.
+///
+/// # Examples
+///
+/// ```no_run
+/// use tempdir::{CleanupGroup, TempDir};
+///
+/// let mut group = CleanupGroup::new();
+/// let data = group.add_dir(TempDir::new("data").unwrap());
+/// let sockets = group.add_dir(TempDir::new("sockets").unwrap());
+/// // The sockets refer to the data, so they have to go first.
+/// group.depends_on(sockets, data).unwrap();
+/// group.close().unwrap();
+/// ```
+pub struct CleanupGroup {
+    members: Vec<Member>,
+}
+
+impl CleanupGroup {
+
+    /// Creates an empty group.
+    pub fn new() -> CleanupGroup {
+        CleanupGroup { members: Vec::new() }
+    }
+
+    /// Adds `dir` to the group, which takes over its removal.
+    pub fn add_dir(&mut self, dir: TempDir) -> MemberId {
+        self.add(Resource::Dir(dir))
+    }
+
+    /// Adds `file` to the group, which takes over its removal.
+    pub fn add_file(&mut self, file: TempFile) -> MemberId {
+        self.add(Resource::File(file))
+    }
+
+    fn add(&mut self, resource: Resource) -> MemberId {
+        self.members.push(Member { resource: Some(resource), before: Vec::new() });
+        MemberId(self.members.len() - 1)
+    }
+
+    /// Declares that `member` depends on `dependency`, so that it is removed
+    /// first. Fails with `InvalidInput` if `dependency` already depends on
+    /// `member`, directly or not.
+    pub fn depends_on(&mut self, member: MemberId, dependency: MemberId) -> io::Result<()> {
+        if member == dependency || self.reaches(dependency.0, member.0) {
+            return Err(Error::DependencyCycle.into_io_error());
+        }
+        self.members[member.0].before.push(dependency.0);
+        Ok(())
+    }
+
+    /// Returns whether `from` has to be removed before `to`.
+    fn reaches(&self, from: usize, to: usize) -> bool {
+        let mut stack = vec![from];
+        let mut seen = vec![false; self.members.len()];
+        while let Some(i) = stack.pop() {
+            if i == to {
+                return true;
+            }
+            if !seen[i] {
+                seen[i] = true;
+                stack.extend(self.members[i].before.iter().cloned());
+            }
+        }
+        false
+    }
+
+    /// Returns the path of `member`.
+    pub fn path(&self, member: MemberId) -> &Path {
+        self.members[member.0].resource.as_ref().unwrap().path()
+    }
+
+    /// Returns the directory `member`, or `None` if it is a file.
+    pub fn dir(&self, member: MemberId) -> Option<&TempDir> {
+        match self.members[member.0].resource {
+            Some(Resource::Dir(ref dir)) => Some(dir),
+            _ => None,
+        }
+    }
+
+    /// Removes every member in dependency order. All members are removed
+    /// even if some of them fail; the first error is returned.
+    pub fn close(mut self) -> io::Result<()> {
+        self.remove_all()
+    }
+
+    fn remove_all(&mut self) -> io::Result<()> {
+        let mut result = Ok(());
+        for i in (0..self.members.len()).rev() {
+            self.remove(i, &mut result);
+        }
+        result
+    }
+
+    /// Removes member `i`, after every member that depends on it.
+    fn remove(&mut self, i: usize, result: &mut io::Result<()>) {
+        let resource = match self.members[i].resource.take() {
+            Some(resource) => resource,
+            None => return,
+        };
+        for j in (0..self.members.len()).rev() {
+            if self.members[j].before.contains(&i) {
+                self.remove(j, result);
+            }
+        }
+        let r = resource.close();
+        if result.is_ok() {
+            *result = r;
+        }
+    }
+}
+
+impl Drop for CleanupGroup {
+    fn drop(&mut self) {
+        let _ = self.remove_all();
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs::PathExt;
+
+    use super::super::*;
+
+    #[test]
+    fn test_cleanup_group_order() {
+        let outer = TempDir::new("test_cleanup_group_order").unwrap();
+        let inner = TempDir::new_in(outer.path(), "inner").unwrap();
+        let file = TempFile::new_in(inner.path(), "file").unwrap();
+        let paths = vec![outer.path().to_path_buf(), inner.path().to_path_buf(),
+                         file.path().to_path_buf()];
+
+        // Added innermost first, so that the default order would remove the
+        // outer directory first and make closing the others fail.
+        let mut group = CleanupGroup::new();
+        let file = group.add_file(file);
+        let inner = group.add_dir(inner);
+        let outer = group.add_dir(outer);
+        group.depends_on(inner, outer).unwrap();
+        group.depends_on(file, inner).unwrap();
+        assert!(group.depends_on(outer, file).is_err());
+        assert!(group.depends_on(outer, outer).is_err());
+        assert!(group.dir(inner).is_some() && group.dir(file).is_none());
+
+        group.close().unwrap();
+        assert!(paths.iter().all(|p| !p.exists()));
+    }
+}
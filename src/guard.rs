@@ -0,0 +1,152 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use identity::Identity;
+use super::TempDir;
+
+lazy_static! {
+    /// The guards still alive, by the path and identity of their directory,
+    /// each with the record it sets once its owner removes the directory.
+    static ref GUARDS: Mutex<HashMap<(PathBuf, Identity), Arc<AtomicBool>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Returns the removal record of the live guard for the directory at `path`
+/// with `identity`, if there is one, for a `TempDir` adopting it.
+pub fn adopt(path: &Path, identity: &Identity) -> Option<Arc<AtomicBool>> {
+    GUARDS.lock().unwrap().get(&(path.to_path_buf(), *identity)).cloned()
+}
+
+/// Returns whether the owner removed the directory behind `record`, in which
+/// case cleanup by this crate must leave its path alone.
+pub fn removed_by_owner(record: &Option<Arc<AtomicBool>>) -> bool {
+    record.as_ref().map_or(false, |record| record.load(Ordering::SeqCst))
+}
+
+/// The path of a temporary directory released with
+/// `TempDir::into_inner_guarded`.
+///
+/// Like `into_inner`, the directory is no longer removed automatically. The
+/// guard records when its owner removes it, so that a `TempDir` which adopted
+/// the same directory through `TempDir::from_path` while the guard was alive
+/// skips it instead of racing the owner. Directories adopted once the guard
+/// is gone, or created without an identity (such as in minimal syscall
+/// mode), are cleaned up as usual.
+pub struct TempPathGuard {
+    path: PathBuf,
+    identity: Option<Identity>,
+    record: Arc<AtomicBool>,
+}
+
+impl TempPathGuard {
+
+    /// Access the path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns whether the directory was removed through this guard.
+    pub fn is_removed(&self) -> bool {
+        self.record.load(Ordering::SeqCst)
+    }
+
+    /// Removes the directory and everything inside of it, and records that
+    /// it was removed.
+    pub fn remove(self) -> io::Result<()> {
+        try!(fs::remove_dir_all(&self.path));
+        self.mark_removed();
+        Ok(())
+    }
+
+    /// Records that the owner removed the directory by other means.
+    pub fn mark_removed(&self) {
+        self.record.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Drop for TempPathGuard {
+    fn drop(&mut self) {
+        let identity = match self.identity {
+            Some(identity) => identity,
+            None => return,
+        };
+        let mut guards = GUARDS.lock().unwrap_or_else(|e| e.into_inner());
+        let key = (self.path.clone(), identity);
+        // Another guard may have taken over the entry since.
+        let ours = guards.get(&key).map_or(false, |record| {
+            &**record as *const AtomicBool == &*self.record as *const AtomicBool
+        });
+        if ours {
+            guards.remove(&key);
+        }
+    }
+}
+
+impl TempDir {
+
+    /// Unwrap the path like `into_inner`, returning a guard through which
+    /// removal of the directory is recorded.
+    pub fn into_inner_guarded(self) -> TempPathGuard {
+        let identity = self.identity;
+        let guard = TempPathGuard {
+            path: self.into_inner(),
+            identity: identity,
+            record: Arc::new(AtomicBool::new(false)),
+        };
+        if let Some(identity) = identity {
+            GUARDS.lock().unwrap().insert((guard.path.clone(), identity), guard.record.clone());
+        }
+        guard
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs::{self, PathExt};
+
+    use super::super::*;
+    use super::GUARDS;
+
+    #[test]
+    fn test_into_inner_guarded() {
+        let guard = TempDir::new("test_into_inner_guarded").unwrap().into_inner_guarded();
+        let removed = guard.path().to_path_buf();
+        let late = TempDir::from_path(&removed);
+        assert!(!guard.is_removed());
+        guard.remove().unwrap();
+        assert!(!GUARDS.lock().unwrap().keys().any(|&(ref path, _)| *path == removed));
+
+        // A late cleanup of the same directory is skipped rather than
+        // failing.
+        late.close().unwrap();
+
+        // A fresh directory, which may well reuse the inode, is removed.
+        let fresh = TempDir::new("test_into_inner_guarded").unwrap();
+        let path = fresh.path().to_path_buf();
+        fresh.close().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_mark_removed() {
+        let guard = TempDir::new("test_mark_removed").unwrap().into_inner_guarded();
+        let late = TempDir::from_path(guard.path());
+        fs::remove_dir(guard.path()).unwrap();
+        guard.mark_removed();
+        assert!(guard.is_removed());
+        late.close().unwrap();
+    }
+}
@@ -0,0 +1,401 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Checks that the path a `TempDir` is about to remove still refers to the
+//! directory it created, and removes it without being redirected elsewhere.
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use Error;
+
+/// Something suspicious about a directory that is about to be removed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Anomaly {
+    /// The path is no longer a directory, e.g. it was replaced by a symbolic
+    /// link.
+    NotADirectory,
+    /// The directory is owned by somebody other than its creator.
+    Owner { expected: u32, found: u32 },
+    /// The path refers to a different directory than the one created.
+    Replaced,
+    /// Something has been mounted on the path.
+    MountPoint,
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Anomaly::NotADirectory => write!(f, "path is no longer a directory"),
+            Anomaly::Owner { expected, found } =>
+                write!(f, "directory is owned by uid {}, expected {}", found, expected),
+            Anomaly::Replaced => write!(f, "directory has been replaced"),
+            Anomaly::MountPoint => write!(f, "a filesystem is mounted on the directory"),
+        }
+    }
+}
+
+/// The identity of a directory as recorded at creation.
+#[cfg(unix)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Identity {
+    dev: u64,
+    ino: u64,
+    uid: u32,
+}
+
+/// The identity of a directory as recorded at creation.
+#[cfg(windows)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Identity {
+    volume: u32,
+    index: u64,
+}
+
+/// The identity of a directory as recorded at creation.
+#[cfg(not(any(unix, windows)))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Identity;
+
+#[cfg(unix)]
+impl Identity {
+
+    /// Records the identity of the directory at `path`.
+    pub fn of(path: &Path) -> io::Result<Identity> {
+        use std::fs;
+        use std::os::unix::fs::MetadataExt;
+
+        let metadata = try!(fs::symlink_metadata(path));
+        Ok(Identity { dev: metadata.dev() as u64, ino: metadata.ino() as u64, uid: metadata.uid() })
+    }
+
+    /// Checks that `path` still refers to this directory.
+    pub fn check(&self, path: &Path) -> io::Result<Option<Anomaly>> {
+        use std::fs;
+
+        Ok(self.anomaly(&try!(fs::symlink_metadata(path))))
+    }
+
+    /// Checks that the open `dir` is this directory.
+    pub fn check_open(&self, dir: &::std::fs::File) -> io::Result<Option<Anomaly>> {
+        Ok(self.anomaly(&try!(dir.metadata())))
+    }
+
+    /// Compares the metadata of a directory against this identity.
+    fn anomaly(&self, metadata: &::std::fs::Metadata) -> Option<Anomaly> {
+        use std::os::unix::fs::MetadataExt;
+
+        if !metadata.is_dir() {
+            return Some(Anomaly::NotADirectory);
+        }
+        if metadata.uid() != self.uid {
+            return Some(Anomaly::Owner { expected: self.uid, found: metadata.uid() });
+        }
+        if metadata.dev() as u64 != self.dev {
+            return Some(Anomaly::MountPoint);
+        }
+        if metadata.ino() as u64 != self.ino {
+            return Some(Anomaly::Replaced);
+        }
+        None
+    }
+}
+
+#[cfg(windows)]
+impl Identity {
+
+    /// Records the volume serial number and file index of the directory at
+    /// `path`. A symbolic link or junction is not followed, so it has an
+    /// identity of its own.
+    pub fn of(path: &Path) -> io::Result<Identity> {
+        use std::fs::OpenOptions;
+        use std::os::windows::fs::OpenOptionsExt;
+        use std::os::windows::io::{AsRawHandle, RawHandle};
+
+        #[repr(C)]
+        struct ByHandleFileInformation {
+            attributes: u32,
+            creation_time: [u32; 2],
+            access_time: [u32; 2],
+            write_time: [u32; 2],
+            volume: u32,
+            size_high: u32,
+            size_low: u32,
+            links: u32,
+            index_high: u32,
+            index_low: u32,
+        }
+
+        extern "system" {
+            fn GetFileInformationByHandle(file: RawHandle,
+                                          info: *mut ByHandleFileInformation) -> i32;
+        }
+
+        const FILE_READ_ATTRIBUTES: u32 = 0x80;
+        const FILE_FLAG_OPEN_REPARSE_POINT: u32 = 0x0020_0000;
+        const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+
+        let file = try!(OpenOptions::new()
+                            .access_mode(FILE_READ_ATTRIBUTES)
+                            .custom_flags(FILE_FLAG_BACKUP_SEMANTICS | FILE_FLAG_OPEN_REPARSE_POINT)
+                            .open(path));
+        let mut info: ByHandleFileInformation = unsafe { ::std::mem::zeroed() };
+        if unsafe { GetFileInformationByHandle(file.as_raw_handle(), &mut info) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Identity {
+            volume: info.volume,
+            index: (info.index_high as u64) << 32 | info.index_low as u64,
+        })
+    }
+
+    /// Checks that `path` still refers to this directory.
+    pub fn check(&self, path: &Path) -> io::Result<Option<Anomaly>> {
+        use std::fs;
+
+        if !try!(fs::symlink_metadata(path)).is_dir() {
+            return Ok(Some(Anomaly::NotADirectory));
+        }
+        let found = try!(Identity::of(path));
+        if found.volume != self.volume {
+            return Ok(Some(Anomaly::MountPoint));
+        }
+        if found.index != self.index {
+            return Ok(Some(Anomaly::Replaced));
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+impl Identity {
+
+    /// Records the identity of the directory at `path`.
+    pub fn of(path: &Path) -> io::Result<Identity> {
+        use std::fs;
+
+        try!(fs::symlink_metadata(path));
+        Ok(Identity)
+    }
+
+    /// Checks that `path` still refers to this directory. Only the file type
+    /// is checked on this platform.
+    pub fn check(&self, path: &Path) -> io::Result<Option<Anomaly>> {
+        use std::fs;
+
+        let metadata = try!(fs::symlink_metadata(path));
+        if !metadata.is_dir() {
+            return Ok(Some(Anomaly::NotADirectory));
+        }
+        Ok(None)
+    }
+}
+
+/// Returns the error reported when `path` is no longer the directory that
+/// was created, and nothing was removed.
+pub fn swapped(path: &Path, anomaly: &Anomaly) -> io::Error {
+    Error::Swapped(format!("{}: {}", path.display(), anomaly)).into_io_error()
+}
+
+/// Recursively removes the directory at `path` after checking that it is
+/// still the directory identified by `identity`.
+///
+/// The directory is opened once and its contents are removed relative to
+/// that handle, so renaming it or swapping it (or anything inside of it) for a
+/// symbolic link cannot redirect the removal into another tree. Symbolic
+/// links are removed, never followed, and file systems mounted inside of the
+/// directory are not descended into.
+#[cfg(unix)]
+pub fn remove_dir_all(path: &Path, identity: &Identity) -> io::Result<()> {
+    use std::ffi::{AsOsStr, CString};
+    use std::fs;
+    use std::os::unix::OsStrExt;
+
+    let c_path = try!(CString::new(path.as_os_str().as_bytes())
+                          .map_err(|_| Error::nul_byte(path)));
+    let root = try!(hardened::open_dir(::libc::AT_FDCWD, &c_path));
+    if let Some(anomaly) = identity.anomaly(&try!(root.metadata())) {
+        return Err(swapped(path, &anomaly));
+    }
+    try!(hardened::remove_contents(&root, identity.dev, path));
+    drop(root);
+    // Only an empty directory can be removed here, so a swap at this point
+    // cannot destroy anything.
+    fs::remove_dir(path)
+}
+
+/// Recursively removes the directory at `path` after checking that it is
+/// still the directory identified by `identity`. On this platform the check
+/// and the removal are separate steps.
+#[cfg(not(unix))]
+pub fn remove_dir_all(path: &Path, identity: &Identity) -> io::Result<()> {
+    use std::fs;
+
+    if let Some(anomaly) = try!(identity.check(path)) {
+        return Err(swapped(path, &anomaly));
+    }
+    fs::remove_dir_all(path)
+}
+
+#[cfg(unix)]
+mod hardened {
+    use libc;
+    use std::ffi::{CStr, CString, OsStr};
+    use std::fs::File;
+    use std::io;
+    use std::mem;
+    use std::os::unix::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+    use std::path::Path;
+
+    use super::{Anomaly, swapped};
+
+    /// Opens the directory `name` relative to `parent`, refusing to follow a
+    /// symbolic link.
+    pub fn open_dir(parent: libc::c_int, name: &CStr) -> io::Result<File> {
+        let fd = unsafe {
+            libc::openat(parent, name.as_ptr(),
+                         libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC)
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+
+    /// Returns the names of the entries of `dir`, except `.` and `..`.
+    fn read_names(dir: &File) -> io::Result<Vec<CString>> {
+        let fd = unsafe { libc::dup(dir.as_raw_fd()) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let stream = unsafe { libc::fdopendir(fd) };
+        if stream.is_null() {
+            let err = io::Error::last_os_error();
+            unsafe { libc::close(fd); }
+            return Err(err);
+        }
+        let mut names = Vec::new();
+        loop {
+            let entry = unsafe { libc::readdir(stream) };
+            if entry.is_null() {
+                break;
+            }
+            let name = unsafe { CStr::from_ptr((*entry).d_name.as_ptr()) };
+            if name.to_bytes() != b"." && name.to_bytes() != b".." {
+                names.push(name.to_owned());
+            }
+        }
+        unsafe { libc::closedir(stream); }
+        Ok(names)
+    }
+
+    /// Removes everything inside of `dir`, whose path is `path` and which is
+    /// on device `dev`.
+    pub fn remove_contents(dir: &File, dev: u64, path: &Path) -> io::Result<()> {
+        let fd = dir.as_raw_fd();
+        for name in try!(read_names(dir)).into_iter() {
+            let mut stat: libc::stat = unsafe { mem::zeroed() };
+            let r = unsafe {
+                libc::fstatat(fd, name.as_ptr(), &mut stat, libc::AT_SYMLINK_NOFOLLOW)
+            };
+            if r != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let flags = if stat.st_mode & libc::S_IFMT == libc::S_IFDIR {
+                let sub = try!(open_dir(fd, &name));
+                let sub_path = path.join(OsStr::from_bytes(name.to_bytes()));
+                if try!(sub.metadata()).dev() as u64 != dev {
+                    return Err(swapped(&sub_path, &Anomaly::MountPoint));
+                }
+                try!(remove_contents(&sub, dev, &sub_path));
+                libc::AT_REMOVEDIR
+            } else {
+                0
+            };
+            if unsafe { libc::unlinkat(fd, name.as_ptr(), flags) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Moves the suspicious directory at `path` into `quarantine` instead of
+/// removing it, and returns the error describing what happened.
+pub fn quarantine(path: &Path, quarantine: &Path, anomaly: &Anomaly) -> io::Error {
+    use rand::{self, Rng};
+    use std::fs;
+    use std::io::Write;
+
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("tempdir").to_string();
+    let suffix: String = rand::thread_rng().gen_ascii_chars().take(8).collect();
+    let dest = quarantine.join(&format!("{}.{}", name, suffix));
+    let detail = match fs::create_dir_all(quarantine).and_then(|_| fs::rename(path, &dest)) {
+        Ok(()) => format!("{}: {}; moved to {}", path.display(), anomaly, dest.display()),
+        Err(e) => format!("{}: {}; could not be moved to {}: {}",
+                          path.display(), anomaly, dest.display(), e),
+    };
+    let _ = writeln!(&mut io::stderr(), "tempdir: quarantined {}", detail);
+    Error::Quarantined(detail).into_io_error()
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs::{self, PathExt};
+
+    use super::super::*;
+
+    #[test]
+    fn test_quarantine_symlink_swap() {
+        let quarantine = TempDir::new("test_quarantine").unwrap();
+        let victim = TempDir::new("test_quarantine_victim").unwrap();
+        let mut temp_dir = TempDir::new("test_quarantine_symlink_swap").unwrap();
+        temp_dir.set_quarantine(quarantine.path());
+        let path = temp_dir.path().to_path_buf();
+
+        fs::remove_dir(&path).unwrap();
+        fs::soft_link(victim.path(), &path).unwrap();
+
+        assert!(temp_dir.close().is_err());
+        assert!(victim.path().exists());
+        assert_eq!(fs::read_dir(quarantine.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_swap_refused() {
+        let victim = TempDir::new("test_symlink_swap_victim").unwrap();
+        fs::File::create(&victim.path().join("precious")).unwrap();
+        let temp_dir = TempDir::new("test_symlink_swap_refused").unwrap();
+        let path = temp_dir.path().to_path_buf();
+
+        fs::remove_dir(&path).unwrap();
+        fs::soft_link(victim.path(), &path).unwrap();
+
+        assert!(temp_dir.close().is_err());
+        assert!(victim.path().join("precious").exists());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_nested_symlink_not_followed() {
+        let victim = TempDir::new("test_nested_symlink_victim").unwrap();
+        fs::File::create(&victim.path().join("precious")).unwrap();
+        let temp_dir = TempDir::new("test_nested_symlink_not_followed").unwrap();
+        fs::create_dir(&temp_dir.path().join("a")).unwrap();
+        fs::soft_link(victim.path(), &temp_dir.path().join("a/link")).unwrap();
+
+        temp_dir.close().unwrap();
+        assert!(victim.path().join("precious").exists());
+    }
+}
@@ -0,0 +1,313 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fs::{self, FileType, Metadata};
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use super::TempDir;
+use cleanup::Hooks;
+use walk;
+
+/// An entry removed by `CloseIter`.
+#[derive(Clone, Debug)]
+pub struct Removed {
+    /// The full path the entry had.
+    pub path: PathBuf,
+    /// The type of the entry; symbolic links are reported as such.
+    pub file_type: FileType,
+    /// The size of the entry in bytes, as reported by its metadata.
+    pub size: u64,
+}
+
+impl Removed {
+    fn new(path: PathBuf, metadata: &Metadata) -> Removed {
+        Removed { path: path, file_type: metadata.file_type(), size: metadata.len() }
+    }
+}
+
+/// A directory whose entries are being removed.
+struct Frame {
+    dir: PathBuf,
+    /// The entries not yet visited, sorted in reverse so that they are
+    /// popped in order.
+    children: Vec<PathBuf>,
+    /// Whether something inside of the directory was kept, so that the
+    /// directory itself cannot be removed.
+    kept: bool,
+}
+
+impl Frame {
+    fn new(dir: PathBuf) -> io::Result<Frame> {
+        let mut children = Vec::new();
+        for child in try!(fs::read_dir(&dir)) {
+            children.push(try!(child).path());
+        }
+        children.sort_by(|a, b| b.cmp(a));
+        Ok(Frame { dir: dir, children: children, kept: false })
+    }
+}
+
+/// An iterator which removes a temporary directory one entry at a time,
+/// returned by `TempDir::close_iter`.
+///
+/// Each call to `next` removes one entry and yields it, or the error that
+/// prevented its removal. Entries are removed in the same order as with
+/// `Cleanup::Sorted`, ending with the directory itself. If the iterator is
+/// dropped early, the remaining entries are removed without being reported.
+pub struct CloseIter {
+    root: PathBuf,
+    hooks: Hooks,
+    stack: Vec<Frame>,
+    error: Option<io::Error>,
+}
+
+impl CloseIter {
+
+    /// Records that the innermost directory being removed has to stay,
+    /// because something inside of it could not be removed.
+    fn keep_current(&mut self) {
+        if let Some(frame) = self.stack.last_mut() {
+            frame.kept = true;
+        }
+    }
+}
+
+impl Iterator for CloseIter {
+    type Item = io::Result<Removed>;
+
+    fn next(&mut self) -> Option<io::Result<Removed>> {
+        if let Some(e) = self.error.take() {
+            return Some(Err(e));
+        }
+        loop {
+            let child = match self.stack.last_mut() {
+                Some(frame) => frame.children.pop(),
+                None => return None,
+            };
+            let child = match child {
+                Some(child) => child,
+                None => {
+                    let frame = self.stack.pop().unwrap();
+                    if frame.kept {
+                        self.keep_current();
+                        continue;
+                    }
+                    let result = fs::symlink_metadata(&frame.dir).and_then(|metadata| {
+                        try!(fs::remove_dir(&frame.dir));
+                        Ok(metadata)
+                    });
+                    return Some(match result {
+                        Ok(metadata) => {
+                            self.hooks.removed(&frame.dir);
+                            Ok(Removed::new(frame.dir, &metadata))
+                        }
+                        Err(e) => {
+                            self.keep_current();
+                            Err(e)
+                        }
+                    });
+                }
+            };
+            if self.hooks.keep(walk::relative(&self.root, &child)) {
+                self.keep_current();
+                continue;
+            }
+            let result = fs::symlink_metadata(&child).and_then(|metadata| {
+                if metadata.is_dir() {
+                    Ok(Err(try!(Frame::new(child.clone()))))
+                } else {
+                    try!(fs::remove_file(&child));
+                    Ok(Ok(metadata))
+                }
+            });
+            match result {
+                Ok(Err(frame)) => self.stack.push(frame),
+                Ok(Ok(metadata)) => {
+                    self.hooks.removed(&child);
+                    return Some(Ok(Removed::new(child, &metadata)));
+                }
+                Err(e) => {
+                    self.keep_current();
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for CloseIter {
+    fn drop(&mut self) {
+        for _ in self.by_ref() {}
+    }
+}
+
+/// A temporary directory being removed a slice of time at a time, returned
+/// by `TempDir::close_incremental`.
+///
+/// Each call to `step` removes entries until its time budget runs out, and
+/// the next call resumes where it stopped, so that the removal of a huge
+/// tree can be spread over the frames of a game loop or the ticks of a
+/// service. If the handle is dropped before removal has finished, the
+/// remaining entries are removed right away, without a budget.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use tempdir::TempDir;
+///
+/// let mut cleanup = TempDir::new("scratch").unwrap().close_incremental();
+/// while !cleanup.step(Duration::from_millis(2)).unwrap() {
+///     // Render a frame.
+/// }
+/// ```
+pub struct CleanupHandle {
+    iter: CloseIter,
+    removed: u64,
+    done: bool,
+}
+
+impl CleanupHandle {
+
+    /// Removes entries for at most about `budget`, returning whether
+    /// removal has finished. At least one entry is removed per call, so
+    /// every call makes progress even with a zero budget.
+    ///
+    /// An entry that cannot be removed ends the step with its error; the
+    /// next step carries on with the other entries, leaving the directories
+    /// containing the failed entry in place.
+    pub fn step(&mut self, budget: Duration) -> io::Result<bool> {
+        let start = Instant::now();
+        while !self.done {
+            match self.iter.next() {
+                Some(Ok(_)) => self.removed += 1,
+                Some(Err(e)) => return Err(e),
+                None => self.done = true,
+            }
+            if start.elapsed() >= budget {
+                break;
+            }
+        }
+        Ok(self.done)
+    }
+
+    /// Returns whether removal has finished.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Returns how many entries have been removed so far.
+    pub fn removed(&self) -> u64 {
+        self.removed
+    }
+
+    /// Returns the path of the directory being removed.
+    pub fn path(&self) -> &Path {
+        &self.iter.root
+    }
+}
+
+impl TempDir {
+
+    /// Close the temporary directory like `close`, but remove it one entry
+    /// at a time as the returned iterator is advanced, yielding each removed
+    /// entry.
+    ///
+    /// This allows reporting progress while a large directory is removed,
+    /// without walking it twice. The cleanup filter and `on_remove` callback
+    /// are honored; the cleanup strategy is not. If the retention policy
+    /// keeps the directory, the iterator yields nothing.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use tempdir::TempDir;
+    ///
+    /// let temp_dir = TempDir::new("build").unwrap();
+    /// for entry in temp_dir.close_iter() {
+    ///     let entry = entry.unwrap();
+    ///     println!("removed {} ({} bytes)", entry.path.display(), entry.size);
+    /// }
+    /// ```
+    pub fn close_iter(mut self) -> CloseIter {
+        let path = self.detach();
+        let hooks = mem::replace(&mut self.hooks, Hooks::new());
+        let (stack, error) = match self.should_remove(&path).and_then(|remove| {
+            if remove { Frame::new(path.clone()).map(Some) } else { Ok(None) }
+        }) {
+            Ok(frame) => (frame.into_iter().collect(), None),
+            Err(e) => (Vec::new(), Some(e)),
+        };
+        CloseIter { root: path, hooks: hooks, stack: stack, error: error }
+    }
+
+    /// Close the temporary directory like `close`, but remove it in time
+    /// slices with `CleanupHandle::step`. The same rules as for `close_iter`
+    /// apply.
+    pub fn close_incremental(self) -> CleanupHandle {
+        CleanupHandle { iter: self.close_iter(), removed: 0, done: false }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs::{self, File, PathExt};
+    use std::io::Write;
+    use std::time::Duration;
+
+    use super::super::*;
+
+    #[test]
+    fn test_close_iter() {
+        let temp_dir = TempDir::new("test_close_iter").unwrap();
+        let path = temp_dir.path().to_path_buf();
+        fs::create_dir(&path.join("a")).unwrap();
+        File::create(&path.join("a/x")).unwrap().write_all(b"12345").unwrap();
+        File::create(&path.join("b")).unwrap();
+
+        let removed: Vec<Removed> = temp_dir.close_iter().map(|r| r.unwrap()).collect();
+        let paths: Vec<_> = removed.iter().map(|r| r.path.clone()).collect();
+        assert_eq!(paths, vec![path.join("a/x"), path.join("a"), path.join("b"), path.clone()]);
+        assert_eq!(removed[0].size, 5);
+        assert!(removed[1].file_type.is_dir());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_close_iter_dropped_early() {
+        let temp_dir = TempDir::new("test_close_iter_dropped_early").unwrap();
+        let path = temp_dir.path().to_path_buf();
+        File::create(&path.join("a")).unwrap();
+        File::create(&path.join("b")).unwrap();
+
+        assert_eq!(temp_dir.close_iter().take(1).count(), 1);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_close_incremental() {
+        let temp_dir = TempDir::new("test_close_incremental").unwrap();
+        let path = temp_dir.path().to_path_buf();
+        for i in 0..10 {
+            File::create(&path.join(&format!("{}", i))).unwrap();
+        }
+
+        let mut cleanup = temp_dir.close_incremental();
+        assert!(!cleanup.step(Duration::from_millis(0)).unwrap());
+        assert_eq!(cleanup.removed(), 1);
+        assert!(path.exists());
+        while !cleanup.step(Duration::from_millis(0)).unwrap() {}
+        assert!(cleanup.is_done());
+        assert_eq!(cleanup.removed(), 11);
+        assert!(!path.exists());
+    }
+}
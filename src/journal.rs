@@ -0,0 +1,241 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The opt-in journal of operations performed through a `TempDir`'s
+//! helpers.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{Clock, TempDir};
+
+/// The operations recorded in a journal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// A file was created or truncated with `create_file` or `write`.
+    Create,
+    /// Contents were written with `write`.
+    Write,
+    /// Directories were created with `mkdir_all`.
+    CreateDir,
+    /// A modification time was set with `touch`.
+    Touch,
+    /// A slot was replaced with `Slot::replace`.
+    Replace,
+    /// A tree was copied into the directory with `copy_from`.
+    Copy,
+    /// The directory was emptied with `clear`.
+    Clear,
+    /// An entry was removed, by `DirHandle::remove`, one entry at a time
+    /// during cleanup, or while cleaning up after a failed `Slot::replace`.
+    Remove,
+    /// The directory was closed, by `close` or on drop. The event is
+    /// recorded even if the retention policy kept the directory.
+    Close,
+}
+
+/// An operation recorded in a journal.
+#[derive(Clone, Debug)]
+pub struct Event {
+    /// When the operation finished, according to the directory's clock.
+    pub time: SystemTime,
+    /// What was done.
+    pub action: Action,
+    /// The full path operated on.
+    pub path: PathBuf,
+    /// The error the operation failed with, if it did.
+    pub error: Option<String>,
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let time = self.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        try!(write!(f, "{}.{:03} {:?} {}", time.as_secs(), time.subsec_millis(), self.action,
+                    self.path.display()));
+        match self.error {
+            Some(ref e) => write!(f, ": {}", e),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A ring buffer of the most recent events, shared by a `TempDir` and
+/// whatever outlives it, e.g. to read the `Close` event.
+pub struct Journal {
+    capacity: usize,
+    events: Mutex<VecDeque<Event>>,
+}
+
+impl Journal {
+
+    /// Returns the recorded events, oldest first.
+    pub fn events(&self) -> Vec<Event> {
+        self.events.lock().unwrap().iter().cloned().collect()
+    }
+
+    fn push(&self, event: Event) {
+        let mut events = self.events.lock().unwrap();
+        if events.len() >= self.capacity {
+            events.pop_front();
+        }
+        if self.capacity > 0 {
+            events.push_back(event);
+        }
+    }
+}
+
+/// A journal together with the clock timing its events, for the code that
+/// removes entries without access to the `TempDir`.
+#[derive(Clone)]
+pub struct Recorder {
+    journal: Arc<Journal>,
+    clock: Arc<Clock>,
+}
+
+impl Recorder {
+
+    /// Records `action` on `path` with its result and passes the result
+    /// through.
+    pub fn record<T, E>(&self, action: Action, path: &Path, result: Result<T, E>)
+                        -> Result<T, E> where E: fmt::Display {
+        self.journal.push(Event {
+            time: self.clock.now(),
+            action: action,
+            path: path.to_path_buf(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+        });
+        result
+    }
+}
+
+impl TempDir {
+
+    /// Starts recording the operations performed through this directory's
+    /// helpers (`create_file`, `write`, `mkdir_all`, `touch`, `Slot::replace`,
+    /// `copy_from` and `clear`), the entries removed through them and by
+    /// handles opened with `open_handle`, and the closing of the directory,
+    /// keeping the most recent `capacity` of them, so that the history
+    /// behind a flaky test can be printed when it fails. Replaces any
+    /// journal recorded so far.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use tempdir::TempDir;
+    ///
+    /// let mut temp_dir = TempDir::new("flaky").unwrap();
+    /// temp_dir.enable_journal(100);
+    /// temp_dir.write("config.toml", b"").unwrap();
+    /// for event in temp_dir.journal() {
+    ///     println!("{}", event);
+    /// }
+    /// ```
+    pub fn enable_journal(&mut self, capacity: usize) {
+        let journal = Journal { capacity: capacity, events: Mutex::new(VecDeque::new()) };
+        self.journal = Some(Arc::new(journal));
+        self.hooks.journal = recorder(self);
+    }
+
+    /// Returns the recorded events, oldest first. Empty if the journal is
+    /// not enabled.
+    pub fn journal(&self) -> Vec<Event> {
+        match self.journal {
+            Some(ref journal) => journal.events(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns the journal itself, if it is enabled, so that it can still be
+    /// read once the directory has been closed.
+    pub fn journal_handle(&self) -> Option<Arc<Journal>> {
+        self.journal.clone()
+    }
+}
+
+/// Returns a recorder for the journal of `dir`, if it is enabled.
+pub fn recorder(dir: &TempDir) -> Option<Recorder> {
+    dir.journal.as_ref().map(|journal| {
+        Recorder { journal: journal.clone(), clock: dir.clock.clone() }
+    })
+}
+
+/// Records `action` on `path` with its result in the journal of `dir`, if it
+/// is enabled, and passes the result through.
+pub fn record<T, E>(dir: &TempDir, action: Action, path: &Path, result: Result<T, E>)
+                    -> Result<T, E> where E: fmt::Display {
+    match recorder(dir) {
+        Some(recorder) => recorder.record(action, path, result),
+        None => result,
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::path::PathBuf;
+
+    use super::super::*;
+
+    #[test]
+    fn test_journal() {
+        let mut temp_dir = TempDir::new("test_journal").unwrap();
+        temp_dir.write("untracked", b"").unwrap();
+        assert!(temp_dir.journal().is_empty());
+
+        temp_dir.enable_journal(3);
+        temp_dir.write("a/b", b"contents").unwrap();
+        temp_dir.mkdir_all("c").unwrap();
+        temp_dir.clear().unwrap();
+
+        let actions: Vec<Action> = temp_dir.journal().iter().map(|e| e.action).collect();
+        assert_eq!(actions, vec![Action::Write, Action::CreateDir, Action::Clear]);
+        assert_eq!(temp_dir.journal()[0].path, temp_dir.path().join("a/b"));
+    }
+
+    #[test]
+    fn test_journal_records_removals() {
+        let mut temp_dir = TempDir::new("test_journal_records_removals").unwrap();
+        temp_dir.set_cleanup(Cleanup::Sorted);
+        temp_dir.enable_journal(10);
+        temp_dir.write("a", b"").unwrap();
+        temp_dir.write("b", b"").unwrap();
+        let path = temp_dir.path().to_path_buf();
+        let journal = temp_dir.journal_handle().unwrap();
+        temp_dir.close().unwrap();
+
+        let events: Vec<(Action, PathBuf)> =
+            journal.events().into_iter().map(|e| (e.action, e.path)).collect();
+        assert_eq!(events, vec![(Action::Write, path.join("a")),
+                                (Action::Write, path.join("b")),
+                                (Action::Remove, path.join("a")),
+                                (Action::Remove, path.join("b")),
+                                (Action::Remove, path.clone()),
+                                (Action::Close, path.clone())]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_journal_records_handle_removals() {
+        let mut temp_dir = TempDir::new("test_journal_records_handle_removals").unwrap();
+        temp_dir.enable_journal(10);
+        let handle = temp_dir.open_handle().unwrap();
+        handle.create_subdir("sub").unwrap().create_file("a").unwrap();
+        handle.open_subdir("sub").unwrap().remove("a").unwrap();
+        assert!(handle.remove("missing").is_err());
+
+        let events = temp_dir.journal();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].action, Action::Remove);
+        assert_eq!(events[0].path, temp_dir.path().join("sub/a"));
+        assert!(events[0].error.is_none());
+        assert!(events[1].error.is_some());
+    }
+}
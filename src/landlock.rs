@@ -0,0 +1,142 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Running closures under a Landlock ruleset confining writes to a `TempDir`
+//! (Linux 5.13 and later).
+
+use libc;
+use std::ffi::CString;
+use std::io;
+use std::mem;
+use std::os::unix::OsStrExt;
+use std::thread;
+
+use super::{Error, TempDir};
+
+const SYS_LANDLOCK_CREATE_RULESET: libc::c_long = 444;
+const SYS_LANDLOCK_ADD_RULE: libc::c_long = 445;
+const SYS_LANDLOCK_RESTRICT_SELF: libc::c_long = 446;
+
+const LANDLOCK_RULE_PATH_BENEATH: libc::c_int = 1;
+
+/// Every access right that modifies the filesystem in the first Landlock ABI.
+/// Reading and executing are left unrestricted.
+const WRITE_ACCESS: u64 = (1 << 1)   // WRITE_FILE
+                        | (1 << 4)   // REMOVE_DIR
+                        | (1 << 5)   // REMOVE_FILE
+                        | (1 << 6)   // MAKE_CHAR
+                        | (1 << 7)   // MAKE_DIR
+                        | (1 << 8)   // MAKE_REG
+                        | (1 << 9)   // MAKE_SOCK
+                        | (1 << 10)  // MAKE_FIFO
+                        | (1 << 11)  // MAKE_BLOCK
+                        | (1 << 12); // MAKE_SYM
+
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+/// Closes a file descriptor when dropped.
+struct Fd(libc::c_int);
+
+impl Drop for Fd {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.0); }
+    }
+}
+
+fn check(ret: libc::c_long) -> io::Result<libc::c_long> {
+    if ret < 0 { Err(io::Error::last_os_error()) } else { Ok(ret) }
+}
+
+/// Restricts the calling thread so that it can only modify the filesystem
+/// below `dir`. This cannot be undone.
+fn restrict_self(dir: &TempDir) -> io::Result<()> {
+    let path = try!(CString::new(dir.path().as_os_str().as_bytes()).map_err(|_| {
+        Error::nul_byte(dir.path())
+    }));
+    unsafe {
+        let attr = RulesetAttr { handled_access_fs: WRITE_ACCESS };
+        let ruleset = Fd(try!(check(libc::syscall(SYS_LANDLOCK_CREATE_RULESET,
+                                                  &attr as *const RulesetAttr,
+                                                  mem::size_of::<RulesetAttr>(),
+                                                  0u32))) as libc::c_int);
+
+        let parent = libc::open(path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC);
+        if parent < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let parent = Fd(parent);
+        let rule = PathBeneathAttr { allowed_access: WRITE_ACCESS, parent_fd: parent.0 };
+        try!(check(libc::syscall(SYS_LANDLOCK_ADD_RULE, ruleset.0, LANDLOCK_RULE_PATH_BENEATH,
+                                 &rule as *const PathBeneathAttr, 0u32)));
+
+        try!(check(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) as libc::c_long));
+        try!(check(libc::syscall(SYS_LANDLOCK_RESTRICT_SELF, ruleset.0, 0u32)));
+    }
+    Ok(())
+}
+
+impl TempDir {
+
+    /// Runs `f` with filesystem modifications restricted, by the kernel, to
+    /// this temporary directory.
+    ///
+    /// Landlock restrictions can never be lifted from a thread, so `f` runs
+    /// on a new thread which is joined before returning. Reads are not
+    /// restricted. Fails if the kernel does not support Landlock, in which
+    /// case `f` is not run.
+    pub fn confine<F, R>(&self, f: F) -> io::Result<R>
+        where F: FnOnce() -> R + Send, R: Send
+    {
+        let guard = thread::scoped(move || {
+            try!(restrict_self(self));
+            Ok(f())
+        });
+        guard.join()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use libc;
+    use std::fs::File;
+
+    use super::super::*;
+
+    #[test]
+    fn test_confine() {
+        let base = TempDir::new("test_confine").unwrap();
+        let inside = TempDir::new_in(base.path(), "inside").unwrap();
+        let sibling = TempDir::new_in(base.path(), "sibling").unwrap();
+        let result = inside.confine(|| {
+            let written = File::create(&inside.path().join("file")).map(|_| ());
+            let escaped = File::create(&sibling.path().join("file")).map(|_| ());
+            (written.is_ok(), escaped.err().and_then(|e| e.raw_os_error()))
+        });
+        let (written, escaped) = match result {
+            Ok(outcome) => outcome,
+            // Kernels without Landlock, or with it disabled, cannot confine.
+            Err(ref e) if e.raw_os_error() == Some(libc::ENOSYS)
+                       || e.raw_os_error() == Some(libc::EOPNOTSUPP) => return,
+            Err(e) => panic!("failed to confine: {}", e),
+        };
+        assert!(written);
+        assert_eq!(escaped, Some(libc::EACCES));
+        // Only the confined thread was restricted.
+        File::create(&sibling.path().join("file")).unwrap();
+    }
+}
@@ -9,13 +9,154 @@
 #![feature(env, fs, io, path, os, std_misc)]
 
 extern crate rand;
+#[macro_use]
+extern crate lazy_static;
+extern crate libc;
+extern crate unicode_normalization;
 
-use rand::Rng;
-use std::path::{Path, PathBuf};
-use std::ffi::{OsString, AsOsStr, OsStr};
+use std::path::{AsPath, Component, Path, PathBuf};
 use std::env;
-use std::fs;
-use std::io;
+use std::ffi::{AsOsStr, OsString};
+use std::fs::{self, PathExt};
+use std::io::{self, Write};
+use std::ops::Deref;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+pub use archive::ArchiveFormat;
+#[cfg(feature = "async")]
+pub use blocking::Blocking;
+pub use builder::Builder;
+pub use cache::FixtureCache;
+pub use child::ScopedDir;
+pub use cleanup::{Cleanup, Disposition, set_global_cleanup_error_handler};
+use cleanup::Hooks;
+pub use clock::{Clock, MockClock, SystemClock};
+pub use config::{Config, ConfigGuard};
+pub use copy::Symlinks;
+#[cfg(unix)]
+pub use dirfd::DirHandle;
+pub use envfile::EnvFile;
+pub use error::{Error, Operation};
+pub use expect::{CaseSensitivity, Normalization};
+pub use fallback::Substitution;
+pub use farm::BinFarm;
+pub use file::TempFile;
+pub use fixture::{Fixture, SharedFixture};
+pub use fuzz::RandomTree;
+pub use group::{CleanupGroup, MemberId};
+pub use guard::TempPathGuard;
+pub use identity::Anomaly;
+use identity::Identity;
+pub use incremental::{CleanupHandle, CloseIter, Removed};
+pub use journal::{Action, Event, Journal};
+pub use lock::FileLock;
+pub use resolve::{Candidate, Diagnosis, Rejection, Rules, Source, Strategy};
+pub use resolve::{diagnose, register_resolver, rules, set_rules, set_strategies, strategies};
+pub use resolve::temp_dir_source;
+pub use namespace::{Visibility, visibility};
+pub use owner::{OWNER_FILE, OWNER_FORMAT_VERSION, OwnerRecord};
+pub use parallel::remove_dir_all_parallel;
+pub use prefix::{MAX_PREFIX_LEN, Prefix};
+pub use reap::{reap, reap_in, reap_in_with_clock};
+pub use registry::{isolate, register_atexit_cleanup};
+pub use report::CreationReport;
+pub use retention::{KEEP_VAR, RetentionPolicy};
+pub use scope::{Scope, ScopeError, scope};
+pub use scratch::ScratchSpace;
+pub use shared::{SharedTempDir, WeakTempDir};
+#[cfg(feature = "signal-cleanup")]
+pub use signals::install_signal_cleanup;
+pub use slot::Slot;
+pub use soft::SoftDelete;
+pub use throttle::{Throttle, Throttled, set_throttle};
+pub use tracked::TrackedFile;
+pub use transaction::Transaction;
+pub use usage::EntryCount;
+pub use worker::WorkerDirs;
+#[cfg(all(feature = "bind-mount", target_os = "linux"))]
+pub use mount::BindMount;
+
+mod archive;
+mod background;
+#[cfg(feature = "async")]
+mod blocking;
+mod builder;
+mod cache;
+mod child;
+mod cleanup;
+mod clock;
+mod config;
+mod copy;
+#[cfg(unix)]
+mod dirfd;
+#[doc(hidden)]
+#[macro_use]
+pub mod doctest;
+pub mod env_compat;
+mod envfile;
+mod error;
+mod expect;
+mod fallback;
+mod farm;
+#[cfg(feature = "fault-injection")]
+pub mod faults;
+#[cfg(not(feature = "fault-injection"))]
+mod faults {
+    use std::io;
+
+    pub fn check_create() -> io::Result<()> { Ok(()) }
+    pub fn check_cleanup() -> io::Result<()> { Ok(()) }
+}
+mod file;
+mod fixture;
+mod fuzz;
+mod group;
+mod guard;
+mod identity;
+mod incremental;
+mod journal;
+mod lock;
+mod longpath;
+pub mod minimal;
+mod mtime;
+mod namelen;
+mod namespace;
+mod owner;
+mod parallel;
+mod perms;
+mod prefix;
+mod ram;
+mod reap;
+mod registry;
+mod report;
+mod resolve;
+mod retention;
+mod scope;
+mod scratch;
+mod shared;
+#[cfg(feature = "signal-cleanup")]
+mod signals;
+mod slot;
+mod soft;
+mod space;
+#[cfg(feature = "stress")]
+pub mod stress;
+#[cfg(test)]
+mod testing;
+mod throttle;
+mod tracked;
+mod transaction;
+mod usage;
+mod user;
+mod walk;
+mod worker;
+#[cfg(all(feature = "bind-mount", target_os = "linux"))]
+mod mount;
+#[cfg(all(feature = "landlock", target_os = "linux"))]
+mod landlock;
 
 /// Returns the path to a temporary directory.
 ///
@@ -27,40 +168,50 @@ use std::io;
 /// On Windows, returns the value of, in order, the 'TMP', 'TEMP',
 /// 'USERPROFILE' environment variable  if any are set and not the empty
 /// string. Otherwise, tmpdir returns the path to the Windows directory.
+///
+/// Strategies installed with `set_strategies`, such as preferring
+/// `XDG_RUNTIME_DIR` or `/var/tmp`, are tried before the rules above. The
+/// rules can be replaced by those of `std::env::temp_dir` with `set_rules`,
+/// and `temp_dir_source` reports which rule was used.
 pub fn temp_dir() -> PathBuf {
+    temp_dir_source().0
+}
 
-    fn var_nonempty(v: &str) -> Option<PathBuf> {
-        match env::var(v) {
-            Ok(x) =>
-                if x.is_empty() {
-                    None
-                } else {
-                    Some(PathBuf::new(&x))
-                },
-            _ => None
-        }
-    }
+/// Resolves and validates the temporary directory root and seeds the random
+/// name generator up front, so that the first latency-sensitive creation
+/// does not pay for it. Returns the root.
+///
+/// Use `Builder::prewarm` to also create the per-user parent directory.
+pub fn prewarm() -> io::Result<PathBuf> {
+    Builder::new().prewarm()
+}
 
-    #[cfg(unix)]
-    fn lookup() -> PathBuf {
-        let default = if cfg!(target_os = "android") {
-            PathBuf::new("/data/local/tmp")
-        } else {
-            PathBuf::new("/tmp")
-        };
+/// Blocks until every directory handed to `Cleanup::Background` so far has
+/// been removed.
+pub fn flush_cleanup() {
+    background::flush()
+}
 
-        var_nonempty("TMPDIR").unwrap_or(default)
-    }
+/// Returns a path inside of `dir` made of `prefix`, random characters and
+/// `suffix`, which does not exist yet. See `Builder::unique_path_in`, and
+/// `Builder::create_in` for creating the entry without a race.
+pub fn unique_path_in<P: ?Sized, S: ?Sized>(dir: &Path, prefix: &P, suffix: &S)
+                                            -> io::Result<PathBuf>
+    where P: AsOsStr, S: AsOsStr
+{
+    Builder::new().prefix(prefix).suffix(suffix).unique_path_in(dir)
+}
 
-    #[cfg(windows)]
-    fn lookup() -> PathBuf {
-        var_nonempty("TMP").or(
-            var_nonempty("TEMP").or(
-                var_nonempty("USERPROFILE").or(
-                   var_nonempty("WINDIR")))).unwrap_or(Path::new("C:\\Windows"))
+/// Joins the relative path `rel` onto `root`, refusing absolute paths and
+/// `..` components so that the result can never point outside of `root`.
+fn resolve_relative(root: &Path, rel: &Path) -> io::Result<PathBuf> {
+    for component in rel.components() {
+        match component {
+            Component::Normal(_) | Component::CurDir => (),
+            _ => return Err(Error::Escapes(rel.to_path_buf()).into_io_error()),
+        }
     }
-
-    lookup()
+    Ok(root.join(rel))
 }
 
 /// A wrapper for a path to temporary directory implementing automatic
@@ -117,7 +268,27 @@ pub fn temp_dir() -> PathBuf {
 /// }
 /// ```
 pub struct TempDir {
-    path: Option<PathBuf>,
+    path: PathBuf,
+    /// Set once the directory has been handed off or removed, after which
+    /// the wrapper leaves it alone when dropped.
+    detached: bool,
+    created: SystemTime,
+    clock: Arc<Clock>,
+    retention: RetentionPolicy,
+    cleanup: Cleanup,
+    hooks: Hooks,
+    identity: Option<Identity>,
+    quarantine: Option<PathBuf>,
+    retries: u32,
+    retry_delay: Duration,
+    force_cleanup: bool,
+    permissions: Option<perms::Snapshot>,
+    registration: Option<usize>,
+    substitution: Option<Substitution>,
+    journal: Option<Arc<Journal>>,
+    /// The record of the `TempPathGuard` for the directory when it was
+    /// adopted, set once the guard's owner removes it.
+    removed_by_owner: Option<Arc<AtomicBool>>,
 }
 
 /// How many times should we (re)try finding an unused random name? It should be
@@ -137,7 +308,7 @@ impl TempDir {
     /// deleted once the returned wrapper is destroyed.
     ///
     /// If no directory can be created, `Err` is returned.
-    pub fn new<P: ?Sized>(prefix: &P) -> io::Result<TempDir>
+    pub fn new<P: ?Sized>(prefix: &P) -> Result<TempDir, Error>
         where P: AsOsStr
     {
         TempDir::new_in(&temp_dir(), prefix)
@@ -148,84 +319,606 @@ impl TempDir {
     /// deleted once the returned wrapper is destroyed.
     ///
     /// If no directory can be created, `Err` is returned.
-    pub fn new_in<P: ?Sized>(tmpdir: &Path, prefix: &P) -> io::Result<TempDir>
+    pub fn new_in<P: ?Sized>(tmpdir: &Path, prefix: &P) -> Result<TempDir, Error>
+        where P: AsOsStr
+    {
+        Builder::new().prefix(prefix).tempdir_in(tmpdir)
+    }
+
+    /// Attempts to make a temporary directory inside of `os::tmpdir()` whose
+    /// name will have the prefix `prefix` and end with `suffix`, after the
+    /// random characters. This is useful for tools that dispatch on the
+    /// directory extension, e.g. a suffix of `".git"`. The directory will be
+    /// automatically deleted once the returned wrapper is destroyed.
+    ///
+    /// If no directory can be created, `Err` is returned.
+    pub fn with_suffix<P: ?Sized, S: ?Sized>(prefix: &P, suffix: &S) -> Result<TempDir, Error>
+        where P: AsOsStr, S: AsOsStr
+    {
+        Builder::new().prefix(prefix).suffix(suffix).tempdir()
+    }
+
+    /// Makes a temporary directory inside of `os::tmpdir()` whose name will
+    /// have the prefix `prefix`, runs `f` with its path, and then removes it,
+    /// returning what `f` returned.
+    ///
+    /// Unlike on drop, errors removing the directory are returned. If `f`
+    /// panics, the directory is removed while unwinding.
+    pub fn with<P: ?Sized, F, R>(prefix: &P, f: F) -> Result<R, Error>
+        where P: AsOsStr, F: FnOnce(&Path) -> R
+    {
+        let temp_dir = try!(TempDir::new(prefix));
+        let result = f(temp_dir.path());
+        try!(temp_dir.close());
+        Ok(result)
+    }
+
+    /// Attempts to make a temporary directory inside of `$OUT_DIR` whose name
+    /// will have the prefix `prefix`, for scratch space in build scripts that
+    /// should stay out of the system temporary directory. The directory will
+    /// be automatically deleted once the returned wrapper is destroyed.
+    ///
+    /// If `OUT_DIR` is not set (i.e. when not running under Cargo as a build
+    /// script) or no directory can be created, `Err` is returned.
+    pub fn new_in_out_dir<P: ?Sized>(prefix: &P) -> Result<TempDir, Error>
         where P: AsOsStr
     {
-        if tmpdir.is_relative() {
-            let cur_dir = try!(env::current_dir());
-            return TempDir::new_in(&cur_dir.join(tmpdir), prefix);
+        TempDir::new_in_out_dir_from(env::var_os("OUT_DIR"), prefix)
+    }
+
+    /// Does the work of `new_in_out_dir`, given the value of `OUT_DIR`.
+    fn new_in_out_dir_from<P: ?Sized>(out_dir: Option<OsString>, prefix: &P)
+                                      -> Result<TempDir, Error>
+        where P: AsOsStr
+    {
+        match out_dir {
+            Some(out_dir) => TempDir::new_in(&PathBuf::new(&out_dir), prefix),
+            None => Err(Error::OutDirUnset),
         }
+    }
 
-        let mut rng = rand::thread_rng();
-        for _ in 0..NUM_RETRIES {
-            let suffix: String = rng.gen_ascii_chars().take(NUM_RAND_CHARS).collect();
-            let leaf: OsString = if prefix.as_os_str() != OsStr::from_str("") {
-                let mut s = OsString::new();
-                s.push_os_str(prefix.as_os_str());
-                s.push_os_str(OsStr::from_str("."));
-                s.push_os_str(suffix.as_os_str());
-                s
-            } else {
-                // If we're given an empty string for a prefix, then creating a
-                // directory starting with "." would lead to it being
-                // semi-invisible on some systems.
-                suffix.as_os_str().to_os_string()
-            };
-            let path: PathBuf = tmpdir.join(&leaf);
-            match fs::create_dir(&path) {
-                Ok(_) => return Ok(TempDir { path: Some(path) }),
-                Err(ref e) if e.kind() == io::ErrorKind::PathAlreadyExists => (),
-                Err(e) => return Err(e)
-            }
+    /// Attempts to make a temporary directory next to `dest`, i.e. in its
+    /// parent directory, whose name will have the prefix `prefix`. The
+    /// directory will be automatically deleted once the returned wrapper is
+    /// destroyed.
+    ///
+    /// Being on the same file system as `dest`, the directory can later be
+    /// moved there with `persist` as a single atomic rename. `dest` itself
+    /// need not exist; a relative `dest` without a parent is taken to be in
+    /// the current directory.
+    ///
+    /// If no directory can be created, `Err` is returned.
+    pub fn new_sibling_of<D: ?Sized, P: ?Sized>(dest: &D, prefix: &P) -> Result<TempDir, Error>
+        where D: AsPath, P: AsOsStr
+    {
+        let dest = dest.as_path();
+        match dest.parent() {
+            Some(parent) if parent != Path::new("") => TempDir::new_in(parent, prefix),
+            Some(_) => TempDir::new_in(Path::new("."), prefix),
+            None => Err(Error::NotADirectory(dest.to_path_buf())),
         }
+    }
+
+    /// Wraps the freshly created directory at `path` with the default
+    /// settings.
+    fn with_path(path: PathBuf) -> TempDir {
+        let identity = Identity::of(&path).ok();
+        TempDir::with_identity(path, identity)
+    }
+
+    /// Wraps the directory at `path`, to be checked against `identity`, if
+    /// any, before it is removed.
+    fn with_identity(path: PathBuf, identity: Option<Identity>) -> TempDir {
+        let mut dir = TempDir::untracked(path, identity);
+        dir.registration = registry::track(&dir);
+        dir
+    }
 
-        Err(io::Error::new(io::ErrorKind::PathAlreadyExists, "Exhausted", None))
+    /// Wraps the directory at `path` like `with_identity`, without recording
+    /// it in the exit cleanup registry.
+    fn untracked(path: PathBuf, identity: Option<Identity>) -> TempDir {
+        TempDir {
+            path: path,
+            detached: false,
+            created: SystemTime::now(),
+            clock: Arc::new(SystemClock),
+            retention: RetentionPolicy::Never,
+            cleanup: Cleanup::Recursive,
+            hooks: Hooks::new(),
+            identity: identity,
+            quarantine: None,
+            retries: 0,
+            retry_delay: Duration::from_millis(0),
+            force_cleanup: false,
+            permissions: None,
+            registration: None,
+            substitution: None,
+            journal: None,
+            removed_by_owner: None,
+        }
     }
 
     /// Unwrap the wrapped `std::path::Path` from the `TempDir` wrapper.
     /// This discards the wrapper so that the automatic deletion of the
     /// temporary directory is prevented.
     pub fn into_inner(mut self) -> PathBuf {
-        self.path.take().unwrap()
+        self.detach()
+    }
+
+    /// Moves the temporary directory to `dest` and disarms the wrapper, so
+    /// that a tree built in scratch space can be published atomically.
+    /// Returns the new path.
+    ///
+    /// If the directory cannot be renamed, e.g. because `dest` already exists
+    /// or is on another file system, the error is returned and the temporary
+    /// directory is cleaned up as usual.
+    pub fn persist<P: ?Sized>(mut self, dest: &P) -> Result<PathBuf, Error>
+        where P: AsPath
+    {
+        let dest = dest.as_path().to_path_buf();
+        try!(fs::rename(self.path(), &dest).map_err(|e| {
+            Error::Failed(Operation::Persist, dest.clone(), 1, e)
+        }));
+        self.detach();
+        Ok(dest)
     }
 
     /// Access the wrapped `std::path::Path` to the temporary directory.
     pub fn path<'a>(&'a self) -> &'a Path {
-        &self.path.as_ref().unwrap()
+        &self.path
+    }
+
+    /// Returns the canonical, absolute form of the path to the temporary
+    /// directory, with all symbolic links resolved. See also
+    /// `Builder::canonicalize`.
+    pub fn canonicalize(&self) -> io::Result<PathBuf> {
+        fs::canonicalize(self.path())
+    }
+
+    /// Joins `rel` to the path of the temporary directory, failing with an
+    /// `Escapes` error if `rel` is absolute or contains `..` components.
+    /// Symbolic links are not looked at; use `join_resolved` for that.
+    pub fn join_checked<P: ?Sized>(&self, rel: &P) -> io::Result<PathBuf> where P: AsPath {
+        resolve_relative(self.path(), rel.as_path())
+    }
+
+    /// Like `join_checked`, but also resolves the symbolic links in the part
+    /// of the path that already exists, failing with an `Escapes` error if
+    /// they lead outside the temporary directory (or nowhere). Returns the
+    /// resolved path, with any components that do not exist yet appended.
+    ///
+    /// An entry can still be replaced by a link once it has been checked,
+    /// so untrusted processes must not be able to write to the directory
+    /// at the same time; `DirHandle` avoids that race on Unix.
+    pub fn join_resolved<P: ?Sized>(&self, rel: &P) -> io::Result<PathBuf> where P: AsPath {
+        let rel = rel.as_path();
+        let path = try!(self.join_checked(rel));
+        let root = try!(self.canonicalize());
+        let mut existing = &*path;
+        let mut missing = Vec::new();
+        loop {
+            match fs::symlink_metadata(existing) {
+                Ok(_) => break,
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                    missing.push(existing.file_name().unwrap());
+                    existing = existing.parent().unwrap();
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        let escapes = || Error::Escapes(rel.to_path_buf()).into_io_error();
+        let mut resolved = try!(fs::canonicalize(existing).map_err(|_| escapes()));
+        if !resolved.starts_with(&root) {
+            return Err(escapes());
+        }
+        for name in missing.iter().rev() {
+            resolved.push(name);
+        }
+        Ok(resolved)
+    }
+
+    /// Disarms the wrapper, so that dropping it no longer removes the
+    /// directory, and returns the path.
+    fn detach(&mut self) -> PathBuf {
+        self.detached = true;
+        self.update_registration();
+        self.path.clone()
+    }
+
+    /// Points the wrapper at the directory's new location after it has been
+    /// renamed.
+    fn set_path(&mut self, path: PathBuf) {
+        self.path = path;
+        self.update_registration();
+    }
+
+    /// Tells the exit cleanup registry whether and where the directory is to
+    /// be removed.
+    fn update_registration(&self) {
+        if self.registration.is_none() {
+            return;
+        }
+        let live = !self.detached && self.retention != RetentionPolicy::Always;
+        registry::set(self.registration, if live { Some(registry::Entry::of(self)) } else { None });
+    }
+
+    /// Returns whether the registry removed the directory already, e.g.
+    /// because it outlived the `isolate` call that created it, in which case
+    /// its path must be left alone.
+    fn collected(&self) -> bool {
+        !self.detached && self.retention != RetentionPolicy::Always &&
+            registry::collected(self.registration)
+    }
+
+    /// Returns how long ago the directory was created, according to its
+    /// clock.
+    pub fn age(&self) -> Duration {
+        self.clock.since(self.created)
+    }
+
+    /// Replaces the clock used to age the directory, e.g. by a `MockClock`
+    /// in tests. The creation time is reset to the new clock's current time.
+    pub fn set_clock(&mut self, clock: Arc<Clock>) {
+        self.created = clock.now();
+        self.clock = clock;
+        self.hooks.journal = journal::recorder(self);
+        self.update_registration();
+    }
+
+    /// Returns the retention policy consulted when the directory is dropped
+    /// or closed.
+    pub fn retention(&self) -> RetentionPolicy {
+        self.retention
+    }
+
+    /// Sets the retention policy consulted when the directory is dropped or
+    /// closed.
+    pub fn set_retention(&mut self, policy: RetentionPolicy) {
+        self.retention = policy;
+        self.update_registration();
+    }
+
+    /// If `keep` is true, the directory is kept, and its path printed to
+    /// stderr, if the thread is panicking when it is dropped. This is a
+    /// shorthand for `set_retention(RetentionPolicy::OnFailure)`; if `keep`
+    /// is false, a retention policy of `OnFailure` is reset to `Never` and
+    /// any other policy is left alone.
+    pub fn set_keep_on_panic(&mut self, keep: bool) {
+        if keep {
+            self.set_retention(RetentionPolicy::OnFailure);
+        } else if self.retention == RetentionPolicy::OnFailure {
+            self.set_retention(RetentionPolicy::Never);
+        }
+    }
+
+    /// Returns how the directory is removed when it is dropped or closed.
+    pub fn cleanup(&self) -> Cleanup {
+        self.cleanup
+    }
+
+    /// Sets how the directory is removed when it is dropped or closed.
+    pub fn set_cleanup(&mut self, cleanup: Cleanup) {
+        self.cleanup = cleanup;
+        self.update_registration();
+    }
+
+    /// Makes removal, on drop or `close`, retry up to `retries` more times,
+    /// waiting `delay` between attempts, if it fails.
+    pub fn set_cleanup_retries(&mut self, retries: u32, delay: Duration) {
+        self.retries = retries;
+        self.retry_delay = delay;
+    }
+
+    /// If `force` is true and removal fails, the read-only attributes (on
+    /// Unix, the owner's permissions) of everything inside are reset and
+    /// removal is attempted again.
+    pub fn set_force_cleanup(&mut self, force: bool) {
+        self.force_cleanup = force;
+    }
+
+    /// Registers a callback invoked with the path of every entry removed,
+    /// in order, by cleanup strategies that remove entries one at a time
+    /// (currently `Cleanup::Sorted`).
+    pub fn on_remove<F>(&mut self, f: F)
+        where F: Fn(&Path) + Send + Sync + 'static
+    {
+        self.hooks.on_remove = Some(Box::new(f));
+    }
+
+    /// Registers a callback invoked with the path and the error if removing
+    /// the directory on drop fails, where the error would otherwise be
+    /// discarded. Takes precedence over `set_global_cleanup_error_handler`.
+    pub fn set_cleanup_error_handler<F>(&mut self, f: F)
+        where F: Fn(&Path, io::Error) + Send + Sync + 'static
+    {
+        self.hooks.on_error = Some(Box::new(f));
+    }
+
+    /// Registers a filter consulted for every entry during recursive
+    /// cleanup, with the entry's path relative to the temporary directory.
+    /// Entries for which it returns `Disposition::Keep` are left in place,
+    /// along with the directories containing them, e.g. to always preserve
+    /// core dumps found in scratch space.
+    pub fn set_cleanup_filter<F>(&mut self, f: F)
+        where F: Fn(&Path) -> Disposition + Send + Sync + 'static
+    {
+        self.hooks.filter = Some(Arc::new(f));
+        self.update_registration();
+    }
+
+    /// Enables quarantine mode: before the directory is removed, it is
+    /// checked against what was recorded at creation (owner, device and
+    /// inode). If anything looks wrong, for instance because the path was
+    /// swapped for another directory or something was mounted on it, the
+    /// directory is moved into `dir` and reported instead of being deleted.
+    pub fn set_quarantine(&mut self, dir: &Path) {
+        self.quarantine = Some(dir.to_path_buf());
+        self.update_registration();
+    }
+
+    /// Removes everything inside of the temporary directory according to the
+    /// cleanup strategy, but keeps the directory itself, so that it can be
+    /// reused without invalidating its path or any open handle to it.
+    ///
+    /// Unlike closing, this ignores the retention policy.
+    pub fn clear(&self) -> io::Result<()> {
+        journal::record(self, Action::Clear, self.path(),
+                        self.cleanup.clear_with(self.path(), &self.hooks))
     }
 
     /// Close and remove the temporary directory.
     ///
     /// Although `TempDir` removes the directory on drop, in the destructor any errors are ignored.
     /// To detect errors cleaning up the temporary directory, call `close` instead.
-    pub fn close(self) -> io::Result<()> {
-        fs::remove_dir_all(&self.into_inner())
+    ///
+    /// If the retention policy keeps the directory, it is left in place and
+    /// `Ok` is returned.
+    pub fn close(self) -> Result<(), Error> {
+        self.close_path().map(|_| ())
+    }
+
+    /// Close and remove the temporary directory like `close`, returning the
+    /// path that was removed so that it can be logged or asserted on, or
+    /// `None` if the directory was kept, e.g. by its retention policy or
+    /// because a cleanup filter kept entries inside of it, or had already
+    /// been removed by `isolate`.
+    pub fn close_path(mut self) -> Result<Option<PathBuf>, Error> {
+        if self.collected() {
+            self.detach();
+            return Ok(None);
+        }
+        let path = self.detach();
+        let removed = journal::record(&self, Action::Close, &path, self.remove(&path));
+        if !try!(removed) {
+            return Ok(None);
+        }
+        // Entries kept by a cleanup filter keep the directory itself too.
+        if self.hooks.filter.is_some() && fs::symlink_metadata(&path).is_ok() {
+            return Ok(None);
+        }
+        Ok(Some(path))
+    }
+
+    /// Removes `path` according to the cleanup strategy, unless the retention
+    /// policy keeps it. Returns whether it was removed.
+    fn remove(&self, path: &Path) -> Result<bool, Error> {
+        if !try!(self.should_remove(path)) {
+            return Ok(false);
+        }
+        // Deep trees inside the directory may exceed the Windows path
+        // length limit otherwise.
+        let long = longpath::extended(path);
+        let mut attempt = 0;
+        let mut forced = false;
+        loop {
+            match self.remove_once(&long) {
+                Err(_) if self.force_cleanup && !forced && self.still_ours(&long) => {
+                    // If this fails, retrying reports the original problem.
+                    let _ = cleanup::make_writable(&long);
+                    forced = true;
+                }
+                Err(_) if attempt < self.retries && long.exists() => {
+                    attempt += 1;
+                    thread::sleep(self.retry_delay);
+                }
+                result => {
+                    return result.map(|_| true).map_err(|e| {
+                        Error::Failed(Operation::Cleanup, path.to_path_buf(), attempt + 1, e)
+                    });
+                }
+            }
+        }
+    }
+
+    /// Decides whether `path` is to be removed at all, and checks that it is
+    /// still the directory that was created.
+    fn should_remove(&self, path: &Path) -> io::Result<bool> {
+        if guard::removed_by_owner(&self.removed_by_owner) {
+            return Ok(false);
+        }
+        if self.retention.retains(self.age()) {
+            if self.retention == RetentionPolicy::OnFailure {
+                let _ = writeln!(&mut io::stderr(),
+                                 "tempdir: keeping {} after a panic", path.display());
+            }
+            return Ok(false);
+        }
+        if retention::keep_from_env() {
+            let _ = writeln!(&mut io::stderr(),
+                             "tempdir: keeping {} because {} is set",
+                             path.display(), retention::KEEP_VAR);
+            return Ok(false);
+        }
+        try!(faults::check_cleanup());
+        // Checking the identity takes an `lstat`, which the minimal syscall
+        // mode promises not to make.
+        if self.cleanup == Cleanup::MinimalSyscalls {
+            return Ok(true);
+        }
+        if let Some(identity) = self.identity.as_ref() {
+            if let Some(anomaly) = try!(identity.check(path)) {
+                return Err(match self.quarantine {
+                    Some(ref quarantine) => identity::quarantine(path, quarantine, &anomaly),
+                    None => identity::swapped(path, &anomaly),
+                });
+            }
+        }
+        Ok(true)
+    }
+
+    /// Makes one attempt at removing `path` with the cleanup strategy. Plain
+    /// recursive removal goes through the hardened routine, which checks the
+    /// directory's identity as it goes.
+    fn remove_once(&self, path: &Path) -> io::Result<()> {
+        match (self.cleanup, self.identity.as_ref()) {
+            (Cleanup::Recursive, Some(identity)) if self.hooks.filter.is_none() => {
+                identity::remove_dir_all(path, identity)
+            }
+            (Cleanup::Background, identity) if self.hooks.filter.is_none() => {
+                background::remove(path, identity.cloned())
+            }
+            _ => self.cleanup.remove_with(path, &self.hooks),
+        }
+    }
+
+    /// Returns whether `path` still exists and is the directory that was
+    /// created, so that its permissions may be changed.
+    fn still_ours(&self, path: &Path) -> bool {
+        match self.identity {
+            Some(ref identity) => identity.check(path).ok().map_or(false, |a| a.is_none()),
+            None => path.exists(),
+        }
+    }
+}
+
+/// Allows passing a `TempDir` directly wherever a path is expected, e.g. to
+/// `Command::current_dir`.
+impl AsPath for TempDir {
+    fn as_path(&self) -> &Path {
+        self.path()
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        self.path()
     }
 }
 
 impl Drop for TempDir {
     fn drop(&mut self) {
-        for p in self.path.iter() {
-            let _ = fs::remove_dir_all(p);
+        if self.detached || self.collected() {
+            return;
+        }
+        let removed = journal::record(self, Action::Close, &self.path, self.remove(&self.path));
+        if let Err(e) = removed {
+            match self.hooks.failed(&self.path, e.into_io_error()) {
+                Some(ref e) if self.cleanup == Cleanup::NonRecursive => {
+                    let _ = writeln!(&mut io::stderr(),
+                                     "tempdir: failed to remove {}: {}", self.path.display(), e);
+                }
+                _ => (),
+            }
         }
+        self.detach();
     }
 }
 
 #[cfg(test)]
 mod test {
 
-    use std::fs::{self, PathExt};
-    use std::path::PathBuf;
+    use std::fs::{self, File, PathExt};
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, Mutex};
     use std::thread;
+    use std::time::{Duration, Instant};
 
     use super::*;
 
+    #[cfg(unix)]
+    #[test]
+    fn test_cleanup_error_handler() {
+        let base = TempDir::new("test_cleanup_error_handler").unwrap();
+        let mut temp_dir = TempDir::new_in(base.path(), "x").unwrap();
+        temp_dir.set_cleanup(Cleanup::NonRecursive);
+        File::create(&temp_dir.path().join("file")).unwrap();
+
+        let failures = Arc::new(Mutex::new(Vec::new()));
+        let f = failures.clone();
+        temp_dir.set_cleanup_error_handler(move |path, err| {
+            f.lock().unwrap().push((path.to_path_buf(), err.kind()));
+        });
+        let path = temp_dir.path().to_path_buf();
+        drop(temp_dir);
+        let failures = failures.lock().unwrap();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, path);
+    }
+
+    #[test]
+    fn test_new_sibling_of() {
+        let base = TempDir::new("test_new_sibling_of").unwrap();
+        let dest = base.path().join("published");
+        let temp_dir = TempDir::new_sibling_of(&dest, "staging").unwrap();
+        assert_eq!(temp_dir.path().parent(), Some(base.path()));
+        assert_eq!(temp_dir.persist(&dest).unwrap(), dest);
+        assert!(dest.is_dir());
+        assert!(TempDir::new_sibling_of(Path::new("/"), "staging").is_err());
+    }
+
+    #[test]
+    fn test_join_resolved() {
+        let temp_dir = TempDir::new("test_join_resolved").unwrap();
+        let outside = TempDir::new("test_join_resolved").unwrap();
+        let root = fs::canonicalize(temp_dir.path()).unwrap();
+        fs::create_dir(&temp_dir.path().join("a")).unwrap();
+        fs::soft_link("a", &temp_dir.path().join("inside")).unwrap();
+        fs::soft_link(outside.path(), &temp_dir.path().join("outside")).unwrap();
+
+        assert!(temp_dir.join_checked("/etc/passwd").is_err());
+        assert!(temp_dir.join_checked("a/../../x").is_err());
+        assert_eq!(temp_dir.join_checked("outside/x").unwrap(),
+                   temp_dir.path().join("outside/x"));
+        assert_eq!(temp_dir.join_resolved("inside/new/file").unwrap(),
+                   root.join("a/new/file"));
+        let err = temp_dir.join_resolved("outside/x").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
     #[test]
     fn test_tempdir_prefix() {
         let temp_dir = TempDir::new("test_tempdir_prefix").unwrap();
         assert!(temp_dir.path().to_str().unwrap().contains("test_tempdir_prefix"));
     }
 
+    #[test]
+    fn test_tempdir_with_suffix() {
+        let temp_dir = TempDir::with_suffix("test_tempdir_with_suffix", ".git").unwrap();
+        let name = temp_dir.path().file_name().unwrap().to_str().unwrap().to_string();
+        assert!(name.starts_with("test_tempdir_with_suffix."));
+        assert!(name.ends_with(".git"));
+    }
+
+    #[test]
+    fn test_tempdir_with() {
+        let path = TempDir::with("test_tempdir_with", |p| {
+            assert!(p.exists());
+            p.to_path_buf()
+        }).unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_tempdir_as_path() {
+        let temp_dir = TempDir::new("test_tempdir_as_path").unwrap();
+        File::create(&temp_dir.join("a.txt")).unwrap();
+        assert!(fs::metadata(&temp_dir).unwrap().is_dir());
+        assert!(temp_dir.join("a.txt").exists());
+    }
+
     #[test]
     fn test_tempdir_drop() {
         let temp_dir = TempDir::new("test_tempdir_drop").unwrap();
@@ -256,6 +949,199 @@ mod test {
         assert!(!path.exists());
     }
 
+    #[test]
+    fn test_tempdir_close_path() {
+        let temp_dir = TempDir::new("test_tempdir_close_path").unwrap();
+        let path = temp_dir.path().to_path_buf();
+
+        assert_eq!(temp_dir.close_path().unwrap(), Some(path.clone()));
+        assert!(!path.exists());
+
+        let mut temp_dir = TempDir::new("test_tempdir_close_path").unwrap();
+        let path = temp_dir.path().to_path_buf();
+        temp_dir.set_retention(RetentionPolicy::Always);
+        assert_eq!(temp_dir.close_path().unwrap(), None);
+        assert!(path.exists());
+        fs::remove_dir(&path).unwrap();
+    }
+
+    #[test]
+    fn test_tempdir_retention() {
+        let mut temp_dir = TempDir::new("test_tempdir_retention").unwrap();
+        let path = temp_dir.path().to_path_buf();
+
+        temp_dir.set_retention(RetentionPolicy::Always);
+        temp_dir.close().unwrap();
+        assert!(path.exists());
+        fs::remove_dir(&path).unwrap();
+    }
+
+    #[test]
+    fn test_tempdir_keep_on_panic() {
+        let mut temp_dir = TempDir::new("test_tempdir_keep_on_panic").unwrap();
+        let path = temp_dir.path().to_path_buf();
+        temp_dir.set_keep_on_panic(true);
+
+        let result = thread::spawn(move || {
+            let _temp_dir = temp_dir;
+            panic!("test failure");
+        }).join();
+        assert!(result.is_err());
+        assert!(path.exists());
+        fs::remove_dir(&path).unwrap();
+    }
+
+    #[test]
+    fn test_tempdir_non_recursive() {
+        let mut temp_dir = TempDir::new("test_tempdir_non_recursive").unwrap();
+        let path = temp_dir.path().to_path_buf();
+        temp_dir.set_cleanup(Cleanup::NonRecursive);
+        fs::create_dir(&path.join("leftover")).unwrap();
+
+        assert!(temp_dir.close().is_err());
+        assert!(path.join("leftover").exists());
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn test_tempdir_cleanup_retries() {
+        let mut temp_dir = TempDir::new("test_tempdir_cleanup_retries").unwrap();
+        let path = temp_dir.path().to_path_buf();
+        temp_dir.set_cleanup(Cleanup::NonRecursive);
+        temp_dir.set_cleanup_retries(2, Duration::from_millis(10));
+        fs::create_dir(&path.join("leftover")).unwrap();
+
+        let start = Instant::now();
+        assert!(temp_dir.close().is_err());
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tempdir_force_cleanup() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut temp_dir = TempDir::new("test_tempdir_force_cleanup").unwrap();
+        let path = temp_dir.path().to_path_buf();
+        fs::create_dir(&path.join("locked")).unwrap();
+        File::create(&path.join("locked/file")).unwrap();
+        fs::set_permissions(&path.join("locked"), fs::Permissions::from_mode(0o500)).unwrap();
+
+        temp_dir.set_force_cleanup(true);
+        temp_dir.close().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_tempdir_sorted_cleanup() {
+        let mut temp_dir = TempDir::new("test_tempdir_sorted_cleanup").unwrap();
+        let path = temp_dir.path().to_path_buf();
+        fs::create_dir(&path.join("b")).unwrap();
+        fs::create_dir(&path.join("a")).unwrap();
+        fs::create_dir(&path.join("a/c")).unwrap();
+
+        let removed = Arc::new(Mutex::new(Vec::new()));
+        let log = removed.clone();
+        temp_dir.set_cleanup(Cleanup::Sorted);
+        temp_dir.on_remove(move |p| log.lock().unwrap().push(p.to_path_buf()));
+        temp_dir.close().unwrap();
+
+        assert_eq!(*removed.lock().unwrap(),
+                   vec![path.join("a/c"), path.join("a"), path.join("b"), path.clone()]);
+    }
+
+    #[test]
+    fn test_tempdir_cleanup_filter() {
+        let mut temp_dir = TempDir::new("test_tempdir_cleanup_filter").unwrap();
+        let path = temp_dir.path().to_path_buf();
+        fs::create_dir(&path.join("a")).unwrap();
+        File::create(&path.join("a/core")).unwrap();
+        File::create(&path.join("a/junk")).unwrap();
+
+        temp_dir.set_cleanup_filter(|p| {
+            if p.file_name().and_then(|n| n.to_str()) == Some("core") {
+                Disposition::Keep
+            } else {
+                Disposition::Delete
+            }
+        });
+        assert_eq!(temp_dir.close_path().unwrap(), None);
+
+        assert!(path.join("a/core").exists());
+        assert!(!path.join("a/junk").exists());
+        fs::remove_dir_all(&path).unwrap();
+
+        // Nothing to keep, so the directory goes too.
+        let mut temp_dir = TempDir::new("test_tempdir_cleanup_filter").unwrap();
+        let path = temp_dir.path().to_path_buf();
+        File::create(&path.join("junk")).unwrap();
+        temp_dir.set_cleanup_filter(|_| Disposition::Delete);
+        assert_eq!(temp_dir.close_path().unwrap(), Some(path.clone()));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_tempdir_clear() {
+        let temp_dir = TempDir::new("test_tempdir_clear").unwrap();
+        let path = temp_dir.path().to_path_buf();
+        fs::create_dir(&path.join("a")).unwrap();
+        File::create(&path.join("a/b")).unwrap();
+        File::create(&path.join("c")).unwrap();
+
+        temp_dir.clear().unwrap();
+        assert!(path.exists());
+        assert_eq!(fs::read_dir(&path).unwrap().count(), 0);
+        File::create(&path.join("d")).unwrap();
+        temp_dir.close().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_tempdir_new_in_out_dir() {
+        let out_dir = TempDir::new("test_tempdir_new_in_out_dir").unwrap();
+        let value = out_dir.path().as_os_str().to_os_string();
+        let temp_dir = TempDir::new_in_out_dir_from(Some(value), "scratch").unwrap();
+        assert_eq!(temp_dir.path().parent(), Some(out_dir.path()));
+        match TempDir::new_in_out_dir_from(None, "scratch") {
+            Err(Error::OutDirUnset) => {}
+            _ => panic!("expected OutDirUnset"),
+        }
+    }
+
+    #[test]
+    fn test_tempdir_retention_mock_clock() {
+        let clock = Arc::new(MockClock::epoch());
+        let mut temp_dir = TempDir::new("test_tempdir_retention_mock_clock").unwrap();
+        let path = temp_dir.path().to_path_buf();
+        temp_dir.set_clock(clock.clone());
+        temp_dir.set_retention(RetentionPolicy::OlderThan(Duration::from_secs(60)));
+
+        clock.advance(Duration::from_secs(90));
+        assert_eq!(temp_dir.age(), Duration::from_secs(90));
+        temp_dir.close().unwrap();
+        assert!(path.exists());
+        fs::remove_dir(&path).unwrap();
+    }
+
+    #[test]
+    fn test_tempdir_persist() {
+        let parent = TempDir::new("test_tempdir_persist").unwrap();
+        let temp_dir = TempDir::new("test_tempdir_persist").unwrap();
+        let path = temp_dir.path().to_path_buf();
+        let dest = parent.path().join("published");
+
+        assert_eq!(temp_dir.persist(&dest).unwrap(), dest);
+        assert!(!path.exists());
+        assert!(dest.exists());
+
+        let taken = TempDir::new("test_tempdir_persist").unwrap();
+        let path = taken.path().to_path_buf();
+        File::create(&dest.join("occupied")).unwrap();
+        assert!(taken.persist(&dest).is_err());
+        assert!(!path.exists());
+    }
+
     #[test]
     fn test_tempdir_into_inner() {
         let temp_dir: TempDir = TempDir::new("test_tempdir_drop").unwrap();
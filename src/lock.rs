@@ -0,0 +1,203 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::{AsPath, Path, PathBuf};
+
+use super::{TempDir, resolve_relative};
+
+/// An exclusive advisory lock on a file inside a `TempDir`, taken with
+/// `TempDir::lock_file` and released when dropped.
+///
+/// The lock is held through `flock(2)` on Unix and `LockFileEx` on Windows,
+/// so it coordinates with other processes (and other handles in this one)
+/// locking the same file, but does not stop anyone from simply opening it.
+pub struct FileLock<'a> {
+    dir: &'a TempDir,
+    path: PathBuf,
+    file: File,
+}
+
+impl<'a> FileLock<'a> {
+
+    /// Returns the directory containing the lock file.
+    pub fn dir(&self) -> &'a TempDir {
+        self.dir
+    }
+
+    /// Returns the full path of the lock file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Access the locked file, e.g. to record who holds the lock.
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+}
+
+impl<'a> Drop for FileLock<'a> {
+    fn drop(&mut self) {
+        // Closing the file would release the lock too, but only once every
+        // duplicate of the handle is closed.
+        let _ = imp::unlock(&self.file);
+    }
+}
+
+impl TempDir {
+
+    /// Creates the file at `rel`, relative to the temporary directory, if it
+    /// does not exist yet and waits until an exclusive lock on it can be
+    /// taken.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use tempdir::TempDir;
+    ///
+    /// let dir = TempDir::new("shared").unwrap();
+    /// {
+    ///     let _lock = dir.lock_file("build.lock").unwrap();
+    ///     // Only one process at a time gets here.
+    /// }
+    /// ```
+    pub fn lock_file<P: ?Sized>(&self, rel: &P) -> io::Result<FileLock> where P: AsPath {
+        self.lock(rel.as_path(), true)
+    }
+
+    /// Like `lock_file`, but fails with `WouldBlock` instead of waiting if
+    /// the file is already locked.
+    pub fn try_lock_file<P: ?Sized>(&self, rel: &P) -> io::Result<FileLock> where P: AsPath {
+        self.lock(rel.as_path(), false)
+    }
+
+    fn lock(&self, rel: &Path, wait: bool) -> io::Result<FileLock> {
+        let path = try!(resolve_relative(self.path(), rel));
+        let file = try!(OpenOptions::new().read(true).write(true).create(true).open(&path));
+        try!(imp::lock(&file, wait));
+        Ok(FileLock { dir: self, path: path, file: file })
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use libc;
+    use std::fs::File;
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+
+    use Error;
+
+    pub fn lock(file: &File, wait: bool) -> io::Result<()> {
+        let operation = if wait { libc::LOCK_EX } else { libc::LOCK_EX | libc::LOCK_NB };
+        loop {
+            if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+                return Ok(());
+            }
+            let err = io::Error::last_os_error();
+            match err.raw_os_error() {
+                Some(libc::EINTR) => continue,
+                Some(libc::EWOULDBLOCK) => {
+                    return Err(Error::Locked.into_io_error())
+                }
+                _ => return Err(err),
+            }
+        }
+    }
+
+    pub fn unlock(file: &File) -> io::Result<()> {
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_UN) } == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::fs::File;
+    use std::io;
+    use std::mem;
+    use std::os::windows::io::AsRawHandle;
+
+    use Error;
+
+    const LOCKFILE_EXCLUSIVE_LOCK: u32 = 0x2;
+    const LOCKFILE_FAIL_IMMEDIATELY: u32 = 0x1;
+    const ERROR_LOCK_VIOLATION: i32 = 33;
+
+    #[repr(C)]
+    struct Overlapped {
+        internal: usize,
+        internal_high: usize,
+        offset: u32,
+        offset_high: u32,
+        event: *mut u8,
+    }
+
+    extern "system" {
+        fn LockFileEx(file: *mut u8, flags: u32, reserved: u32, low: u32, high: u32,
+                      overlapped: *mut Overlapped) -> i32;
+        fn UnlockFileEx(file: *mut u8, reserved: u32, low: u32, high: u32,
+                        overlapped: *mut Overlapped) -> i32;
+    }
+
+    pub fn lock(file: &File, wait: bool) -> io::Result<()> {
+        let flags = if wait {
+            LOCKFILE_EXCLUSIVE_LOCK
+        } else {
+            LOCKFILE_EXCLUSIVE_LOCK | LOCKFILE_FAIL_IMMEDIATELY
+        };
+        let mut overlapped: Overlapped = unsafe { mem::zeroed() };
+        let handle = file.as_raw_handle() as *mut u8;
+        if unsafe { LockFileEx(handle, flags, 0, !0, !0, &mut overlapped) } != 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() == Some(ERROR_LOCK_VIOLATION) {
+            return Err(Error::Locked.into_io_error());
+        }
+        Err(err)
+    }
+
+    pub fn unlock(file: &File) -> io::Result<()> {
+        let mut overlapped: Overlapped = unsafe { mem::zeroed() };
+        let handle = file.as_raw_handle() as *mut u8;
+        if unsafe { UnlockFileEx(handle, 0, !0, !0, &mut overlapped) } != 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::io;
+
+    use super::super::*;
+
+    #[test]
+    fn test_lock_file() {
+        let temp_dir = TempDir::new("test_lock_file").unwrap();
+        {
+            let lock = temp_dir.lock_file("a.lock").unwrap();
+            assert_eq!(lock.path(), &*temp_dir.path().join("a.lock"));
+            // Locks taken through separate handles exclude each other, even
+            // within one process.
+            let err = temp_dir.try_lock_file("a.lock").err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+            assert!(temp_dir.try_lock_file("b.lock").is_ok());
+        }
+        assert!(temp_dir.try_lock_file("a.lock").is_ok());
+        assert!(temp_dir.lock_file("../escape.lock").is_err());
+    }
+}
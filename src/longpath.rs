@@ -0,0 +1,163 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::TempDir;
+
+/// Expands any 8.3 short names (like `RUNNER~1`) in `path`, which must
+/// exist, into their long form.
+#[cfg(windows)]
+pub fn long_path(path: &Path) -> io::Result<PathBuf> {
+    use std::ffi::{AsOsStr, OsString};
+    use std::os::windows::{OsStrExt, OsStringExt};
+
+    extern "system" {
+        fn GetLongPathNameW(short: *const u16, long: *mut u16, len: u32) -> u32;
+    }
+
+    let mut short: Vec<u16> = path.as_os_str().encode_wide().collect();
+    short.push(0);
+    let mut buf = vec![0u16; 260];
+    loop {
+        let n = unsafe { GetLongPathNameW(short.as_ptr(), buf.as_mut_ptr(), buf.len() as u32) };
+        if n == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if (n as usize) < buf.len() {
+            buf.truncate(n as usize);
+            return Ok(PathBuf::new(&OsString::from_wide(&buf)));
+        }
+        // The buffer was too small; `n` is the required length, including
+        // the terminating nul.
+        buf = vec![0u16; n as usize];
+    }
+}
+
+/// Short names only exist on Windows, so paths are returned unchanged.
+#[cfg(not(windows))]
+pub fn long_path(path: &Path) -> io::Result<PathBuf> {
+    Ok(path.to_path_buf())
+}
+
+/// Returns the extended-length (`\\?\`) form of `path`, which Windows
+/// accepts beyond the usual limit of 260 characters. Relative paths are made
+/// absolute, and `.` and `..` are resolved lexically, since the system no
+/// longer does so for paths in this form. Paths that use some other prefix,
+/// or cannot be made absolute, are returned unchanged.
+#[cfg(windows)]
+pub fn extended(path: &Path) -> PathBuf {
+    use std::env;
+    use std::ffi::{OsStr, OsString};
+    use std::path::{Component, Prefix};
+
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        match env::current_dir() {
+            Ok(cwd) => cwd.join(path),
+            Err(_) => return path.to_path_buf(),
+        }
+    };
+    let mut components = absolute.components();
+    let mut result = match components.next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(letter) => OsString::from_string(format!("\\\\?\\{}:", letter as char)),
+            Prefix::UNC(server, share) => {
+                let mut s = OsString::from_str("\\\\?\\UNC\\");
+                s.push_os_str(server);
+                s.push_os_str(OsStr::from_str("\\"));
+                s.push_os_str(share);
+                s
+            }
+            _ => return absolute,
+        },
+        _ => return absolute,
+    };
+    let mut names: Vec<OsString> = Vec::new();
+    for component in components {
+        match component {
+            Component::Normal(name) => names.push(name.to_os_string()),
+            Component::ParentDir => { names.pop(); }
+            _ => (),
+        }
+    }
+    for name in names.iter() {
+        result.push_os_str(OsStr::from_str("\\"));
+        result.push_os_str(name.as_os_str());
+    }
+    if names.is_empty() {
+        result.push_os_str(OsStr::from_str("\\"));
+    }
+    PathBuf::new(&result)
+}
+
+/// Paths are not length-limited this way elsewhere, so they are returned
+/// unchanged.
+#[cfg(not(windows))]
+pub fn extended(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+impl TempDir {
+
+    /// Returns the path of the temporary directory with any Windows 8.3
+    /// short names (e.g. `C:\Users\RUNNER~1\AppData\Local\Temp`, as found on
+    /// some CI runners) expanded into their long form, for comparing against
+    /// paths reported by other tools. On other platforms this is the same
+    /// as `path`.
+    pub fn long_path(&self) -> io::Result<PathBuf> {
+        long_path(self.path())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::super::*;
+
+    #[test]
+    fn test_long_path() {
+        let temp_dir = TempDir::new("test_long_path").unwrap();
+        let long = temp_dir.long_path().unwrap();
+        assert_eq!(long.file_name(), temp_dir.path().file_name());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_extended() {
+        use std::path::Path;
+
+        use super::extended;
+
+        assert_eq!(extended(Path::new("C:\\a\\.\\b\\..\\c")), Path::new("\\\\?\\C:\\a\\c"));
+        assert_eq!(extended(Path::new("\\\\server\\share\\x")),
+                   Path::new("\\\\?\\UNC\\server\\share\\x"));
+        assert_eq!(extended(Path::new("\\\\?\\C:\\x")), Path::new("\\\\?\\C:\\x"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_deep_tree_cleanup() {
+        use std::fs::{self, PathExt};
+
+        let temp_dir = Builder::new().prefix("test_deep_tree_cleanup")
+                                     .long_paths(true)
+                                     .tempdir()
+                                     .unwrap();
+        let mut deep = temp_dir.path().to_path_buf();
+        for _ in 0..30 {
+            deep.push("a-rather-long-directory-name");
+        }
+        fs::create_dir_all(&deep).unwrap();
+        let path = temp_dir.path().to_path_buf();
+        temp_dir.close().unwrap();
+        assert!(!path.exists());
+    }
+}
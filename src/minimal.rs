@@ -0,0 +1,264 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Creation and cleanup restricted to a small, fixed set of system calls, for
+//! use inside tightly seccomp-filtered sandboxes.
+//!
+//! Random names come from a generator seeded once by `init`, so no entropy
+//! system calls are made afterwards, and removal walks directories with raw
+//! `getdents64` instead of going through `stat`-family calls. For the same
+//! reason, directories created in this mode are not checked for having been
+//! swapped for something else before they are removed.
+
+use std::io;
+use std::path::Path;
+
+/// The system calls made by the minimal creation and cleanup paths after
+/// `init`, in addition to those made by the memory allocator.
+#[cfg(target_os = "linux")]
+pub const SYSCALLS: &'static [&'static str] = &[
+    "mkdirat", "openat", "getdents64", "unlinkat", "close", "getcwd",
+];
+
+/// The system calls made by `init`.
+#[cfg(target_os = "linux")]
+pub const INIT_SYSCALLS: &'static [&'static str] = &["openat", "read", "close"];
+
+/// Returns the system calls made by the minimal creation and cleanup paths
+/// after `init`, for authors of seccomp filters.
+#[cfg(target_os = "linux")]
+pub fn syscalls() -> &'static [&'static str] {
+    SYSCALLS
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use libc;
+    use std::ffi::CString;
+    use std::fs::File;
+    use std::io::{self, Read};
+    use std::mem;
+    use std::os::unix::OsStrExt;
+    use std::path::Path;
+    use std::sync::atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
+
+    use Error;
+
+    static STATE: AtomicUsize = ATOMIC_USIZE_INIT;
+
+    const ALPHABET: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+    /// Seeds the name generator from `/dev/urandom`. Calling it again
+    /// reseeds.
+    pub fn init() -> io::Result<()> {
+        let mut seed = [0u8; 8];
+        let mut f = try!(File::open("/dev/urandom"));
+        let mut read = 0;
+        while read < seed.len() {
+            match try!(f.read(&mut seed[read..])) {
+                0 => {
+                    let urandom = Path::new("/dev/urandom").to_path_buf();
+                    return Err(Error::ShortRead(urandom).into_io_error());
+                }
+                n => read += n,
+            }
+        }
+        let mut state = 0usize;
+        for b in seed.iter() {
+            state = (state << 8) | *b as usize;
+        }
+        // xorshift never leaves the all-zero state.
+        STATE.store(if state == 0 { 1 } else { state }, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn next() -> io::Result<usize> {
+        loop {
+            let current = STATE.load(Ordering::SeqCst);
+            if current == 0 {
+                return Err(Error::Uninitialized.into_io_error());
+            }
+            let mut x = current;
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            if STATE.compare_and_swap(current, x, Ordering::SeqCst) == current {
+                return Ok(x);
+            }
+        }
+    }
+
+    /// Returns `n` random alphanumeric characters.
+    pub fn rand_chars(n: usize) -> io::Result<String> {
+        let mut s = String::with_capacity(n);
+        let mut bits = 0;
+        let mut avail = 0;
+        while s.len() < n {
+            if avail < 6 {
+                bits = try!(next());
+                avail = mem::size_of::<usize>() * 8;
+            }
+            s.push(ALPHABET[(bits & 63) % ALPHABET.len()] as char);
+            bits >>= 6;
+            avail -= 6;
+        }
+        Ok(s)
+    }
+
+    fn cstring(path: &Path) -> io::Result<CString> {
+        CString::new(path.as_os_str().as_bytes()).map_err(|_| Error::nul_byte(path))
+    }
+
+    /// Creates a directory with `mkdirat`.
+    pub fn create_dir(path: &Path, mode: u32) -> io::Result<()> {
+        let path = try!(cstring(path));
+        if unsafe { libc::mkdirat(libc::AT_FDCWD, path.as_ptr(), mode as libc::mode_t) } == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    /// Recursively removes a directory using `openat`, `getdents64` and
+    /// `unlinkat` only. Entry types come from `d_type`, so filesystems which
+    /// report `DT_UNKNOWN` are not supported.
+    pub fn remove_dir_all(path: &Path) -> io::Result<()> {
+        let path = try!(cstring(path));
+        let fd = unsafe {
+            libc::openat(libc::AT_FDCWD, path.as_ptr(),
+                         libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC)
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let result = remove_contents(fd);
+        unsafe { libc::close(fd); }
+        try!(result);
+        if unsafe { libc::unlinkat(libc::AT_FDCWD, path.as_ptr(), libc::AT_REMOVEDIR) } == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    fn remove_contents(dir: libc::c_int) -> io::Result<()> {
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = unsafe {
+                libc::syscall(libc::SYS_getdents64, dir, buf.as_mut_ptr(), buf.len())
+            };
+            if n < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if n == 0 {
+                return Ok(());
+            }
+            let mut offset = 0;
+            while offset < n as usize {
+                // struct linux_dirent64 { u64 d_ino; i64 d_off; u16 d_reclen;
+                //                         u8 d_type; char d_name[]; }
+                let entry = &buf[offset..];
+                let reclen = entry[16] as usize | (entry[17] as usize) << 8;
+                let d_type = entry[18];
+                let name = &entry[19..reclen];
+                let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+                offset += reclen;
+                if name == b"." || name == b".." {
+                    continue;
+                }
+                let name = CString::new(name.to_vec()).unwrap();
+                if d_type == libc::DT_DIR {
+                    let fd = unsafe {
+                        libc::openat(dir, name.as_ptr(),
+                                     libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC)
+                    };
+                    if fd < 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    let result = remove_contents(fd);
+                    unsafe { libc::close(fd); }
+                    try!(result);
+                    if unsafe { libc::unlinkat(dir, name.as_ptr(), libc::AT_REMOVEDIR) } != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                } else if d_type == libc::DT_UNKNOWN {
+                    return Err(Error::NoEntryTypes.into_io_error());
+                } else if unsafe { libc::unlinkat(dir, name.as_ptr(), 0) } != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use rand::{self, Rng};
+    use std::fs;
+    use std::io;
+    use std::path::Path;
+
+    pub fn init() -> io::Result<()> { Ok(()) }
+
+    pub fn rand_chars(n: usize) -> io::Result<String> {
+        Ok(rand::thread_rng().gen_ascii_chars().take(n).collect())
+    }
+
+    pub fn create_dir(path: &Path, _mode: u32) -> io::Result<()> { fs::create_dir(path) }
+
+    pub fn remove_dir_all(path: &Path) -> io::Result<()> { fs::remove_dir_all(path) }
+}
+
+/// Seeds the random name generator used by the minimal syscall mode. This
+/// must be called before entering the sandbox, and before creating any
+/// directory with `Builder::minimal_syscalls`.
+///
+/// On platforms other than Linux this does nothing, and the minimal mode uses
+/// the regular implementation.
+pub fn init() -> io::Result<()> {
+    imp::init()
+}
+
+#[doc(hidden)]
+pub fn rand_chars(n: usize) -> io::Result<String> {
+    imp::rand_chars(n)
+}
+
+#[doc(hidden)]
+pub fn create_dir(path: &Path, mode: u32) -> io::Result<()> {
+    imp::create_dir(path, mode)
+}
+
+#[doc(hidden)]
+pub fn remove_dir_all(path: &Path) -> io::Result<()> {
+    imp::remove_dir_all(path)
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod test {
+
+    use std::fs::{self, File, PathExt};
+
+    use super::super::*;
+
+    #[test]
+    fn test_minimal_syscalls() {
+        minimal::init().unwrap();
+        let temp_dir = Builder::new().prefix("test_minimal_syscalls")
+                                     .minimal_syscalls(true)
+                                     .tempdir()
+                                     .unwrap();
+        // Capturing the identity would have taken an `lstat`.
+        assert!(temp_dir.identity.is_none());
+        let path = temp_dir.path().to_path_buf();
+        fs::create_dir(&path.join("a")).unwrap();
+        File::create(&path.join("a/b.txt")).unwrap();
+        temp_dir.close().unwrap();
+        assert!(!path.exists());
+    }
+}
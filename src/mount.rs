@@ -0,0 +1,153 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Read-only bind mounts of host directories into a `TempDir` (Linux only).
+
+use libc;
+use std::ffi::CString;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::os::unix::OsStrExt;
+use std::path::{AsPath, Path, PathBuf};
+use std::ptr;
+
+use namespace::unescape;
+use super::{Error, TempDir, resolve_relative};
+
+/// A host directory bind-mounted read-only inside a `TempDir`.
+///
+/// The mount is detached when the guard is dropped. The guard borrows the
+/// `TempDir`, so the mount is always gone before the directory is removed.
+pub struct BindMount<'a> {
+    target: PathBuf,
+    _dir: &'a TempDir,
+}
+
+impl<'a> BindMount<'a> {
+
+    /// The mount point inside the temporary directory.
+    pub fn path(&self) -> &Path {
+        &self.target
+    }
+
+    /// Unmounts the directory, returning any error encountered.
+    pub fn unmount(mut self) -> io::Result<()> {
+        let target = self.target.clone();
+        self.target = PathBuf::new("");
+        umount(&target)
+    }
+}
+
+impl<'a> Drop for BindMount<'a> {
+    fn drop(&mut self) {
+        if self.target.as_os_str().len() != 0 {
+            let _ = umount(&self.target);
+        }
+    }
+}
+
+fn cstring(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes()).map_err(|_| Error::nul_byte(path))
+}
+
+fn mount(source: &Path, target: &Path, flags: libc::c_ulong) -> io::Result<()> {
+    let source = try!(cstring(source));
+    let target = try!(cstring(target));
+    let ret = unsafe {
+        libc::mount(source.as_ptr(), target.as_ptr(), ptr::null(), flags, ptr::null())
+    };
+    if ret == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+/// Returns the mount points at or below `target`, parents before children.
+fn mounts_below(target: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut mountinfo = String::new();
+    try!(File::open("/proc/self/mountinfo").and_then(|mut f| f.read_to_string(&mut mountinfo)));
+    Ok(mountinfo.lines().filter_map(|line| line.split(' ').nth(4))
+                .map(|field| PathBuf::new(&unescape(field)))
+                .filter(|mount_point| mount_point.starts_with(target))
+                .collect())
+}
+
+fn umount(target: &Path) -> io::Result<()> {
+    let target = try!(cstring(target));
+    let ret = unsafe { libc::umount2(target.as_ptr(), libc::MNT_DETACH) };
+    if ret == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+impl TempDir {
+
+    /// Bind-mounts the host directory `source` read-only at `rel` inside the
+    /// temporary directory, creating the mount point if necessary.
+    ///
+    /// Mounts below `source` are bound along with it and are read-only too.
+    ///
+    /// This requires the privileges to call `mount(2)`, usually
+    /// `CAP_SYS_ADMIN` or a private user and mount namespace.
+    pub fn bind_readonly<P: ?Sized>(&self, source: &Path, rel: &P) -> io::Result<BindMount>
+        where P: AsPath
+    {
+        let target = try!(resolve_relative(self.path(), rel.as_path()));
+        try!(fs::create_dir_all(&target));
+        // Mountinfo lists canonical paths.
+        let target = try!(fs::canonicalize(&target));
+        try!(mount(source, &target, libc::MS_BIND | libc::MS_REC));
+        let guard = BindMount { target: target, _dir: self };
+        // A read-only bind mount has to be made in two steps: the initial
+        // bind ignores MS_RDONLY, so remount the new mount point read-only,
+        // along with every mount below `source` that came with it. Dropping
+        // the guard on failure detaches the whole tree again.
+        for mount_point in try!(mounts_below(&guard.target)) {
+            try!(mount(&mount_point, &mount_point,
+                       libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY));
+        }
+        Ok(guard)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use libc;
+    use std::fs::{self, File};
+    use std::path::Path;
+
+    use super::super::*;
+    use super::{BindMount, mount};
+
+    /// Whether a write through the mount at `path` fails with EROFS.
+    fn read_only(path: &Path) -> bool {
+        File::create(&path.join("written")).err()
+            .and_then(|e| e.raw_os_error()) == Some(libc::EROFS)
+    }
+
+    #[test]
+    fn test_bind_readonly() {
+        let source = TempDir::new("test_bind_readonly").unwrap();
+        fs::create_dir(&source.path().join("sub")).unwrap();
+        let inner = TempDir::new("test_bind_readonly").unwrap();
+        // Needs CAP_SYS_ADMIN, e.g. root or `unshare -rm`.
+        match mount(inner.path(), &source.path().join("sub"), libc::MS_BIND) {
+            Ok(()) => {}
+            Err(ref e) if e.raw_os_error() == Some(libc::EPERM) => return,
+            Err(e) => panic!("{}", e),
+        }
+        let _sub = BindMount { target: source.path().join("sub"), _dir: &source };
+
+        let temp_dir = TempDir::new("test_bind_readonly").unwrap();
+        let bound = temp_dir.bind_readonly(source.path(), "host").unwrap();
+        assert!(read_only(bound.path()));
+        assert!(read_only(&bound.path().join("sub")));
+        // The host directories themselves stay writable.
+        File::create(&source.path().join("written")).unwrap();
+        File::create(&inner.path().join("written")).unwrap();
+
+        bound.unmount().unwrap();
+        assert_eq!(fs::read_dir(&temp_dir.path().join("host")).unwrap().count(), 0);
+    }
+}
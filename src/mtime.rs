@@ -0,0 +1,111 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fs::{self, OpenOptions, PathExt};
+use std::io;
+use std::path::{AsPath, Path};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use journal;
+use super::{Action, TempDir, resolve_relative};
+use tracked::create_parent;
+use walk::{relative, walk};
+
+/// Sets the modification time of the file or directory at `path`, and its
+/// access time to the same. Times before the Unix epoch are clamped to it.
+pub fn set_mtime(path: &Path, mtime: SystemTime) -> io::Result<()> {
+    let since_epoch = mtime.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let millis = since_epoch.as_secs() * 1000 + since_epoch.subsec_millis() as u64;
+    fs::set_file_times(path, millis, millis)
+}
+
+impl TempDir {
+
+    /// Sets the modification time, with millisecond precision, of the entry
+    /// at `rel`, relative to the temporary directory, creating an empty file there (and any missing
+    /// parent directories) if nothing exists yet. Pass `SystemTime::now()`
+    /// for the usual `touch`. `rel` may not be absolute or contain `..`
+    /// components.
+    pub fn touch<P: ?Sized>(&self, rel: &P, mtime: SystemTime) -> io::Result<()>
+        where P: AsPath
+    {
+        let path = try!(resolve_relative(self.path(), rel.as_path()));
+        let result = if path.is_dir() {
+            set_mtime(&path, mtime)
+        } else {
+            create_parent(&path).and_then(|_| {
+                OpenOptions::new().write(true).create(true).open(&path)
+            }).and_then(|_| set_mtime(&path, mtime))
+        };
+        journal::record(self, Action::Touch, &path, result)
+    }
+
+    /// Sets the modification time of the temporary directory and everything
+    /// inside of it to `mtime`, so that tools comparing timestamps see a
+    /// deterministic tree. Symbolic links are skipped.
+    pub fn normalize_mtimes(&self, mtime: SystemTime) -> io::Result<()> {
+        self.normalize_mtimes_with(|_| Some(mtime))
+    }
+
+    /// Sets the modification time of each entry to the one returned by `f`
+    /// for its path relative to the temporary directory (the directory itself
+    /// is passed as the empty path), leaving it alone if `f` returns `None`.
+    /// Symbolic links are skipped.
+    ///
+    /// Contents are visited before their directory, and the directory
+    /// last.
+    pub fn normalize_mtimes_with<F>(&self, mut f: F) -> io::Result<()>
+        where F: FnMut(&Path) -> Option<SystemTime>
+    {
+        let root = self.path();
+        for entry in try!(walk(root)).into_iter().rev() {
+            if entry.metadata.file_type().is_symlink() {
+                continue;
+            }
+            if let Some(mtime) = f(relative(root, &entry.path)) {
+                try!(set_mtime(&entry.path, mtime));
+            }
+        }
+        match f(Path::new("")) {
+            Some(mtime) => set_mtime(root, mtime),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::super::*;
+
+    #[test]
+    fn test_touch() {
+        let temp_dir = TempDir::new("test_touch").unwrap();
+        let mtime = UNIX_EPOCH + Duration::from_secs(1000000000);
+        temp_dir.touch("new", mtime).unwrap();
+        assert_eq!(fs::metadata(&temp_dir.path().join("new")).unwrap().modified().unwrap(),
+                   mtime);
+        assert!(temp_dir.touch("../outside", mtime).is_err());
+    }
+
+    #[test]
+    fn test_normalize_mtimes() {
+        let temp_dir = TempDir::new("test_normalize_mtimes").unwrap();
+        fs::create_dir(&temp_dir.path().join("a")).unwrap();
+        temp_dir.write("a/b", b"b").unwrap();
+        let mtime = UNIX_EPOCH + Duration::from_secs(86400);
+        temp_dir.normalize_mtimes(mtime).unwrap();
+        for rel in ["", "a", "a/b"].iter() {
+            let path = temp_dir.path().join(rel);
+            assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), mtime);
+        }
+    }
+}
@@ -0,0 +1,129 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Limits on the length of a single file name, which are lower than the
+//! usual 255 bytes on some file systems (e.g. 143 bytes on eCryptfs).
+
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::Path;
+
+use Error;
+
+/// Returns the longest file name, in bytes, that the file system holding
+/// `dir` accepts, if it can be determined.
+#[cfg(unix)]
+pub fn name_max(dir: &Path) -> Option<usize> {
+    use libc;
+    use std::ffi::{AsOsStr, CString};
+    use std::os::unix::OsStrExt;
+
+    let dir = match CString::new(dir.as_os_str().as_bytes()) {
+        Ok(dir) => dir,
+        Err(_) => return None,
+    };
+    match unsafe { libc::pathconf(dir.as_ptr(), libc::_PC_NAME_MAX) } {
+        n if n > 0 => Some(n as usize),
+        _ => None,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn name_max(_dir: &Path) -> Option<usize> {
+    None
+}
+
+/// The name length limits of common file systems, longest first.
+const COMMON_LIMITS: &'static [usize] = &[255, 143];
+
+/// Returns the longest common file system limit below `len` bytes, to try
+/// after a name of that length was rejected without knowing the real limit.
+pub fn common_limit_below(len: usize) -> Option<usize> {
+    COMMON_LIMITS.iter().cloned().find(|&limit| limit < len)
+}
+
+/// Returns whether `err` says that a file name was too long.
+#[cfg(unix)]
+pub fn is_too_long(err: &io::Error) -> bool {
+    use libc;
+
+    err.raw_os_error() == Some(libc::ENAMETOOLONG)
+}
+
+#[cfg(not(unix))]
+pub fn is_too_long(_err: &io::Error) -> bool {
+    false
+}
+
+/// Shortens `prefix` so that a name made of it and `rest_len` more bytes
+/// fits within `limit`. At least one byte of a non-empty prefix is kept,
+/// since dropping it entirely would change how the name looks; if that is
+/// not enough, a `NameTooLong` error for `dir` is returned.
+pub fn fit(dir: &Path, prefix: &OsStr, rest_len: usize, limit: usize) -> io::Result<OsString> {
+    if prefix.len() + rest_len <= limit {
+        return Ok(prefix.to_os_string());
+    }
+    let too_long = || Error::NameTooLong(dir.to_path_buf(), limit).into_io_error();
+    if prefix.len() == 0 || rest_len >= limit {
+        return Err(too_long());
+    }
+    truncate(prefix, limit - rest_len).ok_or_else(too_long)
+}
+
+/// Returns the longest non-empty prefix of `s` of at most `max` bytes which
+/// ends on a character boundary.
+fn truncate(s: &OsStr, max: usize) -> Option<OsString> {
+    match s.to_str() {
+        Some(s) => {
+            let end = (1..max + 1).rev().find(|&i| s.is_char_boundary(i)).unwrap_or(0);
+            if end == 0 { None } else { Some(OsStr::from_str(&s[..end]).to_os_string()) }
+        }
+        None => truncate_bytes(s, max),
+    }
+}
+
+#[cfg(unix)]
+fn truncate_bytes(s: &OsStr, max: usize) -> Option<OsString> {
+    use std::os::unix::OsStrExt;
+
+    Some(<OsStr as OsStrExt>::from_bytes(&s.as_bytes()[..max]).to_os_string())
+}
+
+#[cfg(not(unix))]
+fn truncate_bytes(_s: &OsStr, _max: usize) -> Option<OsString> {
+    None
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::ffi::OsStr;
+    use std::path::Path;
+
+    use super::{common_limit_below, fit};
+
+    #[test]
+    fn test_fit() {
+        let dir = Path::new("/tmp");
+        assert_eq!(fit(dir, OsStr::from_str("prefix"), 4, 10).unwrap(),
+                   OsStr::from_str("prefix").to_os_string());
+        assert_eq!(fit(dir, OsStr::from_str("prefix"), 7, 10).unwrap(),
+                   OsStr::from_str("pre").to_os_string());
+        // Never cut a character in half.
+        assert_eq!(fit(dir, OsStr::from_str("\u{e9}\u{e9}"), 7, 10).unwrap(),
+                   OsStr::from_str("\u{e9}").to_os_string());
+        assert!(fit(dir, OsStr::from_str("prefix"), 10, 10).is_err());
+    }
+
+    #[test]
+    fn test_common_limit_below() {
+        assert_eq!(common_limit_below(300), Some(255));
+        assert_eq!(common_limit_below(255), Some(143));
+        assert_eq!(common_limit_below(143), None);
+    }
+}
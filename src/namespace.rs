@@ -0,0 +1,137 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Detection of namespace-private (polyinstantiated) temporary directories.
+
+use std::path::Path;
+
+use super::TempDir;
+
+/// Whether paths below a directory can be seen by other processes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Visibility {
+    /// The directory is part of the global filesystem tree, so other
+    /// processes (subject to permissions) see the same entries.
+    Global,
+    /// The directory belongs to a private mount namespace, such as a
+    /// polyinstantiated `/tmp` set up by `pam_namespace` or systemd's
+    /// `PrivateTmp=`. Paths below it mean something else, or nothing, to
+    /// system daemons and other users.
+    NamespacePrivate,
+    /// Visibility could not be determined.
+    Unknown,
+}
+
+/// Returns whether paths below `path` are visible to other processes.
+///
+/// On Linux this looks for the signature of a polyinstantiated directory: the
+/// mount covering `path` is a bind mount of a subdirectory, and either the
+/// process lives in a different mount namespace than `init`, or the mount is
+/// placed over `/tmp` or `/var/tmp`. Other platforms have no such mechanism
+/// and always report `Global`.
+#[cfg(target_os = "linux")]
+pub fn visibility(path: &Path) -> Visibility {
+    use std::fs::{self, File};
+    use std::io::Read;
+
+    let path = match fs::canonicalize(path) {
+        Ok(path) => path,
+        Err(_) => return Visibility::Unknown,
+    };
+    let mut mountinfo = String::new();
+    if File::open("/proc/self/mountinfo").and_then(|mut f| f.read_to_string(&mut mountinfo)).is_err() {
+        return Visibility::Unknown;
+    }
+
+    // Find the mount covering `path`: the one with the longest mount point
+    // that is a prefix of it.
+    let mut covering: Option<(String, String)> = None;
+    for line in mountinfo.lines() {
+        let fields: Vec<&str> = line.split(' ').collect();
+        if fields.len() < 5 { continue; }
+        let root = unescape(fields[3]);
+        let mount_point = unescape(fields[4]);
+        if !path.starts_with(Path::new(&mount_point)) { continue; }
+        let longer = match covering {
+            Some((_, ref current)) => mount_point.len() >= current.len(),
+            None => true,
+        };
+        if longer {
+            covering = Some((root, mount_point));
+        }
+    }
+
+    let (root, mount_point) = match covering {
+        Some(covering) => covering,
+        None => return Visibility::Unknown,
+    };
+    if root == "/" {
+        return Visibility::Global;
+    }
+    let private_ns = match (fs::read_link("/proc/self/ns/mnt"), fs::read_link("/proc/1/ns/mnt")) {
+        (Ok(ours), Ok(init)) => Some(ours != init),
+        _ => None,
+    };
+    match private_ns {
+        Some(true) => Visibility::NamespacePrivate,
+        _ if mount_point == "/tmp" || mount_point == "/var/tmp" => Visibility::NamespacePrivate,
+        Some(false) => Visibility::Global,
+        None => Visibility::Unknown,
+    }
+}
+
+/// Returns whether paths below `path` are visible to other processes.
+#[cfg(not(target_os = "linux"))]
+pub fn visibility(_path: &Path) -> Visibility {
+    Visibility::Global
+}
+
+/// Decodes the octal escapes (`\040` for a space, ...) used in mountinfo.
+#[cfg(target_os = "linux")]
+pub fn unescape(field: &str) -> String {
+    use std::str;
+
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 3 < bytes.len() {
+            let digits = str::from_utf8(&bytes[i + 1..i + 4]).unwrap_or("");
+            if let Ok(b) = u8::from_str_radix(digits, 8) {
+                out.push(b);
+                i += 4;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+impl TempDir {
+
+    /// Returns whether the temporary directory is visible to other processes
+    /// and users, or only inside a private mount namespace. Paths that are
+    /// handed to system daemons should be created somewhere `Global`.
+    pub fn visibility(&self) -> Visibility {
+        visibility(self.path())
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod test {
+
+    use super::unescape;
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape("/mnt/with\\040space"), "/mnt/with space");
+        assert_eq!(unescape("/tmp"), "/tmp");
+    }
+}
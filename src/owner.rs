@@ -0,0 +1,200 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The owner manifest written by `Builder::record_owner`.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use Error;
+
+/// The name of the owner manifest inside a temporary directory.
+pub const OWNER_FILE: &'static str = ".tempdir-owner";
+
+/// The version of the owner manifest format written by this crate.
+pub const OWNER_FORMAT_VERSION: u32 = 1;
+
+const MAGIC: &'static str = "tempdir-owner";
+
+/// The contents of an owner manifest, which `Builder::record_owner` writes to
+/// the root of each directory it creates.
+///
+/// The manifest is meant to be read by cleanup tools written in any
+/// language, so its format is fixed: a UTF-8 text file named
+/// `.tempdir-owner` at the root of the temporary directory, whose first line
+/// is `tempdir-owner 1` (the format version), followed by `key=value` lines:
+///
+/// ```text
+/// tempdir-owner 1
+/// pid=4242
+/// created=1425168000
+/// host=buildbox
+/// keep=false
+/// ```
+///
+/// * `pid` is the id of the creating process.
+/// * `created` is the creation time, in seconds since the Unix epoch.
+/// * `host` is the host name of the machine the process ran on, and may be
+///   missing.
+/// * `keep` is `true` if the directory is never removed by its owner, and
+///   must then be left alone by cleanup tools too.
+///
+/// Readers must ignore keys they do not know, and must treat a directory
+/// whose manifest has a higher version, or cannot be parsed, as not
+/// collectable. A directory may be collected once no process `pid` is alive
+/// on `host`. The manifest is replaced atomically, so it is never seen half
+/// written. A file holding nothing but a process id is read as a manifest
+/// with only `pid` set.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OwnerRecord {
+    /// The id of the process that created the directory.
+    pub pid: u32,
+    /// When the directory was created, in seconds since the Unix epoch.
+    pub created: Option<u64>,
+    /// The host name of the machine the creating process ran on.
+    pub host: Option<String>,
+    /// Whether the directory is meant to outlive its creator.
+    pub keep: bool,
+}
+
+impl OwnerRecord {
+
+    /// Returns a record naming the current process as the owner, created
+    /// now.
+    pub fn current(keep: bool) -> OwnerRecord {
+        OwnerRecord {
+            pid: process::id(),
+            created: SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs()),
+            host: host_name(),
+            keep: keep,
+        }
+    }
+
+    /// Parses the contents of a manifest.
+    pub fn parse(contents: &str) -> io::Result<OwnerRecord> {
+        let invalid = |detail: &str| Error::InvalidManifest(detail.to_string()).into_io_error();
+        let mut lines = contents.lines();
+        let first = lines.next().unwrap_or("").trim();
+        if let Ok(pid) = first.parse() {
+            return Ok(OwnerRecord { pid: pid, created: None, host: None, keep: false });
+        }
+        let mut words = first.split(' ');
+        match (words.next(), words.next().and_then(|v| v.parse::<u32>().ok())) {
+            (Some(MAGIC), Some(version)) if version <= OWNER_FORMAT_VERSION => (),
+            (Some(MAGIC), Some(_)) => return Err(invalid("unsupported version")),
+            _ => return Err(invalid("missing header")),
+        }
+        let mut record = OwnerRecord { pid: 0, created: None, host: None, keep: false };
+        let mut pid = None;
+        for line in lines {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = match line.find('=') {
+                Some(i) => (&line[..i], &line[i + 1..]),
+                None => return Err(invalid(line)),
+            };
+            match key {
+                "pid" => pid = Some(try!(value.parse().map_err(|_| invalid(line)))),
+                "created" => {
+                    record.created = Some(try!(value.parse().map_err(|_| invalid(line))));
+                }
+                "host" => record.host = Some(value.to_string()),
+                "keep" => record.keep = try!(value.parse().map_err(|_| invalid(line))),
+                _ => (),
+            }
+        }
+        record.pid = try!(pid.ok_or_else(|| invalid("missing pid")));
+        Ok(record)
+    }
+
+    /// Reads the manifest of the directory `dir`, returning `None` if it
+    /// has none.
+    pub fn read_from(dir: &Path) -> io::Result<Option<OwnerRecord>> {
+        let mut contents = String::new();
+        match File::open(&dir.join(OWNER_FILE)) {
+            Ok(mut file) => try!(file.read_to_string(&mut contents)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        OwnerRecord::parse(&contents).map(Some)
+    }
+
+    /// Writes the record as the manifest of the directory `dir`, replacing
+    /// any previous one atomically.
+    pub fn write_to(&self, dir: &Path) -> io::Result<()> {
+        let staging = dir.join(&format!("{}.{}.tmp", OWNER_FILE, process::id()));
+        let result = File::create(&staging).and_then(|mut file| {
+            file.write_all(self.to_string().as_bytes())
+        }).and_then(|_| fs::rename(&staging, &dir.join(OWNER_FILE)));
+        if result.is_err() {
+            let _ = fs::remove_file(&staging);
+        }
+        result
+    }
+}
+
+/// Formats the record as the contents of a manifest.
+impl fmt::Display for OwnerRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{} {}\npid={}\n", MAGIC, OWNER_FORMAT_VERSION, self.pid));
+        if let Some(created) = self.created {
+            try!(write!(f, "created={}\n", created));
+        }
+        if let Some(ref host) = self.host {
+            try!(write!(f, "host={}\n", host));
+        }
+        write!(f, "keep={}\n", self.keep)
+    }
+}
+
+/// Returns the host name of this machine.
+#[cfg(unix)]
+pub fn host_name() -> Option<String> {
+    use libc;
+
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8(buf[..len].to_vec()).ok()
+}
+
+#[cfg(not(unix))]
+pub fn host_name() -> Option<String> {
+    use std::env;
+
+    env::var("COMPUTERNAME").ok()
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::super::*;
+
+    #[test]
+    fn test_owner_record_round_trip() {
+        let temp_dir = TempDir::new("test_owner_record_round_trip").unwrap();
+        assert_eq!(OwnerRecord::read_from(temp_dir.path()).unwrap(), None);
+        let record = OwnerRecord::current(true);
+        record.write_to(temp_dir.path()).unwrap();
+        assert_eq!(OwnerRecord::read_from(temp_dir.path()).unwrap(), Some(record));
+
+        let parsed = OwnerRecord::parse("tempdir-owner 1\npid=7\nfuture=1\n").unwrap();
+        assert_eq!((parsed.pid, parsed.created, parsed.keep), (7, None, false));
+        assert_eq!(OwnerRecord::parse("4242\n").unwrap().pid, 4242);
+        assert!(OwnerRecord::parse("tempdir-owner 2\npid=7\n").is_err());
+        assert!(OwnerRecord::parse("tempdir-owner 1\n").is_err());
+    }
+}
@@ -0,0 +1,109 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use Error;
+use walk::walk;
+
+/// Removes the directory at `path` and everything inside of it, unlinking
+/// files from `threads` threads at once. This is much faster than
+/// `fs::remove_dir_all` for trees of many small files on file systems (and
+/// disks) that handle concurrent metadata updates well.
+///
+/// The tree is listed first; its files are then split between the threads,
+/// and the directories are removed last, deepest first. Symbolic links are
+/// removed, never followed. If anything cannot be removed, the first error
+/// is returned once all threads are done.
+pub fn remove_dir_all_parallel(path: &Path, threads: usize) -> io::Result<()> {
+    try!(clear_parallel(path, threads));
+    fs::remove_dir(path)
+}
+
+/// Removes everything inside of `path` like `remove_dir_all_parallel`, but
+/// not `path` itself.
+pub fn clear_parallel(path: &Path, threads: usize) -> io::Result<()> {
+    let mut files = Vec::new();
+    let mut dirs = Vec::new();
+    for entry in try!(walk(path)).into_iter() {
+        if entry.metadata.is_dir() {
+            dirs.push(entry.path);
+        } else {
+            files.push(entry.path);
+        }
+    }
+
+    let threads = if threads == 0 { 1 } else { threads };
+    let chunk = (files.len() + threads - 1) / threads;
+    let mut handles = Vec::new();
+    while !files.is_empty() {
+        let rest = files.split_off(if files.len() > chunk { chunk } else { files.len() });
+        let mine: Vec<PathBuf> = files;
+        files = rest;
+        handles.push(thread::spawn(move || -> io::Result<()> {
+            for file in mine.iter() {
+                try!(fs::remove_file(file));
+            }
+            Ok(())
+        }));
+    }
+    let mut result = Ok(());
+    for handle in handles.into_iter() {
+        let r = handle.join().unwrap_or_else(|_| Err(Error::Panicked.into_io_error()));
+        if result.is_ok() {
+            result = r;
+        }
+    }
+    try!(result);
+
+    // Walked in pre-order, so reversed every directory comes after its
+    // contents.
+    for dir in dirs.iter().rev() {
+        try!(fs::remove_dir(dir));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs::{self, File, PathExt};
+
+    use super::super::*;
+
+    #[test]
+    fn test_remove_dir_all_parallel() {
+        let temp_dir = TempDir::new("test_remove_dir_all_parallel").unwrap();
+        let path = temp_dir.into_inner();
+        for d in 0..3 {
+            let dir = path.join(&format!("d{}", d));
+            fs::create_dir(&dir).unwrap();
+            for f in 0..10 {
+                File::create(&dir.join(&format!("f{}", f))).unwrap();
+            }
+        }
+        remove_dir_all_parallel(&path, 4).unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_parallel_cleanup() {
+        let mut temp_dir = TempDir::new("test_parallel_cleanup").unwrap();
+        temp_dir.set_cleanup(Cleanup::Parallel(2));
+        let path = temp_dir.path().to_path_buf();
+        fs::create_dir(&path.join("a")).unwrap();
+        File::create(&path.join("a/b")).unwrap();
+        temp_dir.clear().unwrap();
+        assert_eq!(fs::read_dir(&path).unwrap().count(), 0);
+        temp_dir.close().unwrap();
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,122 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fs::{self, Permissions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::{Error, TempDir};
+use walk::{relative, walk};
+
+/// The permissions of a directory and everything inside of it, as they were
+/// when the snapshot was taken.
+pub struct Snapshot {
+    /// Relative paths in pre-order, starting with the root itself as the
+    /// empty path, so that directories are restored before their contents.
+    entries: Vec<(PathBuf, Permissions)>,
+}
+
+impl Snapshot {
+
+    /// Records the permissions of `root` and every entry below it. Symbolic
+    /// links are skipped, since their own permissions are meaningless.
+    pub fn take(root: &Path) -> io::Result<Snapshot> {
+        let mut entries = vec![(PathBuf::new(""), try!(fs::metadata(root)).permissions())];
+        for entry in try!(walk(root)).into_iter() {
+            if entry.metadata.file_type().is_symlink() {
+                continue;
+            }
+            entries.push((relative(root, &entry.path).to_path_buf(),
+                          entry.metadata.permissions()));
+        }
+        Ok(Snapshot { entries: entries })
+    }
+
+    /// Puts the recorded permissions back. Entries removed since the
+    /// snapshot are ignored and entries created since are left alone.
+    pub fn restore(&self, root: &Path) -> io::Result<()> {
+        for &(ref rel, ref perms) in self.entries.iter() {
+            let path = if rel.as_os_str().is_empty() { root.to_path_buf() }
+                       else { root.join(rel) };
+            match fs::set_permissions(&path, perms.clone()) {
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+                r => try!(r),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TempDir {
+
+    /// Adopts the existing directory at `path` like `from_path`, after
+    /// recording the permissions of everything inside of it so that they can
+    /// be put back with `restore_permissions`.
+    ///
+    /// Like any adopted directory it is removed once the wrapper is
+    /// destroyed; set the retention policy to `RetentionPolicy::Always` to
+    /// keep a fixture around instead.
+    pub fn from_path_snapshot(path: &Path) -> io::Result<TempDir> {
+        let mut dir = TempDir::from_path(path);
+        try!(dir.snapshot_permissions());
+        Ok(dir)
+    }
+
+    /// Records the permissions of everything inside of the temporary
+    /// directory, replacing any earlier snapshot.
+    pub fn snapshot_permissions(&mut self) -> io::Result<()> {
+        self.permissions = Some(try!(Snapshot::take(self.path())));
+        Ok(())
+    }
+
+    /// Restores the permissions recorded by `from_path_snapshot` or
+    /// `snapshot_permissions`, undoing any `chmod` done since.
+    ///
+    /// Fails with `InvalidInput` if no snapshot was taken.
+    pub fn restore_permissions(&self) -> io::Result<()> {
+        match self.permissions {
+            Some(ref snapshot) => snapshot.restore(self.path()),
+            None => Err(Error::NoSnapshot(self.path().to_path_buf()).into_io_error()),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+
+    use super::super::*;
+
+    fn mode(path: &Path) -> u32 {
+        fs::metadata(path).unwrap().permissions().mode() & 0o777
+    }
+
+    #[test]
+    fn test_restore_permissions() {
+        let fixture = TempDir::new("test_restore_permissions").unwrap();
+        let sub = fixture.path().join("sub");
+        fs::create_dir(&sub).unwrap();
+        fs::set_permissions(&sub, fs::Permissions::from_mode(0o750)).unwrap();
+        let file = fixture.write("sub/a", b"a").unwrap().path().to_path_buf();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o640)).unwrap();
+
+        let mut adopted = TempDir::from_path_snapshot(fixture.path()).unwrap();
+        adopted.set_retention(RetentionPolicy::Always);
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o400)).unwrap();
+        fs::set_permissions(&sub, fs::Permissions::from_mode(0o500)).unwrap();
+        adopted.restore_permissions().unwrap();
+        assert_eq!(mode(&file), 0o640);
+        assert_eq!(mode(&sub), 0o750);
+
+        let fresh = TempDir::new("test_restore_permissions").unwrap();
+        assert!(fresh.restore_permissions().is_err());
+    }
+}
@@ -0,0 +1,149 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::borrow::Cow;
+use std::ffi::{AsOsStr, OsStr};
+use std::fmt;
+use std::io;
+use std::ops::Deref;
+
+use Error;
+
+/// The longest prefix, in bytes, that a `Prefix` accepts.
+pub const MAX_PREFIX_LEN: usize = 64;
+
+/// A validated prefix for temporary directory and file names.
+///
+/// A `Prefix` is at most `MAX_PREFIX_LEN` bytes long and contains no path
+/// separators, nul bytes or, on Windows, characters Windows does not allow
+/// in file names. Defining the prefixes of a code base in one place keeps
+/// its naming conventions there too:
+///
+/// ```no_run
+/// #[macro_use] extern crate lazy_static;
+/// extern crate tempdir;
+///
+/// use tempdir::{Builder, Prefix, TempDir};
+///
+/// lazy_static! {
+///     static ref FIXTURES: Prefix = Prefix::from_static("fixtures");
+/// }
+///
+/// # fn main() {
+/// let a = TempDir::new(&*FIXTURES).unwrap();
+/// let b = Builder::new().prefix(&*FIXTURES).tempdir().unwrap();
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Prefix(Cow<'static, str>);
+
+impl Prefix {
+
+    /// Validates `prefix`, failing with `InvalidInput` if it cannot be used.
+    pub fn new(prefix: &str) -> io::Result<Prefix> {
+        match check(prefix.as_bytes()) {
+            None => Ok(Prefix(Cow::Owned(prefix.to_string()))),
+            Some(reason) => {
+                Err(Error::InvalidPrefix(format!("{:?}: {}", prefix, reason)).into_io_error())
+            }
+        }
+    }
+
+    /// Validates `prefix` like `new`, but without copying it, and panics if
+    /// it cannot be used. Meant for prefixes written into the program, for
+    /// which an invalid prefix is a bug.
+    pub fn from_static(prefix: &'static str) -> Prefix {
+        match check(prefix.as_bytes()) {
+            None => Prefix(Cow::Borrowed(prefix)),
+            Some(reason) => panic!("invalid temporary directory prefix {:?}: {}", prefix, reason),
+        }
+    }
+
+    /// Returns the prefix as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Returns why `prefix` cannot be used, if it cannot.
+fn check(prefix: &[u8]) -> Option<&'static str> {
+    if prefix.len() > MAX_PREFIX_LEN {
+        Some("too long")
+    } else if prefix.iter().any(|&b| forbidden(b)) {
+        Some("contains a character not allowed in file names")
+    } else {
+        None
+    }
+}
+
+#[cfg(unix)]
+fn forbidden(b: u8) -> bool {
+    b == b'/' || b == 0
+}
+
+#[cfg(windows)]
+fn forbidden(b: u8) -> bool {
+    match b {
+        0...31 | b'/' | b'\\' | b':' | b'*' | b'?' | b'"' | b'<' | b'>' | b'|' => true,
+        _ => false,
+    }
+}
+
+impl Deref for Prefix {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsOsStr for Prefix {
+    fn as_os_str(&self) -> &OsStr {
+        OsStr::from_str(self.as_str())
+    }
+}
+
+impl fmt::Display for Prefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::io;
+
+    use super::super::*;
+
+    lazy_static! {
+        static ref STATIC: Prefix = Prefix::from_static("test_prefix_static");
+    }
+
+    #[test]
+    fn test_prefix() {
+        let temp_dir = TempDir::new(&*STATIC).unwrap();
+        let name = temp_dir.path().file_name().unwrap().to_str().unwrap().to_string();
+        assert!(name.starts_with("test_prefix_static"));
+        let temp_dir = Builder::new().prefix(&Prefix::new("test_prefix").unwrap())
+                                     .tempdir()
+                                     .unwrap();
+        assert!(temp_dir.path().file_name().unwrap().to_str().unwrap().starts_with("test_prefix"));
+
+        assert_eq!(Prefix::new("a/b").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        let long: String = (0..MAX_PREFIX_LEN + 1).map(|_| 'x').collect();
+        assert!(Prefix::new(&long).is_err());
+        assert_eq!(Prefix::new("ok").unwrap().as_str(), "ok");
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid temporary directory prefix")]
+    fn test_prefix_from_static_panics() {
+        Prefix::from_static("a/b");
+    }
+}
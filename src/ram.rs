@@ -0,0 +1,86 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Finding a memory-backed base directory for `Builder::prefer_ram`.
+
+use std::path::{Path, PathBuf};
+
+/// Returns the first memory-backed directory that can be written to, if
+/// any.
+#[cfg(target_os = "linux")]
+pub fn ram_dir() -> Option<PathBuf> {
+    use std::env;
+
+    use resolve::writable;
+    use super::temp_dir;
+
+    let mut candidates = vec![PathBuf::new("/dev/shm")];
+    if let Some(runtime) = env::var_os("XDG_RUNTIME_DIR") {
+        if !runtime.is_empty() {
+            candidates.push(PathBuf::new(&runtime));
+        }
+    }
+    candidates.push(temp_dir());
+    candidates.into_iter().find(|dir| is_memory_backed(dir) && writable(dir))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn ram_dir() -> Option<PathBuf> {
+    None
+}
+
+/// Returns whether `dir` is on a tmpfs or ramfs file system.
+#[cfg(target_os = "linux")]
+pub fn is_memory_backed(dir: &Path) -> bool {
+    use libc;
+    use std::ffi::{AsOsStr, CString};
+    use std::mem;
+    use std::os::unix::OsStrExt;
+
+    const TMPFS_MAGIC: i64 = 0x01021994;
+    const RAMFS_MAGIC: i64 = 0x858458f6;
+
+    let dir = match CString::new(dir.as_os_str().as_bytes()) {
+        Ok(dir) => dir,
+        Err(_) => return false,
+    };
+    let mut buf: libc::statfs = unsafe { mem::zeroed() };
+    if unsafe { libc::statfs(dir.as_ptr(), &mut buf) } != 0 {
+        return false;
+    }
+    let kind = buf.f_type as i64;
+    kind == TMPFS_MAGIC || kind == RAMFS_MAGIC
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn is_memory_backed(_dir: &Path) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::super::*;
+    use super::{is_memory_backed, ram_dir};
+    use testing::lock_globals;
+
+    #[test]
+    fn test_prefer_ram() {
+        // The fallback is `temp_dir()`, which other tests reconfigure.
+        let _globals = lock_globals();
+        let temp_dir = Builder::new().prefix("test_prefer_ram").prefer_ram(true).tempdir().unwrap();
+        let parent = temp_dir.path().parent().unwrap();
+        match ram_dir() {
+            Some(ram) => {
+                assert_eq!(parent, &*ram);
+                assert!(is_memory_backed(temp_dir.path()));
+            }
+            None => assert_eq!(parent, &*super::super::temp_dir()),
+        }
+    }
+}
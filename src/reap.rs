@@ -0,0 +1,222 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Removal of temporary directories left behind by crashed processes.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
+
+use clock::{Clock, SystemClock};
+use owner::host_name;
+use super::{OwnerRecord, temp_dir};
+
+/// Returns the process id in the part of a name made by
+/// `Builder::owner_names` that follows the prefix, e.g. `.4242.1425168000.x8Hq`.
+fn owner_from_name(rest: &str) -> Option<u32> {
+    let mut parts = rest.trim_left_matches(|c: char| !c.is_alphanumeric()).split('.');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(pid), Some(created), Some(_)) if created.parse::<u64>().is_ok() => pid.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Removes the directories in `temp_dir()` whose names start with `prefix`
+/// and which were last modified at least `older_than` ago, unless the
+/// process recorded in them by `Builder::record_owner`, or in their name by
+/// `Builder::owner_names`, is still running. Directories with neither a
+/// manifest nor a `prefix.PID.TIMESTAMP.*` name are never removed, as they
+/// may have nothing to do with this crate; nor are those whose owner
+/// manifest says to keep them, or names another host.
+///
+/// Returns the directories that were removed. Entries that cannot be
+/// inspected or removed, e.g. because they belong to another user, are
+/// skipped.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// // Clean up after test runs that crashed more than a day ago.
+/// for path in tempdir::reap("ci-test", Duration::from_secs(24 * 60 * 60)).unwrap() {
+///     println!("removed {}", path.display());
+/// }
+/// ```
+pub fn reap(prefix: &str, older_than: Duration) -> io::Result<Vec<PathBuf>> {
+    reap_in(&temp_dir(), prefix, older_than)
+}
+
+/// Removes stale directories in `dir` like `reap` does in `temp_dir()`.
+pub fn reap_in(dir: &Path, prefix: &str, older_than: Duration) -> io::Result<Vec<PathBuf>> {
+    reap_in_with_clock(dir, prefix, older_than, &SystemClock)
+}
+
+/// Removes stale directories in `dir` like `reap_in`, telling their age by
+/// `clock`, e.g. a `MockClock` in tests.
+pub fn reap_in_with_clock(dir: &Path, prefix: &str, older_than: Duration, clock: &Clock)
+                          -> io::Result<Vec<PathBuf>> {
+    let now = clock.now();
+    let mut removed = Vec::new();
+    for entry in try!(fs::read_dir(dir)) {
+        let entry = try!(entry);
+        let name = entry.file_name();
+        let rest = match name.to_str() {
+            Some(name) if name.starts_with(prefix) => &name[prefix.len()..],
+            _ => continue,
+        };
+        // Never follow a symbolic link out of `dir`.
+        let metadata = match fs::symlink_metadata(&entry.path()) {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        if !metadata.is_dir() {
+            continue;
+        }
+        let stale = match metadata.modified().map(|m| now.duration_since(m)) {
+            Ok(Ok(age)) => age >= older_than,
+            _ => false,
+        };
+        let path = entry.path();
+        if !stale {
+            continue;
+        }
+        let owner = match OwnerRecord::read_from(&path) {
+            // Neither kept, nor possibly alive on another machine.
+            Ok(Some(ref record)) if record.keep => continue,
+            Ok(Some(ref record)) if record.host.is_some() && record.host != host_name() => continue,
+            Ok(Some(record)) => Some(record.pid),
+            Ok(None) => owner_from_name(rest),
+            // An unreadable or newer manifest; leave it to someone who knows.
+            Err(_) => continue,
+        };
+        // Without a manifest or an owner in its name, the directory may
+        // well not be ours at all.
+        let owner = match owner {
+            Some(owner) => owner,
+            None => continue,
+        };
+        if is_running(owner) {
+            continue;
+        }
+        if fs::remove_dir_all(&path).is_ok() {
+            removed.push(path);
+        }
+    }
+    Ok(removed)
+}
+
+/// Returns whether a process with id `pid` exists.
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    use libc;
+
+    if pid == process::id() {
+        return true;
+    }
+    // Signal 0 only checks whether the process could be signalled; EPERM
+    // means it exists but belongs to someone else.
+    unsafe { libc::kill(pid as libc::pid_t, 0) == 0 } ||
+        io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(windows)]
+fn is_running(pid: u32) -> bool {
+    const SYNCHRONIZE: u32 = 0x00100000;
+    const ERROR_INVALID_PARAMETER: i32 = 87;
+    const WAIT_OBJECT_0: u32 = 0;
+
+    extern "system" {
+        fn OpenProcess(access: u32, inherit: i32, pid: u32) -> *mut u8;
+        fn WaitForSingleObject(handle: *mut u8, millis: u32) -> u32;
+        fn CloseHandle(handle: *mut u8) -> i32;
+    }
+
+    unsafe {
+        let handle = OpenProcess(SYNCHRONIZE, 0, pid);
+        if handle.is_null() {
+            return io::Error::last_os_error().raw_os_error() != Some(ERROR_INVALID_PARAMETER);
+        }
+        let exited = WaitForSingleObject(handle, 0) == WAIT_OBJECT_0;
+        CloseHandle(handle);
+        !exited
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn is_running(_pid: u32) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs::{self, File, PathExt};
+    use std::io::Write;
+    use std::process;
+    use std::time::Duration;
+
+    use super::super::*;
+    use super::owner_from_name;
+
+    #[test]
+    fn test_owner_from_name() {
+        assert_eq!(owner_from_name(".4242.1425168000.x8Hq2Lw0aBcD"), Some(4242));
+        assert_eq!(owner_from_name("-4242.1425168000.x8Hq2Lw0aBcD.work"), Some(4242));
+        assert_eq!(owner_from_name(".x8Hq2Lw0aBcD"), None);
+    }
+
+    #[test]
+    fn test_reap_in() {
+        let base = TempDir::new("test_reap_in").unwrap();
+        let owned = Builder::new().prefix("crashed").record_owner(true)
+                                  .tempdir_in(base.path())
+                                  .unwrap();
+        assert!(owned.path().join(OWNER_FILE).exists());
+        let orphan = base.path().join("crashed.orphan");
+        fs::create_dir(&orphan).unwrap();
+        let dead = base.path().join("crashed.dead");
+        fs::create_dir(&dead).unwrap();
+        // Far beyond any real process id.
+        write!(File::create(&dead.join(OWNER_FILE)).unwrap(), "{}", 0x7ffffff0u32).unwrap();
+        let other = base.path().join("other.orphan");
+        fs::create_dir(&other).unwrap();
+        let named = base.path().join(&format!("crashed.{}.1425168000.x8Hq", process::id()));
+        fs::create_dir(&named).unwrap();
+
+        let mut removed = reap_in(base.path(), "crashed", Duration::from_secs(0)).unwrap();
+        removed.sort();
+        assert_eq!(removed, vec![dead.clone()]);
+        assert!(owned.path().exists());
+        assert!(orphan.exists());
+        assert!(other.exists());
+        assert!(named.exists());
+
+        // Nothing is old enough.
+        fs::create_dir(&dead).unwrap();
+        write!(File::create(&dead.join(OWNER_FILE)).unwrap(), "{}", 0x7ffffff0u32).unwrap();
+        assert!(reap_in(base.path(), "crashed", Duration::from_secs(3600)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_reap_in_with_clock() {
+        let base = TempDir::new("test_reap_in_with_clock").unwrap();
+        let dead = base.path().join("crashed.dead");
+        fs::create_dir(&dead).unwrap();
+        write!(File::create(&dead.join(OWNER_FILE)).unwrap(), "{}", 0x7ffffff0u32).unwrap();
+        let modified = fs::metadata(&dead).unwrap().modified().unwrap();
+        let ttl = Duration::from_secs(3600);
+
+        let clock = MockClock::new(modified + ttl - Duration::from_secs(1));
+        assert!(reap_in_with_clock(base.path(), "crashed", ttl, &clock).unwrap().is_empty());
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(reap_in_with_clock(base.path(), "crashed", ttl, &clock).unwrap(), vec![dead]);
+    }
+}
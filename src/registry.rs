@@ -0,0 +1,333 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The opt-in registry of live temporary directories which are removed when
+//! the process exits without running destructors, or when a unit of work run
+//! by `isolate` finishes.
+
+use libc;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, ATOMIC_BOOL_INIT, ATOMIC_USIZE_INIT, Ordering};
+use std::sync::{Arc, Mutex, Once, ONCE_INIT};
+use std::thread;
+use std::time::SystemTime;
+
+use cleanup::{Cleanup, Filter};
+use clock::Clock;
+use identity::Identity;
+use super::{RetentionPolicy, TempDir};
+
+static ENABLED: AtomicBool = ATOMIC_BOOL_INIT;
+static NEXT_ID: AtomicUsize = ATOMIC_USIZE_INIT;
+static AT_EXIT: Once = ONCE_INIT;
+
+/// The `isolate` calls in progress on a thread, innermost last, each with
+/// the ids of the directories the thread created while it ran.
+thread_local!(static ISOLATED: RefCell<Vec<Vec<usize>>> = RefCell::new(Vec::new()));
+
+lazy_static! {
+    static ref LIVE: Mutex<HashMap<usize, Entry>> = Mutex::new(HashMap::new());
+}
+
+/// What the registry needs to remove a directory the way its `TempDir` would
+/// when dropped: the identity it is checked against, the cleanup strategy
+/// and filter, and the retention policy.
+pub struct Entry {
+    path: PathBuf,
+    identity: Option<Identity>,
+    retention: RetentionPolicy,
+    created: SystemTime,
+    clock: Arc<Clock>,
+    cleanup: Cleanup,
+    filter: Option<Filter>,
+    quarantine: Option<PathBuf>,
+    removed_by_owner: Option<Arc<AtomicBool>>,
+}
+
+impl Entry {
+
+    /// Records the settings of `dir` that decide how it is removed.
+    pub fn of(dir: &TempDir) -> Entry {
+        Entry {
+            path: dir.path.clone(),
+            identity: dir.identity,
+            retention: dir.retention,
+            created: dir.created,
+            clock: dir.clock.clone(),
+            cleanup: dir.cleanup,
+            filter: dir.hooks.filter.clone(),
+            quarantine: dir.quarantine.clone(),
+            removed_by_owner: dir.removed_by_owner.clone(),
+        }
+    }
+
+    /// Removes the directory like `TempDir::close`. With `failed`, the
+    /// directory is kept if its policy is `OnFailure`: the panic that would
+    /// have kept it has been caught by then.
+    fn remove(self, failed: bool) {
+        if failed && self.retention == RetentionPolicy::OnFailure {
+            let _ = writeln!(&mut io::stderr(),
+                             "tempdir: keeping {} after a panic", self.path.display());
+            return;
+        }
+        let mut dir = TempDir::untracked(self.path, self.identity);
+        dir.retention = self.retention;
+        dir.created = self.created;
+        dir.clock = self.clock;
+        dir.cleanup = self.cleanup;
+        dir.hooks.filter = self.filter;
+        dir.quarantine = self.quarantine;
+        dir.removed_by_owner = self.removed_by_owner;
+        let _ = dir.close();
+    }
+}
+
+/// Starts recording every `TempDir` created or adopted from now on, and
+/// removes those still alive when the process exits through
+/// `std::process::exit` (or by returning from `main`), which does not run
+/// their destructors.
+///
+/// Each directory is removed as its `TempDir` would be when dropped: it is
+/// checked against its recorded identity, removed with its cleanup strategy
+/// and filter, and kept if its retention policy or `TEMPDIR_KEEP` say so.
+/// Nothing can be done for a process that is killed or aborts.
+pub fn register_atexit_cleanup() {
+    ENABLED.store(true, Ordering::SeqCst);
+    AT_EXIT.call_once(|| unsafe { libc::atexit(cleanup_at_exit); });
+}
+
+/// Starts tracking `dir`, if the registry is enabled or the current thread
+/// is running `isolate`. Returns its registration.
+pub fn track(dir: &TempDir) -> Option<usize> {
+    let isolating = ISOLATED.with(|isolated| !isolated.borrow().is_empty());
+    if !ENABLED.load(Ordering::SeqCst) && !isolating {
+        return None;
+    }
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    LIVE.lock().unwrap().insert(id, Entry::of(dir));
+    if isolating {
+        ISOLATED.with(|isolated| isolated.borrow_mut().last_mut().unwrap().push(id));
+    }
+    Some(id)
+}
+
+/// Records the current settings of the directory registered as `id`, or,
+/// with `None`, that it no longer needs to be removed at exit.
+pub fn set(id: Option<usize>, entry: Option<Entry>) {
+    let id = match id {
+        Some(id) => id,
+        None => return,
+    };
+    let mut live = LIVE.lock().unwrap();
+    match entry {
+        Some(entry) => { live.insert(id, entry); }
+        None => { live.remove(&id); }
+    }
+}
+
+/// Returns whether the directory registered as `id` has left the registry.
+/// For a `TempDir` that is still to be removed, this means `isolate` or the
+/// exit cleanup removed it.
+pub fn collected(id: Option<usize>) -> bool {
+    match id {
+        Some(id) => !LIVE.lock().unwrap().contains_key(&id),
+        None => false,
+    }
+}
+
+/// Runs `f`, then removes every `TempDir` the current thread created while
+/// it ran that is still alive, whether it was leaked with `mem::forget`,
+/// stashed somewhere global or is still unwinding. If `f` panics, the
+/// directories are removed as the panic unwinds through the call, which
+/// then carries on as usual.
+///
+/// This includes a `TempDir` that `f` returns or hands to an owner outside
+/// of the call: it cannot be told apart from a leaked one. Its directory is
+/// removed all the same, and once it is dropped or closed (`close_path`
+/// returns `None`), it leaves the path alone. Create directories that must
+/// outlive the call before calling `isolate`.
+///
+/// Only directories created by the calling thread are collected, so units
+/// of work isolated on different threads, like tests run in parallel, do not
+/// interfere; directories created by threads that `f` spawns are not
+/// collected. Directories are removed as at exit (see
+/// `register_atexit_cleanup`), except that those kept on failure are kept if
+/// `f` panicked.
+pub fn isolate<F, R>(f: F) -> R where F: FnOnce() -> R {
+    ISOLATED.with(|isolated| isolated.borrow_mut().push(Vec::new()));
+    let _collect = Collect;
+    f()
+}
+
+/// Removes the directories created during the innermost `isolate` call when
+/// it returns or unwinds.
+struct Collect;
+
+impl Drop for Collect {
+    fn drop(&mut self) {
+        let ids = ISOLATED.with(|isolated| isolated.borrow_mut().pop().unwrap());
+        // Directories made by nested calls were removed by them already, but
+        // the enclosing call must still collect them if they were not.
+        ISOLATED.with(|isolated| {
+            if let Some(outer) = isolated.borrow_mut().last_mut() {
+                outer.extend(ids.iter().cloned());
+            }
+        });
+        let leaked: Vec<Entry> = {
+            let mut live = LIVE.lock().unwrap();
+            ids.into_iter().filter_map(|id| live.remove(&id)).collect()
+        };
+        remove_live(leaked, thread::panicking());
+    }
+}
+
+extern "C" fn cleanup_at_exit() {
+    // Another thread may have been holding the lock when `exit` was called;
+    // waiting for it would hang the exit.
+    let live: Vec<Entry> = match LIVE.try_lock() {
+        Ok(mut live) => live.drain().map(|(_, entry)| entry).collect(),
+        Err(_) => return,
+    };
+    remove_live(live, false);
+}
+
+/// Removes every directory in the registry right away, e.g. because the
+/// process is about to be terminated by a signal.
+#[cfg(feature = "signal-cleanup")]
+pub fn remove_all() {
+    let live: Vec<Entry> = LIVE.lock().unwrap().drain().map(|(_, entry)| entry).collect();
+    remove_live(live, false);
+}
+
+/// Removes the directories of `live`, which have left the registry (so that
+/// cleanup filters may create directories of their own), going through the
+/// same checks as `TempDir::close`.
+fn remove_live(live: Vec<Entry>, failed: bool) {
+    for entry in live.into_iter() {
+        entry.remove(failed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs::{self, File, PathExt};
+    use std::mem;
+    use std::path::Path;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::super::*;
+    use super::LIVE;
+    use testing::lock_globals;
+
+    #[test]
+    fn test_registry_tracks_live_dirs() {
+        register_atexit_cleanup();
+        let temp_dir = TempDir::new("test_registry_tracks_live_dirs").unwrap();
+        let path = temp_dir.path().to_path_buf();
+        assert!(LIVE.lock().unwrap().values().any(|e| e.path == path));
+        drop(temp_dir);
+        assert!(!LIVE.lock().unwrap().values().any(|e| e.path == path));
+    }
+
+    #[test]
+    fn test_isolate_removes_leaked_dirs() {
+        let _globals = lock_globals();
+        let path = isolate(|| {
+            let temp_dir = TempDir::new("test_isolate_removes_leaked_dirs").unwrap();
+            let path = temp_dir.path().to_path_buf();
+            mem::forget(temp_dir);
+            path
+        });
+        assert!(!path.exists());
+
+        let (tx, rx) = mpsc::channel();
+        let result = thread::spawn(move || isolate(|| {
+            let temp_dir = TempDir::new("test_isolate_removes_leaked_dirs").unwrap();
+            tx.send(temp_dir.path().to_path_buf()).unwrap();
+            mem::forget(temp_dir);
+            panic!("leaked on purpose");
+        })).join();
+        assert!(result.is_err());
+        assert!(!rx.recv().unwrap().exists());
+    }
+
+    #[test]
+    fn test_isolate_ignores_other_threads() {
+        let _globals = lock_globals();
+        let (other, mine) = isolate(|| {
+            let other = thread::spawn(|| {
+                TempDir::new("test_isolate_ignores_other_threads").unwrap()
+            }).join().unwrap();
+            let mine = TempDir::new("test_isolate_ignores_other_threads").unwrap();
+            let path = mine.path().to_path_buf();
+            mem::forget(mine);
+            (other, path)
+        });
+        assert!(other.path().exists());
+        assert!(!mine.exists());
+    }
+
+    #[test]
+    fn test_isolate_collects_returned_dirs() {
+        let _globals = lock_globals();
+        let temp_dir = isolate(|| TempDir::new("test_isolate_collects_returned_dirs").unwrap());
+        let path = temp_dir.path().to_path_buf();
+        assert!(!path.exists());
+
+        // Something else may have taken the path since.
+        fs::create_dir(&path).unwrap();
+        assert_eq!(temp_dir.close_path().unwrap(), None);
+        assert!(path.exists());
+        fs::remove_dir(&path).unwrap();
+    }
+
+    #[test]
+    fn test_isolate_applies_cleanup_settings() {
+        let _globals = lock_globals();
+        let (filtered, kept) = isolate(|| {
+            let mut filtered = TempDir::new("test_isolate_applies_cleanup_settings").unwrap();
+            File::create(&filtered.path().join("core")).unwrap();
+            File::create(&filtered.path().join("scratch")).unwrap();
+            filtered.set_cleanup_filter(|p| {
+                if p == Path::new("core") { Disposition::Keep } else { Disposition::Delete }
+            });
+            let mut kept = TempDir::new("test_isolate_applies_cleanup_settings").unwrap();
+            kept.set_retention(RetentionPolicy::OlderThan(Duration::from_secs(0)));
+            let paths = (filtered.path().to_path_buf(), kept.path().to_path_buf());
+            mem::forget(filtered);
+            mem::forget(kept);
+            paths
+        });
+        assert!(filtered.join("core").exists());
+        assert!(!filtered.join("scratch").exists());
+        assert!(kept.exists());
+        fs::remove_dir_all(&filtered).unwrap();
+        fs::remove_dir_all(&kept).unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let result = thread::spawn(move || isolate(|| {
+            let temp_dir = Builder::new().prefix("test_isolate_applies_cleanup_settings")
+                                         .keep_on_panic(true)
+                                         .tempdir()
+                                         .unwrap();
+            tx.send(temp_dir.path().to_path_buf()).unwrap();
+            mem::forget(temp_dir);
+            panic!("leaked on purpose");
+        })).join();
+        assert!(result.is_err());
+        let on_failure = rx.recv().unwrap();
+        assert!(on_failure.exists());
+        fs::remove_dir_all(&on_failure).unwrap();
+    }
+}
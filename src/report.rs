@@ -0,0 +1,87 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use fallback::Substitution;
+use resolve::{Candidate, Source};
+
+/// How a `TempDir` came to be created where it was, as returned by
+/// `Builder::tempdir_with_report` and `Builder::tempdir_in_with_report`.
+///
+/// Meant for logging and monitoring: the `Display` implementation gives a
+/// short human readable summary.
+#[derive(Clone, Debug)]
+pub struct CreationReport {
+    /// The directory the `TempDir` was created in.
+    pub base: PathBuf,
+    /// The rule that picked the base directory, if it was resolved with
+    /// `temp_dir` rather than given explicitly.
+    pub source: Option<Source>,
+    /// The candidate base directories considered and rejected while
+    /// resolving the base directory.
+    pub rejected: Vec<Candidate>,
+    /// The base directory used instead of the requested one, if
+    /// `Builder::read_only_fallback` had to pick another one.
+    pub substitution: Option<Substitution>,
+    /// How many names were tried, including the one that succeeded.
+    pub attempts: u32,
+    /// How long creation took.
+    pub elapsed: Duration,
+    /// The permission bits the directory was created with, on Unix.
+    pub mode: Option<u32>,
+}
+
+impl fmt::Display for CreationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "created in {} after {} attempt{} in {:?}", self.base.display(),
+                    self.attempts, if self.attempts == 1 { "" } else { "s" }, self.elapsed));
+        if let Some(mode) = self.mode {
+            try!(write!(f, " (mode {:o})", mode));
+        }
+        if let Some(ref source) = self.source {
+            try!(write!(f, "\n  source: {:?}", source));
+        }
+        for c in self.rejected.iter() {
+            if let Some(ref rejection) = c.rejection {
+                try!(write!(f, "\n  rejected {:?}: {:?}", c.source, rejection));
+            }
+        }
+        if let Some(ref s) = self.substitution {
+            try!(write!(f, "\n  substituted for {}: {}", s.requested.display(), s.reason));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs;
+
+    use super::super::*;
+
+    #[test]
+    fn test_builder_report() {
+        let base = TempDir::new("test_builder_report").unwrap();
+        fs::create_dir(&base.path().join("taken-0")).unwrap();
+        let (temp_dir, report) = Builder::new().name_fn(|n| format!("taken-{}", n))
+                                               .tempdir_in_with_report(base.path())
+                                               .unwrap();
+        assert_eq!(temp_dir.path(), &*base.path().join("taken-1"));
+        assert_eq!(report.base, base.path());
+        assert_eq!(report.attempts, 2);
+        assert!(report.substitution.is_none());
+
+        let (_, report) = Builder::new().tempdir_with_report().unwrap();
+        assert!(report.source.is_some());
+        assert!(report.to_string().contains("1 attempt"));
+    }
+}
@@ -0,0 +1,477 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Resolution of the base temporary directory.
+
+use std::env::{self, VarError};
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
+
+use config;
+
+/// The set of rules `temp_dir` uses to pick the base temporary directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rules {
+    /// This crate's own rules, as documented on `temp_dir`. This is the
+    /// default.
+    Crate,
+    /// Whatever `std::env::temp_dir` returns.
+    Std,
+}
+
+/// The rule that produced a resolved base temporary directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Source {
+    /// The value of the named environment variable.
+    Var(&'static str),
+    /// The platform default, used because none of the environment variables
+    /// were set.
+    Default,
+    /// The result of `std::env::temp_dir`.
+    Std,
+    /// A base directory installed for the current thread with `Config`.
+    Override,
+    /// The resolver registered at the given index with `register_resolver`.
+    Resolver(usize),
+    /// The strategy at the given index of those installed with
+    /// `set_strategies`.
+    Strategy(usize),
+}
+
+/// An additional way for `temp_dir` to find the base temporary directory,
+/// installed with `set_strategies`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    /// The directory named by `XDG_RUNTIME_DIR`, which is private to the
+    /// current user and suited to sockets, pid files and other runtime
+    /// files. Only used if it is set to an absolute path.
+    Runtime,
+    /// `/var/tmp` on Unix, which unlike `/tmp` is usually kept across
+    /// reboots and so suits files that must survive a crash. Never used on
+    /// other platforms.
+    Persistent,
+    /// The first of the given directories that is usable.
+    Roots(Vec<PathBuf>),
+}
+
+lazy_static! {
+    static ref STRATEGIES: Mutex<Vec<Strategy>> = Mutex::new(Vec::new());
+}
+
+/// Installs the strategies consulted by `temp_dir`, and therefore
+/// `TempDir::new`, for the whole process, replacing any installed before.
+///
+/// Under `Rules::Crate`, the strategies are tried in order after any
+/// resolvers, and the first one naming an existing directory that a probe
+/// shows can be written to wins. Only if none does are the environment
+/// variables and the platform default consulted.
+///
+/// # Examples
+///
+/// ```no_run
+/// use tempdir::{Strategy, set_strategies};
+///
+/// // A daemon keeping its sockets in the runtime directory if there is one.
+/// set_strategies(vec![Strategy::Runtime, Strategy::Persistent]);
+/// ```
+pub fn set_strategies(strategies: Vec<Strategy>) {
+    *STRATEGIES.lock().unwrap() = strategies;
+}
+
+/// Returns the strategies currently consulted by `temp_dir`.
+pub fn strategies() -> Vec<Strategy> {
+    STRATEGIES.lock().unwrap().clone()
+}
+
+/// Returns the raw values a strategy proposes, with the directory each names
+/// or the reason it cannot be used.
+fn propose(strategy: &Strategy) -> Vec<(Option<OsString>, Result<PathBuf, Rejection>)> {
+    match *strategy {
+        Strategy::Runtime => {
+            let (value, result) = examine("XDG_RUNTIME_DIR");
+            let result = result.and_then(|path| {
+                if path.is_absolute() { Ok(path) } else { Err(Rejection::Unusable) }
+            });
+            vec![(value, result)]
+        }
+        Strategy::Persistent if cfg!(unix) => {
+            let path = PathBuf::new("/var/tmp");
+            vec![(Some(path.as_os_str().to_os_string()), Ok(path))]
+        }
+        Strategy::Persistent => vec![(None, Err(Rejection::Unset))],
+        Strategy::Roots(ref roots) => {
+            roots.iter().map(|root| (Some(root.as_os_str().to_os_string()), Ok(root.clone())))
+                 .collect()
+        }
+    }
+}
+
+static PROBES: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Returns whether `dir` is an existing directory entries can be created in,
+/// by creating and removing one.
+pub fn writable(dir: &Path) -> bool {
+    if !fs::metadata(dir).map(|m| m.is_dir()).unwrap_or(false) {
+        return false;
+    }
+    let probe = dir.join(&format!(".tempdir-probe.{}.{}", process::id(),
+                                  PROBES.fetch_add(1, Ordering::SeqCst)));
+    fs::create_dir(&probe).and_then(|_| fs::remove_dir(&probe)).is_ok()
+}
+
+/// Tries the installed strategies, recording every candidate considered in
+/// `candidates` if given.
+fn try_strategies(mut candidates: Option<&mut Vec<Candidate>>) -> Option<(PathBuf, Source)> {
+    for (i, strategy) in strategies().iter().enumerate() {
+        for (value, result) in propose(strategy).into_iter() {
+            let result = result.and_then(|path| {
+                if writable(&path) { Ok(path) } else { Err(Rejection::Unusable) }
+            });
+            let rejection = result.as_ref().err().cloned();
+            if let Some(ref mut candidates) = candidates {
+                candidates.push(Candidate {
+                    source: Source::Strategy(i),
+                    value: value,
+                    rejection: rejection,
+                });
+            }
+            if let Ok(path) = result {
+                return Some((path, Source::Strategy(i)));
+            }
+        }
+    }
+    None
+}
+
+type Resolver = Arc<Fn() -> Option<PathBuf> + Send + Sync>;
+
+lazy_static! {
+    static ref RESOLVERS: Mutex<Vec<Resolver>> = Mutex::new(Vec::new());
+}
+
+/// Registers a callback consulted by `temp_dir`, and therefore
+/// `TempDir::new`, before the built-in rules.
+///
+/// Resolvers are consulted in the order they were registered, and the first
+/// one returning `Some` wins. This allows the base directory to come from
+/// somewhere the crate knows nothing about, such as the scratch path handed
+/// out by a cluster scheduler.
+pub fn register_resolver<F>(resolver: F)
+    where F: Fn() -> Option<PathBuf> + Send + Sync + 'static
+{
+    RESOLVERS.lock().unwrap().push(Arc::new(resolver));
+}
+
+/// Returns a snapshot of the registered resolvers, so that they can be called
+/// without holding the lock (a resolver may itself call `temp_dir`).
+fn resolvers() -> Vec<Resolver> {
+    RESOLVERS.lock().unwrap().clone()
+}
+
+static RULES: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Selects the rules used by `temp_dir`, and therefore `TempDir::new`, for
+/// the whole process.
+pub fn set_rules(rules: Rules) {
+    RULES.store(rules as usize, Ordering::SeqCst);
+}
+
+/// Returns the rules currently used by `temp_dir`.
+pub fn rules() -> Rules {
+    if RULES.load(Ordering::SeqCst) == Rules::Std as usize {
+        Rules::Std
+    } else {
+        Rules::Crate
+    }
+}
+
+/// Resolves the base temporary directory like `temp_dir`, additionally
+/// reporting which rule produced it.
+pub fn temp_dir_source() -> (PathBuf, Source) {
+    if let Some(root) = config::root() {
+        return (root, Source::Override);
+    }
+    for (i, resolver) in resolvers().iter().enumerate() {
+        if let Some(path) = resolver() {
+            return (path, Source::Resolver(i));
+        }
+    }
+    match rules() {
+        Rules::Std => (env::temp_dir(), Source::Std),
+        Rules::Crate => lookup(),
+    }
+}
+
+/// Why a candidate base directory was not used.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Rejection {
+    /// The environment variable is not set.
+    Unset,
+    /// The environment variable is set to the empty string.
+    Empty,
+    /// The environment variable is not valid unicode.
+    NotUnicode,
+    /// The candidate is usable, but an earlier candidate was chosen.
+    Shadowed,
+    /// The resolver returned `None`.
+    Declined,
+    /// The directory does not exist, cannot be written to, or is not an
+    /// absolute path where one is required.
+    Unusable,
+}
+
+/// A candidate base directory considered during resolution.
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    /// Where the candidate comes from.
+    pub source: Source,
+    /// The raw value read, if any.
+    pub value: Option<OsString>,
+    /// Why the candidate was rejected, or `None` if it was chosen.
+    pub rejection: Option<Rejection>,
+}
+
+/// A report of how the base temporary directory was resolved, as returned by
+/// `diagnose`.
+#[derive(Clone, Debug)]
+pub struct Diagnosis {
+    /// The rules in effect.
+    pub rules: Rules,
+    /// Every candidate considered, in order.
+    pub candidates: Vec<Candidate>,
+    /// The resolved base directory.
+    pub path: PathBuf,
+    /// The rule that produced `path`.
+    pub source: Source,
+}
+
+impl fmt::Display for Diagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(f, "temporary directory: {} (rules: {:?}, source: {:?})",
+                      self.path.display(), self.rules, self.source));
+        for c in self.candidates.iter() {
+            let value = match c.value {
+                Some(ref v) => format!("{:?}", v),
+                None => "<none>".to_string(),
+            };
+            match c.rejection {
+                Some(ref r) => try!(writeln!(f, "  {:?} = {}: rejected ({:?})", c.source, value, r)),
+                None => try!(writeln!(f, "  {:?} = {}: chosen", c.source, value)),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns a report of every candidate considered while resolving the base
+/// temporary directory, and why each was rejected, to help explain where
+/// temporary files end up.
+pub fn diagnose() -> Diagnosis {
+    let rules = rules();
+    let mut candidates = Vec::new();
+    let mut chosen = None;
+    if let Some(root) = config::root() {
+        // Nothing else is consulted while an override is installed.
+        candidates.push(Candidate {
+            source: Source::Override,
+            value: Some(root.as_os_str().to_os_string()),
+            rejection: None,
+        });
+        chosen = Some((root, Source::Override));
+    } else {
+        for (i, resolver) in resolvers().iter().enumerate() {
+            let path = resolver();
+            candidates.push(Candidate {
+                source: Source::Resolver(i),
+                value: path.as_ref().map(|p| p.as_os_str().to_os_string()),
+                rejection: if path.is_none() { Some(Rejection::Declined) } else { None },
+            });
+            if let Some(path) = path {
+                chosen = Some((path, Source::Resolver(i)));
+                break;
+            }
+        }
+    }
+    let (path, source) = match chosen {
+        Some(chosen) => chosen,
+        None => diagnose_rules(rules, &mut candidates),
+    };
+    Diagnosis { rules: rules, candidates: candidates, path: path, source: source }
+}
+
+/// Applies the built-in `rules`, recording every candidate considered.
+fn diagnose_rules(rules: Rules, candidates: &mut Vec<Candidate>) -> (PathBuf, Source) {
+    if rules == Rules::Std {
+        let path = env::temp_dir();
+        candidates.push(Candidate {
+            source: Source::Std,
+            value: Some(path.as_os_str().to_os_string()),
+            rejection: None,
+        });
+        return (path, Source::Std);
+    }
+
+    let mut chosen = try_strategies(Some(&mut *candidates));
+    for var in VARS.iter() {
+        let (value, result) = examine(var);
+        let rejection = match result {
+            Ok(path) => {
+                if chosen.is_some() {
+                    Some(Rejection::Shadowed)
+                } else {
+                    chosen = Some((path, Source::Var(*var)));
+                    None
+                }
+            }
+            Err(rejection) => Some(rejection),
+        };
+        candidates.push(Candidate { source: Source::Var(*var), value: value, rejection: rejection });
+    }
+    let default = default_dir();
+    candidates.push(Candidate {
+        source: Source::Default,
+        value: Some(default.as_os_str().to_os_string()),
+        rejection: if chosen.is_some() { Some(Rejection::Shadowed) } else { None },
+    });
+    chosen.unwrap_or((default, Source::Default))
+}
+
+/// Reads the environment variable `v`, returning its raw value and either the
+/// directory it names or the reason it cannot be used.
+fn examine(v: &str) -> (Option<OsString>, Result<PathBuf, Rejection>) {
+    match env::var(v) {
+        Ok(x) =>
+            if x.is_empty() {
+                (Some(OsString::from_string(x)), Err(Rejection::Empty))
+            } else {
+                (Some(OsString::from_string(x.clone())), Ok(PathBuf::new(&x)))
+            },
+        Err(VarError::NotUnicode(x)) => (Some(x), Err(Rejection::NotUnicode)),
+        Err(VarError::NotPresent) => (None, Err(Rejection::Unset)),
+    }
+}
+
+/// The environment variables consulted, in order, before falling back to the
+/// platform default.
+#[cfg(unix)]
+pub const VARS: &'static [&'static str] = &["TMPDIR"];
+#[cfg(windows)]
+pub const VARS: &'static [&'static str] = &["TMP", "TEMP", "USERPROFILE", "WINDIR"];
+
+/// The directory used when none of `VARS` is set.
+#[cfg(unix)]
+pub fn default_dir() -> PathBuf {
+    if cfg!(target_os = "android") {
+        PathBuf::new("/data/local/tmp")
+    } else {
+        PathBuf::new("/tmp")
+    }
+}
+#[cfg(windows)]
+pub fn default_dir() -> PathBuf {
+    PathBuf::new("C:\\Windows")
+}
+
+fn lookup() -> (PathBuf, Source) {
+    if let Some(chosen) = try_strategies(None) {
+        return chosen;
+    }
+    for var in VARS.iter() {
+        if let (_, Ok(path)) = examine(var) {
+            return (path, Source::Var(*var));
+        }
+    }
+    (default_dir(), Source::Default)
+}
+
+/// The resolution settings in force when it was created, put back when it
+/// is dropped. It holds the lock on global test state meanwhile, so that
+/// tests changing how `temp_dir` resolves do not see each other's settings.
+#[cfg(test)]
+pub struct SavedResolution {
+    strategies: Vec<Strategy>,
+    resolvers: Vec<Resolver>,
+    rules: Rules,
+    _globals: ::std::sync::MutexGuard<'static, ()>,
+}
+
+#[cfg(test)]
+impl SavedResolution {
+
+    pub fn new() -> SavedResolution {
+        let globals = ::testing::lock_globals();
+        SavedResolution {
+            strategies: strategies(),
+            resolvers: resolvers(),
+            rules: rules(),
+            _globals: globals,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Drop for SavedResolution {
+    fn drop(&mut self) {
+        set_strategies(self.strategies.clone());
+        *RESOLVERS.lock().unwrap_or_else(|e| e.into_inner()) = self.resolvers.clone();
+        set_rules(self.rules);
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn test_diagnose() {
+        let _saved = SavedResolution::new();
+        let diagnosis = diagnose();
+        assert_eq!(diagnosis.path, temp_dir_source().0);
+        assert_eq!(diagnosis.candidates.iter().filter(|c| c.rejection.is_none()).count(), 1);
+    }
+
+    #[test]
+    fn test_resolver_declined() {
+        let saved = SavedResolution::new();
+        register_resolver(|| None);
+        let diagnosis = diagnose();
+        drop(saved);
+        assert!(diagnosis.candidates.iter().any(|c| c.rejection == Some(Rejection::Declined)));
+        assert!(!diagnose().candidates.iter().any(|c| c.rejection == Some(Rejection::Declined)));
+    }
+
+    #[test]
+    fn test_strategies() {
+        let _saved = SavedResolution::new();
+        let missing = env::temp_dir().join("test_strategies_missing");
+        set_strategies(vec![Strategy::Roots(vec![missing, env::temp_dir()])]);
+        let diagnosis = diagnose();
+        let (path, source) = temp_dir_source();
+        assert_eq!((path, source), (env::temp_dir(), Source::Strategy(0)));
+        let strategy = diagnosis.candidates.iter().filter(|c| c.source == Source::Strategy(0));
+        let rejections: Vec<_> = strategy.map(|c| c.rejection.clone()).collect();
+        assert_eq!(rejections, vec![Some(Rejection::Unusable), None]);
+    }
+
+    #[test]
+    fn test_std_rules() {
+        let _saved = SavedResolution::new();
+        set_rules(Rules::Std);
+        let (path, source) = temp_dir_source();
+        assert_eq!(path, env::temp_dir());
+        assert_eq!(source, Source::Std);
+    }
+}
@@ -0,0 +1,84 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::env;
+use std::ffi::OsStr;
+use std::thread;
+use std::time::Duration;
+
+/// The environment variable which, when set to anything other than `""` or
+/// `"0"`, keeps every `TempDir` in the process regardless of its retention
+/// policy, so that a failing CI run can be inspected without recompiling.
+pub const KEEP_VAR: &'static str = "TEMPDIR_KEEP";
+
+/// Decides whether a `TempDir` is kept on disk instead of being removed when
+/// it is dropped or closed.
+///
+/// The default is `Never`, which removes the directory unconditionally.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetentionPolicy {
+    /// Always keep the directory.
+    Always,
+    /// Keep the directory if the thread is panicking when it is dropped,
+    /// which is how a failing test unwinds.
+    OnFailure,
+    /// Never keep the directory.
+    Never,
+    /// Keep the directory if it has existed for at least the given duration,
+    /// e.g. to preserve the output of a test that ran suspiciously long.
+    OlderThan(Duration),
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> RetentionPolicy {
+        RetentionPolicy::Never
+    }
+}
+
+impl RetentionPolicy {
+
+    /// Returns whether a directory of the given age should be retained right
+    /// now.
+    pub fn retains(&self, age: Duration) -> bool {
+        match *self {
+            RetentionPolicy::Always => true,
+            RetentionPolicy::OnFailure => thread::panicking(),
+            RetentionPolicy::Never => false,
+            RetentionPolicy::OlderThan(min) => age >= min,
+        }
+    }
+}
+
+/// Returns whether `KEEP_VAR` asks for all directories to be kept. It is
+/// consulted whenever a directory is about to be removed.
+pub fn keep_from_env() -> bool {
+    keep_requested(env::var_os(KEEP_VAR).as_ref().map(|v| &**v))
+}
+
+fn keep_requested(value: Option<&OsStr>) -> bool {
+    match value {
+        Some(v) => v != OsStr::from_str("") && v != OsStr::from_str("0"),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::ffi::OsStr;
+
+    use super::keep_requested;
+
+    #[test]
+    fn test_keep_requested() {
+        assert!(!keep_requested(None));
+        assert!(!keep_requested(Some(OsStr::from_str(""))));
+        assert!(!keep_requested(Some(OsStr::from_str("0"))));
+        assert!(keep_requested(Some(OsStr::from_str("1"))));
+    }
+}
@@ -0,0 +1,240 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::cell::RefCell;
+use std::error::Error;
+use std::ffi::AsOsStr;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use super::{TempDir, TempFile};
+
+/// Creates temporary resources whose lifetime is bound to a call to `scope`.
+pub struct Scope {
+    resources: RefCell<Vec<Resource>>,
+}
+
+/// A temporary resource owned by a `Scope`.
+enum Resource {
+    Dir(TempDir),
+    File(TempFile),
+}
+
+impl Resource {
+    fn path(&self) -> PathBuf {
+        match *self {
+            Resource::Dir(ref dir) => dir.path().to_path_buf(),
+            Resource::File(ref file) => file.path().to_path_buf(),
+        }
+    }
+
+    fn close(self) -> io::Result<()> {
+        match self {
+            Resource::Dir(dir) => dir.close().map_err(|e| e.into_io_error()),
+            Resource::File(file) => file.close(),
+        }
+    }
+}
+
+/// The errors from a call to `scope`: the body's error, if it failed, and
+/// every error encountered while cleaning up.
+#[derive(Debug)]
+pub struct ScopeError {
+    /// The error returned by the body, if any.
+    pub error: Option<io::Error>,
+    /// The paths that could not be removed, and why.
+    pub cleanup: Vec<(PathBuf, io::Error)>,
+}
+
+impl fmt::Display for ScopeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref e) = self.error {
+            try!(write!(f, "{}", e));
+        }
+        for &(ref path, ref e) in self.cleanup.iter() {
+            try!(write!(f, "; failed to remove {}: {}", path.display(), e));
+        }
+        Ok(())
+    }
+}
+
+impl Error for ScopeError {
+    fn description(&self) -> &str {
+        match self.error {
+            Some(ref e) => e.description(),
+            None => "failed to clean up temporary resources",
+        }
+    }
+}
+
+impl Scope {
+
+    /// Creates a temporary directory inside of `temp_dir()` which is removed
+    /// when the scope exits.
+    pub fn tempdir<P: ?Sized>(&self, prefix: &P) -> io::Result<PathBuf>
+        where P: AsOsStr
+    {
+        self.adopt(try!(TempDir::new(prefix)))
+    }
+
+    /// Creates a temporary directory inside of `tmpdir` which is removed when
+    /// the scope exits.
+    pub fn tempdir_in<P: ?Sized>(&self, tmpdir: &Path, prefix: &P) -> io::Result<PathBuf>
+        where P: AsOsStr
+    {
+        self.adopt(try!(TempDir::new_in(tmpdir, prefix)))
+    }
+
+    /// Hands an existing `TempDir` over to the scope, which will close it on
+    /// exit.
+    pub fn adopt(&self, dir: TempDir) -> io::Result<PathBuf> {
+        self.push(Resource::Dir(dir))
+    }
+
+    /// Creates a temporary file inside of `temp_dir()` which is removed when
+    /// the scope exits.
+    pub fn tempfile<P: ?Sized>(&self, prefix: &P) -> io::Result<PathBuf>
+        where P: AsOsStr
+    {
+        self.adopt_file(try!(TempFile::new(prefix)))
+    }
+
+    /// Creates a temporary file inside of `tmpdir` which is removed when the
+    /// scope exits.
+    pub fn tempfile_in<P: ?Sized>(&self, tmpdir: &Path, prefix: &P) -> io::Result<PathBuf>
+        where P: AsOsStr
+    {
+        self.adopt_file(try!(TempFile::new_in(tmpdir, prefix)))
+    }
+
+    /// Hands an existing `TempFile` over to the scope, which will close it
+    /// on exit, in the same order as its directories.
+    pub fn adopt_file(&self, file: TempFile) -> io::Result<PathBuf> {
+        self.push(Resource::File(file))
+    }
+
+    fn push(&self, resource: Resource) -> io::Result<PathBuf> {
+        let path = resource.path();
+        self.resources.borrow_mut().push(resource);
+        Ok(path)
+    }
+
+    /// Closes every resource, most recently created first.
+    fn close(&self) -> Vec<(PathBuf, io::Error)> {
+        let mut errors = Vec::new();
+        while let Some(resource) = self.resources.borrow_mut().pop() {
+            let path = resource.path();
+            if let Err(e) = resource.close() {
+                errors.push((path, e));
+            }
+        }
+        errors
+    }
+}
+
+/// Closes the scope's resources during unwinding if the body panics.
+struct Guard<'a>(&'a Scope);
+
+impl<'a> Drop for Guard<'a> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.0.close();
+        }
+    }
+}
+
+/// Runs `f` with a `Scope` through which it can create temporary resources,
+/// all of which are removed, in reverse order of creation, when `f` returns
+/// or panics.
+///
+/// Unlike relying on `Drop`, cleanup errors are not discarded: they are
+/// returned along with any error from `f`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+///
+/// let n = tempdir::scope(|s| {
+///     let dir = try!(s.tempdir("work"));
+///     try!(File::create(&dir.join("out.txt")));
+///     Ok(42)
+/// }).unwrap();
+/// ```
+pub fn scope<F, R>(f: F) -> Result<R, ScopeError>
+    where F: FnOnce(&Scope) -> io::Result<R>
+{
+    let scope = Scope { resources: RefCell::new(Vec::new()) };
+    let result = {
+        let _guard = Guard(&scope);
+        f(&scope)
+    };
+    let cleanup = scope.close();
+    match result {
+        Ok(r) if cleanup.is_empty() => Ok(r),
+        Ok(_) => Err(ScopeError { error: None, cleanup: cleanup }),
+        Err(e) => Err(ScopeError { error: Some(e), cleanup: cleanup }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs::PathExt;
+    use std::io;
+
+    use super::super::*;
+
+    #[test]
+    fn test_scope_cleanup() {
+        let mut paths = Vec::new();
+        let result = scope(|s| {
+            let outer = try!(s.tempdir("test_scope_cleanup"));
+            let inner = try!(s.tempdir_in(&outer, "nested"));
+            paths.push(outer);
+            paths.push(inner);
+            Ok(())
+        });
+        assert!(result.is_ok());
+        assert!(!paths[0].exists());
+    }
+
+    #[test]
+    fn test_scope_error() {
+        let result: Result<(), _> = scope(|s| {
+            try!(s.tempdir("test_scope_error"));
+            Err(io::Error::new(io::ErrorKind::Other, "boom", None))
+        });
+        let err = result.unwrap_err();
+        assert!(err.error.is_some());
+        assert!(err.cleanup.is_empty());
+    }
+
+    #[test]
+    fn test_scope_files() {
+        let mut paths = Vec::new();
+        let result = scope(|s| {
+            let dir = try!(s.tempdir("test_scope_files"));
+            // Closed before the directory containing it, so that removing
+            // the directory does not make removing the file fail.
+            let file = try!(s.tempfile_in(&dir, "file"));
+            let adopted = try!(s.adopt_file(try!(TempFile::new("test_scope_files"))));
+            assert!(file.is_file());
+            paths.push(dir);
+            paths.push(file);
+            paths.push(adopted);
+            Ok(())
+        });
+        assert!(result.is_ok());
+        for path in paths.iter() {
+            assert!(!path.exists());
+        }
+    }
+}
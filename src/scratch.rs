@@ -0,0 +1,83 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use guard;
+use super::{TempDir, resolve_relative};
+
+/// An object-safe view of a scratch directory, so that frameworks can accept
+/// a `Box<ScratchSpace>` without caring how it was created.
+pub trait ScratchSpace {
+
+    /// The path of the scratch directory.
+    fn path(&self) -> &Path;
+
+    /// Creates (or truncates) the file at `rel`, relative to the scratch
+    /// directory. `rel` may not be absolute or contain `..` components.
+    fn create_file(&self, rel: &Path) -> io::Result<File> {
+        File::create(&try!(resolve_relative(self.path(), rel)))
+    }
+
+    /// Creates a new, uniquely named scratch directory inside of this one.
+    fn child(&self, prefix: &str) -> io::Result<Box<ScratchSpace + Send>> {
+        let dir = try!(TempDir::new_in(self.path(), prefix));
+        Ok(Box::new(dir) as Box<ScratchSpace + Send>)
+    }
+
+    /// Removes the scratch directory, reporting any error.
+    fn cleanup(self: Box<Self>) -> io::Result<()>;
+}
+
+impl ScratchSpace for TempDir {
+
+    fn path(&self) -> &Path {
+        TempDir::path(self)
+    }
+
+    fn cleanup(self: Box<Self>) -> io::Result<()> {
+        Ok(try!((*self).close()))
+    }
+}
+
+impl TempDir {
+
+    /// Adopts the existing directory at `path`, which will be removed once
+    /// the returned wrapper is destroyed, just like a directory created by
+    /// `TempDir::new`.
+    pub fn from_path(path: &Path) -> TempDir {
+        let mut dir = TempDir::with_path(path.to_path_buf());
+        dir.removed_by_owner = dir.identity.as_ref()
+                                  .and_then(|identity| guard::adopt(path, identity));
+        dir.update_registration();
+        dir
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs::PathExt;
+    use std::path::Path;
+
+    use super::super::*;
+
+    #[test]
+    fn test_scratch_space_object() {
+        let space: Box<ScratchSpace> = Box::new(TempDir::new("test_scratch_space").unwrap());
+        let path = space.path().to_path_buf();
+        space.create_file(Path::new("a.txt")).unwrap();
+        let child = space.child("child").unwrap();
+        assert!(child.path().starts_with(&path));
+        child.cleanup().unwrap();
+        space.cleanup().unwrap();
+        assert!(!path.exists());
+    }
+}
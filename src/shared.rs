@@ -0,0 +1,283 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::io;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::thread;
+
+use super::{Error, ScratchSpace, TempDir};
+
+/// A reference-counted handle to a temporary directory, returned by
+/// `TempDir::into_shared`, which can be cloned and sent to other threads.
+///
+/// The directory is removed, with the settings of the `TempDir` it was
+/// made from, when the last clone is dropped or closed. All of `TempDir`'s
+/// helpers that take `&self` are available through `Deref`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::thread;
+/// use tempdir::TempDir;
+///
+/// let scratch = TempDir::new("workers").unwrap().into_shared();
+/// let workers: Vec<_> = (0..4).map(|i| {
+///     let scratch = scratch.clone();
+///     thread::spawn(move || {
+///         scratch.write(&format!("out-{}", i), b"done").map(|_| ())
+///     })
+/// }).collect();
+/// for worker in workers {
+///     worker.join().unwrap().unwrap();
+/// }
+/// scratch.close().unwrap();
+/// ```
+pub struct SharedTempDir {
+    inner: Arc<Shared>,
+    /// Set once this handle no longer counts towards `handles`.
+    released: bool,
+}
+
+/// The directory behind the handles and how many of them there are. The
+/// count is kept apart from the `Arc`'s, so that exactly one `close` sees
+/// itself as the last even while other handles are still being released.
+struct Shared {
+    dir: TempDir,
+    handles: AtomicUsize,
+}
+
+impl SharedTempDir {
+
+    fn new(inner: Arc<Shared>) -> SharedTempDir {
+        SharedTempDir { inner: inner, released: false }
+    }
+
+    /// Returns the path of the directory.
+    pub fn path(&self) -> &Path {
+        self.inner.dir.path()
+    }
+
+    /// Returns how many handles to the directory exist, this one included.
+    pub fn handles(&self) -> usize {
+        self.inner.handles.load(Ordering::SeqCst)
+    }
+
+    /// Gives up this handle. If it is the last one, the directory is closed
+    /// like `TempDir::close`, reporting any error, and `Ok(true)` is
+    /// returned; otherwise the directory stays for the other handles and
+    /// `Ok(false)` is returned. Of handles closed concurrently, exactly one
+    /// is the last.
+    pub fn close(self) -> Result<bool, Error> {
+        match self.release() {
+            Some(dir) => {
+                try!(dir.close());
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Returns the `TempDir` itself if this is the last handle, and the
+    /// handle back otherwise.
+    pub fn try_unwrap(mut self) -> Result<TempDir, SharedTempDir> {
+        if self.inner.handles.compare_and_swap(1, 0, Ordering::SeqCst) != 1 {
+            return Err(self);
+        }
+        self.released = true;
+        let inner = self.inner.clone();
+        drop(self);
+        Ok(unwrap_last(inner))
+    }
+
+    /// Returns a weak handle to the directory, which does not keep it from
+    /// being removed.
+    pub fn downgrade(&self) -> WeakTempDir {
+        WeakTempDir { inner: Arc::downgrade(&self.inner) }
+    }
+
+    /// Gives up this handle, returning the `TempDir` if it was the last.
+    fn release(mut self) -> Option<TempDir> {
+        self.released = true;
+        let last = self.inner.handles.fetch_sub(1, Ordering::SeqCst) == 1;
+        let inner = self.inner.clone();
+        drop(self);
+        if last { Some(unwrap_last(inner)) } else { None }
+    }
+}
+
+/// Takes the directory out of `inner` once the handles released before the
+/// last one have dropped their references, which they are about to do.
+fn unwrap_last(mut inner: Arc<Shared>) -> TempDir {
+    loop {
+        match Arc::try_unwrap(inner) {
+            Ok(shared) => return shared.dir,
+            Err(again) => {
+                inner = again;
+                thread::yield_now();
+            }
+        }
+    }
+}
+
+impl Clone for SharedTempDir {
+    fn clone(&self) -> SharedTempDir {
+        self.inner.handles.fetch_add(1, Ordering::SeqCst);
+        SharedTempDir::new(self.inner.clone())
+    }
+}
+
+impl Drop for SharedTempDir {
+    fn drop(&mut self) {
+        if !self.released {
+            self.inner.handles.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// A weak handle to a `SharedTempDir`, returned by `downgrade`, for caches
+/// and background tasks that use the directory while it exists but must not
+/// keep it around.
+///
+/// # Examples
+///
+/// ```no_run
+/// use tempdir::TempDir;
+///
+/// let scratch = TempDir::new("cache").unwrap().into_shared();
+/// let weak = scratch.downgrade();
+/// if let Some(scratch) = weak.upgrade() {
+///     scratch.write("entry", b"cached").unwrap();
+/// }
+/// ```
+#[derive(Clone)]
+pub struct WeakTempDir {
+    inner: Weak<Shared>,
+}
+
+impl WeakTempDir {
+
+    /// Returns whether a `SharedTempDir` handle to the directory still
+    /// exists. The last handle may go away, and the directory with it, right
+    /// after this returns `true`; use `upgrade` to keep it.
+    pub fn is_alive(&self) -> bool {
+        self.inner.upgrade().map_or(false, |inner| inner.handles.load(Ordering::SeqCst) > 0)
+    }
+
+    /// Returns a new shared handle, keeping the directory alive, or `None`
+    /// if the last handle has already gone away and the directory has been
+    /// removed.
+    pub fn upgrade(&self) -> Option<SharedTempDir> {
+        let inner = match self.inner.upgrade() {
+            Some(inner) => inner,
+            None => return None,
+        };
+        // Once the count has dropped to zero, the last handle is taking the
+        // directory; it must not come back to life.
+        let mut handles = inner.handles.load(Ordering::SeqCst);
+        while handles > 0 {
+            let previous = inner.handles.compare_and_swap(handles, handles + 1, Ordering::SeqCst);
+            if previous == handles {
+                return Some(SharedTempDir::new(inner));
+            }
+            handles = previous;
+        }
+        None
+    }
+}
+
+impl Deref for SharedTempDir {
+    type Target = TempDir;
+
+    fn deref(&self) -> &TempDir {
+        &self.inner.dir
+    }
+}
+
+impl ScratchSpace for SharedTempDir {
+
+    fn path(&self) -> &Path {
+        SharedTempDir::path(self)
+    }
+
+    fn cleanup(self: Box<Self>) -> io::Result<()> {
+        try!((*self).close());
+        Ok(())
+    }
+}
+
+impl TempDir {
+
+    /// Turns the directory into a handle that can be cloned and shared
+    /// between threads, removing the directory when the last clone goes
+    /// away.
+    pub fn into_shared(self) -> SharedTempDir {
+        SharedTempDir::new(Arc::new(Shared { dir: self, handles: AtomicUsize::new(1) }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs::PathExt;
+    use std::thread;
+
+    use super::super::*;
+
+    #[test]
+    fn test_shared_temp_dir() {
+        let shared = TempDir::new("test_shared_temp_dir").unwrap().into_shared();
+        let path = shared.path().to_path_buf();
+        let clone = shared.clone();
+        assert_eq!(shared.handles(), 2);
+        thread::spawn(move || {
+            clone.write("from-thread", b"").unwrap();
+        }).join().unwrap();
+        assert!(path.join("from-thread").exists());
+
+        let other = shared.clone();
+        assert_eq!(other.close().unwrap(), false);
+        assert!(path.exists());
+        assert_eq!(shared.close().unwrap(), true);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_shared_temp_dir_concurrent_close() {
+        for _ in 0..100 {
+            let shared = TempDir::new("test_shared_temp_dir_concurrent_close").unwrap()
+                                                                             .into_shared();
+            let path = shared.path().to_path_buf();
+            let clone = shared.clone();
+            let other = thread::spawn(move || clone.close().unwrap());
+            let mine = shared.close().unwrap();
+            let theirs = other.join().unwrap();
+            assert!(mine != theirs);
+            assert!(!path.exists());
+        }
+    }
+
+    #[test]
+    fn test_weak_temp_dir() {
+        let shared = TempDir::new("test_weak_temp_dir").unwrap().into_shared();
+        let path = shared.path().to_path_buf();
+        let weak = shared.downgrade();
+        assert!(weak.is_alive());
+        {
+            let upgraded = weak.upgrade().unwrap();
+            assert_eq!(upgraded.path(), &*path);
+            assert_eq!(shared.handles(), 2);
+        }
+        drop(shared);
+        assert!(!weak.is_alive());
+        assert!(weak.upgrade().is_none());
+        assert!(!path.exists());
+    }
+}
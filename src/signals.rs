@@ -0,0 +1,166 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Removal of live temporary directories when the process is interrupted.
+
+use std::io;
+use std::sync::{Mutex, Once, ONCE_INIT};
+
+use Error;
+use registry;
+
+static INSTALL: Once = ONCE_INIT;
+
+lazy_static! {
+    static ref INSTALLED: Mutex<Option<io::Result<()>>> = Mutex::new(None);
+}
+
+/// Installs handlers that remove every live `TempDir` created from now on
+/// when the process receives `SIGINT`, `SIGTERM` or `SIGHUP` (on Windows,
+/// Ctrl-C, Ctrl-Break or the console being closed), and then let the signal
+/// take its usual effect. This also enables `register_atexit_cleanup`.
+///
+/// On Unix, the handlers only wake up a helper thread, which does the
+/// removal; any handler installed earlier for these signals is replaced.
+/// Calling this again has no effect.
+pub fn install_signal_cleanup() -> io::Result<()> {
+    registry::register_atexit_cleanup();
+    INSTALL.call_once(|| *INSTALLED.lock().unwrap() = Some(imp::install()));
+    match *INSTALLED.lock().unwrap() {
+        Some(Ok(())) | None => Ok(()),
+        Some(Err(ref e)) => Err(Error::NoSignalHandlers(e.kind(), e.to_string()).into_io_error()),
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use libc;
+    use std::io;
+    use std::sync::atomic::{AtomicIsize, ATOMIC_ISIZE_INIT, Ordering};
+    use std::thread;
+
+    use registry;
+
+    const SIGNALS: &'static [libc::c_int] = &[libc::SIGINT, libc::SIGTERM, libc::SIGHUP];
+
+    /// The write end of the pipe the handlers report signals through.
+    static PIPE: AtomicIsize = ATOMIC_ISIZE_INIT;
+
+    /// Only calls `write`, which is async-signal-safe.
+    extern "C" fn on_signal(signal: libc::c_int) {
+        let byte = signal as u8;
+        let fd = PIPE.load(Ordering::SeqCst) as libc::c_int;
+        unsafe { libc::write(fd, &byte as *const u8 as *const libc::c_void, 1); }
+    }
+
+    pub fn install() -> io::Result<()> {
+        let mut fds = [0 as libc::c_int; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let (read, write) = (fds[0], fds[1]);
+        PIPE.store(write as isize, Ordering::SeqCst);
+        try!(thread::Builder::new().name("tempdir-signals".to_string()).spawn(move || {
+            let mut byte = 0u8;
+            let n = unsafe { libc::read(read, &mut byte as *mut u8 as *mut libc::c_void, 1) };
+            if n != 1 {
+                return;
+            }
+            registry::remove_all();
+            // Let the signal do what it would have done without us.
+            unsafe {
+                libc::signal(byte as libc::c_int, libc::SIG_DFL);
+                libc::raise(byte as libc::c_int);
+            }
+        }));
+        for &signal in SIGNALS.iter() {
+            let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            if unsafe { libc::signal(signal, handler) } == libc::SIG_ERR {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::io;
+
+    use registry;
+
+    extern "system" {
+        fn SetConsoleCtrlHandler(handler: extern "system" fn(u32) -> i32, add: i32) -> i32;
+    }
+
+    /// Runs on a thread of its own, so it may do the removal directly.
+    extern "system" fn on_ctrl(_event: u32) -> i32 {
+        registry::remove_all();
+        // Not handled: let the default handler terminate the process.
+        0
+    }
+
+    pub fn install() -> io::Result<()> {
+        if unsafe { SetConsoleCtrlHandler(on_ctrl, 1) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+
+    use libc;
+    use std::env;
+    use std::fs::PathExt;
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::process::ExitStatusExt;
+    use std::path::PathBuf;
+    use std::process::{Command, Stdio};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::super::*;
+
+    const CHILD_VAR: &'static str = "TEMPDIR_TEST_SIGNAL_CHILD";
+
+    /// Runs in a child process, so that the signal and the removal of every
+    /// live directory do not hit the other tests.
+    #[test]
+    fn test_install_signal_cleanup() {
+        if env::var_os(CHILD_VAR).is_some() {
+            install_signal_cleanup().unwrap();
+            // Installing twice is harmless.
+            install_signal_cleanup().unwrap();
+            let temp_dir = TempDir::new("test_install_signal_cleanup").unwrap();
+            println!("path={}", temp_dir.path().display());
+            thread::sleep(Duration::from_secs(30));
+            panic!("no signal received");
+        }
+
+        let mut child = Command::new(env::current_exe().unwrap())
+                                .args(&["signals::test::test_install_signal_cleanup", "--exact",
+                                        "--nocapture", "--test-threads=1"])
+                                .env(CHILD_VAR, "1")
+                                .stdout(Stdio::piped())
+                                .spawn()
+                                .unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let path = stdout.lines()
+                         .map(|line| line.unwrap())
+                         .find(|line| line.starts_with("path="))
+                         .map(|line| PathBuf::new(&line["path=".len()..]))
+                         .unwrap();
+        assert!(path.exists());
+        unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM); }
+        let status = child.wait().unwrap();
+        assert_eq!(status.signal(), Some(libc::SIGTERM));
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,108 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{AsPath, Path, PathBuf};
+
+use journal;
+use super::{Action, Error, TempDir, resolve_relative, unique_path_in};
+
+/// A named file inside a `TempDir` whose contents are only ever replaced as
+/// a whole, created by `TempDir::slot`.
+///
+/// Readers of the slot's path see either the previous contents or the new
+/// ones, never a partially written file: `replace` writes to a temporary
+/// file next to the slot and renames it into place.
+///
+/// # Examples
+///
+/// ```no_run
+/// use tempdir::TempDir;
+///
+/// let temp_dir = TempDir::new("artifacts").unwrap();
+/// let report = temp_dir.slot("report.json").unwrap();
+/// for round in 0..3 {
+///     report.replace(format!("{{\"round\": {}}}", round).as_bytes()).unwrap();
+/// }
+/// ```
+pub struct Slot<'a> {
+    dir: &'a TempDir,
+    path: PathBuf,
+}
+
+impl<'a> Slot<'a> {
+
+    /// Returns the directory containing the slot.
+    pub fn dir(&self) -> &'a TempDir {
+        self.dir
+    }
+
+    /// Returns the full path of the slot.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Atomically replaces the contents of the slot with `contents`. The new
+    /// contents are flushed to disk before they become visible.
+    pub fn replace(&self, contents: &[u8]) -> io::Result<()> {
+        let parent = self.path.parent().unwrap();
+        let name = self.path.file_name().unwrap().to_string_lossy().into_owned();
+        let staging = try!(unique_path_in(parent, &format!(".{}", name), ".tmp"));
+        let result = File::create(&staging).and_then(|mut file| {
+            try!(file.write_all(contents));
+            file.sync_all()
+        }).and_then(|_| fs::rename(&staging, &self.path));
+        if result.is_err() {
+            let _ = journal::record(self.dir, Action::Remove, &staging, fs::remove_file(&staging));
+        }
+        journal::record(self.dir, Action::Replace, &self.path, result)
+    }
+
+    /// Reads the current contents of the slot. Fails with `NotFound` if the
+    /// slot has never been filled.
+    pub fn read(&self) -> io::Result<Vec<u8>> {
+        let mut contents = Vec::new();
+        try!(try!(File::open(&self.path)).read_to_end(&mut contents));
+        Ok(contents)
+    }
+}
+
+impl TempDir {
+
+    /// Returns the slot at `rel`, relative to the temporary directory. The
+    /// file is not created until the first `replace`. `rel` may not be
+    /// absolute or contain `..` components.
+    pub fn slot<P: ?Sized>(&self, rel: &P) -> io::Result<Slot> where P: AsPath {
+        let path = try!(resolve_relative(self.path(), rel.as_path()));
+        if path.file_name().is_none() {
+            return Err(Error::NoFileName(path).into_io_error());
+        }
+        Ok(Slot { dir: self, path: path })
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs;
+
+    use super::super::*;
+
+    #[test]
+    fn test_slot_replace() {
+        let temp_dir = TempDir::new("test_slot_replace").unwrap();
+        let slot = temp_dir.slot("artifact").unwrap();
+        assert!(slot.read().is_err());
+        slot.replace(b"first").unwrap();
+        slot.replace(b"second").unwrap();
+        assert_eq!(slot.read().unwrap(), b"second".to_vec());
+        // No staging files are left behind.
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,165 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::ffi::{AsOsStr, OsStr, OsString};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{Builder, Error, TempDir};
+
+/// A temporary directory moved aside by `TempDir::close_soft`, which is
+/// removed once its delay has passed unless `undo` is called first.
+///
+/// Dropping the handle does not cancel the pending removal. If the process
+/// exits before the delay has passed, the directory is left behind.
+pub struct SoftDelete {
+    original: PathBuf,
+    aside: PathBuf,
+    pending: Arc<(Mutex<Option<TempDir>>, Condvar)>,
+}
+
+impl SoftDelete {
+
+    /// Returns the path the directory was moved to while it awaits removal.
+    pub fn path(&self) -> &Path {
+        &self.aside
+    }
+
+    /// Cancels the pending removal and moves the directory back to where it
+    /// was, returning it as a `TempDir` with its previous settings.
+    ///
+    /// Fails if the directory has already been removed.
+    pub fn undo(self) -> io::Result<TempDir> {
+        let mut dir = try!(self.take());
+        if let Err(e) = fs::rename(&self.aside, &self.original) {
+            // Leave it to be removed on schedule.
+            *self.pending.0.lock().unwrap() = Some(dir);
+            return Err(e);
+        }
+        dir.set_path(self.original.clone());
+        Ok(dir)
+    }
+
+    /// Removes the directory immediately instead of waiting for the delay,
+    /// reporting any error.
+    pub fn delete_now(self) -> io::Result<()> {
+        Ok(try!(try!(self.take()).close()))
+    }
+
+    fn take(&self) -> io::Result<TempDir> {
+        let &(ref lock, ref cvar) = &*self.pending;
+        let dir = lock.lock().unwrap().take();
+        cvar.notify_all();
+        dir.ok_or_else(|| Error::Gone(self.aside.clone()).into_io_error())
+    }
+}
+
+/// Waits until `deadline` and then closes the directory in `pending`, unless
+/// it has been taken out in the meantime.
+fn remove_after(pending: Arc<(Mutex<Option<TempDir>>, Condvar)>, deadline: Instant) {
+    let &(ref lock, ref cvar) = &*pending;
+    let mut slot = lock.lock().unwrap();
+    loop {
+        let now = Instant::now();
+        if slot.is_none() {
+            return;
+        }
+        if now >= deadline {
+            break;
+        }
+        slot = cvar.wait_timeout(slot, deadline.duration_since(now)).unwrap().0;
+    }
+    let dir = slot.take().unwrap();
+    drop(slot);
+    let _ = dir.close();
+}
+
+impl TempDir {
+
+    /// Moves the directory aside and schedules its removal once `delay` has
+    /// passed, so that interactive tools can offer to restore it until then.
+    /// The directory is moved to a new hidden name next to it, ending in
+    /// `.deleted`.
+    ///
+    /// The directory keeps its cleanup strategy and retention policy, which
+    /// apply when the removal finally happens. If it cannot be moved aside,
+    /// it is handed back along with the error, untouched.
+    pub fn close_soft(mut self, delay: Duration) -> Result<SoftDelete, (TempDir, io::Error)> {
+        let original = self.path().to_path_buf();
+        let mut prefix = OsString::new();
+        prefix.push_os_str(OsStr::from_str("."));
+        prefix.push_os_str(original.file_name().unwrap_or(OsStr::from_str("")));
+        let parent = original.parent().unwrap_or(Path::new("."));
+        let aside = Builder::new().prefix(&prefix).suffix(".deleted").unique_path_in(parent);
+        let aside = match aside.and_then(|aside| fs::rename(&original, &aside).map(|_| aside)) {
+            Ok(aside) => aside,
+            Err(e) => return Err((self, e)),
+        };
+        self.set_path(aside.clone());
+
+        let pending = Arc::new((Mutex::new(Some(self)), Condvar::new()));
+        let deadline = Instant::now() + delay;
+        let shared = pending.clone();
+        thread::spawn(move || remove_after(shared, deadline));
+
+        Ok(SoftDelete { original: original, aside: aside, pending: pending })
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs::{self, PathExt};
+    use std::time::Duration;
+
+    use super::super::*;
+
+    #[test]
+    fn test_close_soft_undo() {
+        let temp_dir = TempDir::new("test_close_soft_undo").unwrap();
+        let path = temp_dir.path().to_path_buf();
+
+        let soft = temp_dir.close_soft(Duration::from_secs(3600)).ok().unwrap();
+        assert!(!path.exists());
+        assert!(soft.path().exists());
+
+        let temp_dir = soft.undo().unwrap();
+        assert_eq!(temp_dir.path(), &*path);
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn test_close_soft_delete_now() {
+        let temp_dir = TempDir::new("test_close_soft_delete_now").unwrap();
+        let soft = temp_dir.close_soft(Duration::from_secs(3600)).ok().unwrap();
+        let aside = soft.path().to_path_buf();
+
+        soft.delete_now().unwrap();
+        assert!(!aside.exists());
+    }
+
+    #[test]
+    fn test_close_soft_failure_returns_dir() {
+        let base = TempDir::new("test_close_soft_failure_returns_dir").unwrap();
+        let temp_dir = TempDir::new_in(base.path(), "gone").unwrap();
+        let path = temp_dir.path().to_path_buf();
+        let original = base.path().join("original");
+        fs::rename(&path, &original).unwrap();
+
+        let (temp_dir, _) = temp_dir.close_soft(Duration::from_secs(3600)).err().unwrap();
+        assert_eq!(temp_dir.path(), &*path);
+        fs::rename(&original, &path).unwrap();
+        let soft = temp_dir.close_soft(Duration::from_secs(3600)).ok().unwrap();
+        assert!(soft.path().file_name().unwrap().to_str().unwrap().ends_with(".deleted"));
+        soft.delete_now().unwrap();
+    }
+}
@@ -0,0 +1,72 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Free space checks for `Builder::require_free_space`.
+
+use std::io;
+use std::path::Path;
+
+/// Returns how many bytes can still be written to the file system holding
+/// `dir` by the current user.
+#[cfg(unix)]
+pub fn available(dir: &Path) -> io::Result<u64> {
+    use libc;
+    use std::ffi::{AsOsStr, CString};
+    use std::mem;
+    use std::os::unix::OsStrExt;
+
+    use Error;
+
+    let c_dir = try!(CString::new(dir.as_os_str().as_bytes()).map_err(|_| Error::nul_byte(dir)));
+    let mut buf: libc::statvfs = unsafe { mem::zeroed() };
+    if unsafe { libc::statvfs(c_dir.as_ptr(), &mut buf) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(buf.f_bavail as u64 * buf.f_frsize as u64)
+}
+
+#[cfg(windows)]
+pub fn available(dir: &Path) -> io::Result<u64> {
+    use std::ffi::AsOsStr;
+    use std::os::windows::OsStrExt;
+
+    extern "system" {
+        fn GetDiskFreeSpaceExW(dir: *const u16, available: *mut u64, total: *mut u64,
+                               free: *mut u64) -> i32;
+    }
+
+    let wide: Vec<u16> = dir.as_os_str().encode_wide().chain(Some(0).into_iter()).collect();
+    let mut available = 0;
+    let mut total = 0;
+    let mut free = 0;
+    if unsafe { GetDiskFreeSpaceExW(wide.as_ptr(), &mut available, &mut total, &mut free) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(available)
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::io;
+
+    use super::super::*;
+    use super::available;
+
+    #[test]
+    fn test_require_free_space() {
+        let base = TempDir::new("test_require_free_space").unwrap();
+        let free = available(base.path()).unwrap();
+        assert!(Builder::new().require_free_space(0).tempdir_in(base.path()).is_ok());
+        let err = Builder::new().require_free_space(free.saturating_add(1 << 40))
+                                .tempdir_in(base.path())
+                                .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+        assert!(err.to_string().contains("bytes available"));
+    }
+}
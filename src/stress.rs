@@ -0,0 +1,207 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Measures how quickly temporary directories can be created, filled and
+//! removed on a given file system, so that naming and cleanup strategies can
+//! be chosen empirically.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use tempdir::{Builder, Cleanup};
+//! use tempdir::stress::Stress;
+//!
+//! let mut builder = Builder::new();
+//! builder.prefix("stress").cleanup(Cleanup::Sorted);
+//! let report = Stress::new().dirs(1000).files_per_dir(10).builder(builder)
+//!                           .run(Path::new("/tmp"))
+//!                           .unwrap();
+//! println!("{}", report);
+//! ```
+
+use std::fmt;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use super::Builder;
+
+/// A stress run: how many directories to create, and how to create them.
+pub struct Stress {
+    dirs: usize,
+    files_per_dir: usize,
+    file_size: usize,
+    builder: Builder,
+}
+
+impl Stress {
+
+    /// Creates a run of 100 empty directories made with the default
+    /// settings.
+    pub fn new() -> Stress {
+        Stress { dirs: 100, files_per_dir: 0, file_size: 0, builder: Builder::new() }
+    }
+
+    /// Sets how many directories are created and removed.
+    pub fn dirs(&mut self, n: usize) -> &mut Stress {
+        self.dirs = n;
+        self
+    }
+
+    /// Sets how many files are written into each directory before it is
+    /// removed.
+    pub fn files_per_dir(&mut self, n: usize) -> &mut Stress {
+        self.files_per_dir = n;
+        self
+    }
+
+    /// Sets the size in bytes of each file.
+    pub fn file_size(&mut self, bytes: usize) -> &mut Stress {
+        self.file_size = bytes;
+        self
+    }
+
+    /// Sets the builder the directories are created with, which selects the
+    /// naming, creation and cleanup strategies being measured.
+    pub fn builder(&mut self, builder: Builder) -> &mut Stress {
+        self.builder = builder;
+        self
+    }
+
+    /// Runs the stress test inside of `base`, one directory at a time.
+    pub fn run(&self, base: &Path) -> io::Result<Report> {
+        let contents = vec![0u8; self.file_size];
+        let mut create = Vec::with_capacity(self.dirs);
+        let mut fill = Vec::with_capacity(self.dirs);
+        let mut remove = Vec::with_capacity(self.dirs);
+        let start = Instant::now();
+        for _ in 0..self.dirs {
+            let t = Instant::now();
+            let dir = try!(self.builder.tempdir_in(base));
+            create.push(t.elapsed());
+
+            let t = Instant::now();
+            for i in 0..self.files_per_dir {
+                try!(try!(dir.create_file(&format!("file-{}", i))).write_all(&contents));
+            }
+            fill.push(t.elapsed());
+
+            let t = Instant::now();
+            try!(dir.close());
+            remove.push(t.elapsed());
+        }
+        Ok(Report {
+            create: Stats::of(create),
+            fill: Stats::of(fill),
+            remove: Stats::of(remove),
+            total: start.elapsed(),
+        })
+    }
+}
+
+/// Latency statistics for one phase of a stress run.
+#[derive(Clone, Copy, Debug)]
+pub struct Stats {
+    /// How many times the phase ran.
+    pub count: usize,
+    /// The total time spent in the phase.
+    pub total: Duration,
+    /// The median latency.
+    pub p50: Duration,
+    /// The 90th percentile latency.
+    pub p90: Duration,
+    /// The 99th percentile latency.
+    pub p99: Duration,
+    /// The worst latency.
+    pub max: Duration,
+}
+
+impl Stats {
+
+    fn of(mut samples: Vec<Duration>) -> Stats {
+        samples.sort();
+        let zero = Duration::from_millis(0);
+        let percentile = |p: usize| {
+            if samples.is_empty() { zero } else { samples[(samples.len() - 1) * p / 100] }
+        };
+        Stats {
+            count: samples.len(),
+            total: samples.iter().fold(zero, |a, &b| a + b),
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max: percentile(100),
+        }
+    }
+
+    /// Returns how many times per second the phase ran, on average.
+    pub fn per_second(&self) -> f64 {
+        let secs = seconds(self.total);
+        if secs == 0.0 { 0.0 } else { self.count as f64 / secs }
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.0}/s, p50 {}, p90 {}, p99 {}, max {}",
+               self.per_second(), micros(self.p50), micros(self.p90), micros(self.p99),
+               micros(self.max))
+    }
+}
+
+/// The results of a stress run.
+#[derive(Clone, Copy, Debug)]
+pub struct Report {
+    /// Creating each directory.
+    pub create: Stats,
+    /// Writing the files into each directory.
+    pub fill: Stats,
+    /// Removing each directory.
+    pub remove: Stats,
+    /// The wall-clock time of the whole run.
+    pub total: Duration,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(f, "create: {}", self.create));
+        try!(writeln!(f, "fill:   {}", self.fill));
+        try!(writeln!(f, "remove: {}", self.remove));
+        write!(f, "total:  {:.3}s", seconds(self.total))
+    }
+}
+
+fn seconds(d: Duration) -> f64 {
+    d.as_secs() as f64 + d.subsec_nanos() as f64 / 1e9
+}
+
+fn micros(d: Duration) -> String {
+    format!("{:.0}us", seconds(d) * 1e6)
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs;
+
+    use super::super::*;
+    use super::Stress;
+
+    #[test]
+    fn test_stress() {
+        let base = TempDir::new("test_stress").unwrap();
+        let report = Stress::new().dirs(5).files_per_dir(2).file_size(16)
+                                  .run(base.path())
+                                  .unwrap();
+        assert_eq!(report.create.count, 5);
+        assert_eq!(report.remove.count, 5);
+        assert!(report.create.p50 <= report.create.max);
+        assert_eq!(fs::read_dir(base.path()).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,22 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Helpers shared by the unit tests.
+
+use std::sync::{Mutex, MutexGuard};
+
+lazy_static! {
+    static ref GLOBALS: Mutex<()> = Mutex::new(());
+}
+
+/// Serializes the tests which change process-wide state, such as the
+/// resolution rules or the environment. A test that panicked while holding
+/// the lock does not keep the others from running.
+pub fn lock_globals() -> MutexGuard<'static, ()> {
+    GLOBALS.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -0,0 +1,217 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A process-wide limiter on directory creation.
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Why a creation was held back by the limiter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Throttled {
+    /// Too many creations were already in progress.
+    Concurrency,
+    /// Too many creations happened during the last second.
+    Rate,
+}
+
+/// Limits on how quickly directories may be created, installed for the
+/// whole process with `set_throttle`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use tempdir::{Throttle, set_throttle};
+///
+/// let mut throttle = Throttle::new();
+/// throttle.max_concurrent(4).max_per_second(100).on_throttle(|why| {
+///     println!("temporary directory creation throttled: {:?}", why);
+/// });
+/// set_throttle(Some(throttle));
+/// ```
+pub struct Throttle {
+    max_concurrent: Option<usize>,
+    max_per_second: Option<u32>,
+    on_throttle: Option<Arc<Fn(Throttled) + Send + Sync>>,
+}
+
+impl Throttle {
+
+    /// Creates a limiter without any limits.
+    pub fn new() -> Throttle {
+        Throttle { max_concurrent: None, max_per_second: None, on_throttle: None }
+    }
+
+    /// Allows at most `n` creations to be in progress at once. Zero means
+    /// no limit.
+    pub fn max_concurrent(&mut self, n: usize) -> &mut Throttle {
+        self.max_concurrent = if n == 0 { None } else { Some(n) };
+        self
+    }
+
+    /// Allows at most `n` creations to start per second. Zero means no
+    /// limit.
+    pub fn max_per_second(&mut self, n: u32) -> &mut Throttle {
+        self.max_per_second = if n == 0 { None } else { Some(n) };
+        self
+    }
+
+    /// Registers a callback invoked whenever a creation has to wait.
+    pub fn on_throttle<F>(&mut self, f: F) -> &mut Throttle
+        where F: Fn(Throttled) + Send + Sync + 'static
+    {
+        self.on_throttle = Some(Arc::new(f));
+        self
+    }
+}
+
+struct State {
+    throttle: Option<Throttle>,
+    in_flight: usize,
+    window_start: Instant,
+    window_count: u32,
+}
+
+type Limiter = (Mutex<State>, Condvar);
+
+fn limiter() -> Limiter {
+    (Mutex::new(State {
+        throttle: None,
+        in_flight: 0,
+        window_start: Instant::now(),
+        window_count: 0,
+    }), Condvar::new())
+}
+
+lazy_static! {
+    static ref STATE: Limiter = limiter();
+}
+
+/// Installs `throttle` as the process-wide creation limiter, or removes the
+/// current one if `None`.
+pub fn set_throttle(throttle: Option<Throttle>) {
+    install(&STATE, throttle)
+}
+
+fn install(limiter: &Limiter, throttle: Option<Throttle>) {
+    let &(ref lock, ref cvar) = limiter;
+    lock.lock().unwrap().throttle = throttle;
+    cvar.notify_all();
+}
+
+/// A creation slot, released when dropped.
+pub struct Permit {
+    limiter: &'static Limiter,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let &(ref lock, ref cvar) = self.limiter;
+        lock.lock().unwrap().in_flight -= 1;
+        cvar.notify_all();
+    }
+}
+
+/// Waits until the limiter allows another creation to start.
+pub fn acquire() -> Permit {
+    acquire_from(&STATE)
+}
+
+fn acquire_from(limiter: &'static Limiter) -> Permit {
+    let &(ref lock, ref cvar) = limiter;
+    let mut state = lock.lock().unwrap();
+    loop {
+        let (max_concurrent, max_per_second, callback) = match state.throttle {
+            Some(ref t) => (t.max_concurrent, t.max_per_second, t.on_throttle.clone()),
+            None => (None, None, None),
+        };
+
+        let now = Instant::now();
+        if now.duration_since(state.window_start) >= Duration::from_secs(1) {
+            state.window_start = now;
+            state.window_count = 0;
+        }
+
+        let throttled = if max_concurrent.map_or(false, |max| state.in_flight >= max) {
+            Some(Throttled::Concurrency)
+        } else if max_per_second.map_or(false, |max| state.window_count >= max) {
+            Some(Throttled::Rate)
+        } else {
+            None
+        };
+
+        match throttled {
+            None => break,
+            Some(why) => {
+                if let Some(f) = callback {
+                    // Don't hold the lock while running user code.
+                    drop(state);
+                    f(why);
+                    state = lock.lock().unwrap();
+                }
+                let wait = match why {
+                    Throttled::Concurrency => Duration::from_millis(100),
+                    Throttled::Rate => {
+                        // The window may have ended while the callback ran.
+                        let end = state.window_start + Duration::from_secs(1);
+                        let now = Instant::now();
+                        if end <= now {
+                            continue;
+                        }
+                        end.duration_since(now)
+                    }
+                };
+                state = cvar.wait_timeout(state, wait).unwrap().0;
+            }
+        }
+    }
+    state.in_flight += 1;
+    state.window_count += 1;
+    Permit { limiter: limiter }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::{Limiter, Throttle, Throttled, acquire_from, install, limiter};
+
+    // Private limiters, so that tests running in parallel are not throttled.
+    lazy_static! {
+        static ref RATE: Limiter = limiter();
+        static ref UNLIMITED: Limiter = limiter();
+    }
+
+    #[test]
+    fn test_throttle_rate() {
+        let engaged = Arc::new(AtomicUsize::new(0));
+        let counter = engaged.clone();
+        let mut throttle = Throttle::new();
+        throttle.max_per_second(1).on_throttle(move |why| {
+            assert_eq!(why, Throttled::Rate);
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        install(&RATE, Some(throttle));
+        drop(acquire_from(&RATE));
+        drop(acquire_from(&RATE));
+        assert!(engaged.load(Ordering::SeqCst) >= 1);
+    }
+
+    #[test]
+    fn test_throttle_zero_is_unlimited() {
+        let mut throttle = Throttle::new();
+        throttle.max_concurrent(0).max_per_second(0).on_throttle(|why| {
+            panic!("throttled: {:?}", why);
+        });
+        install(&UNLIMITED, Some(throttle));
+        let _a = acquire_from(&UNLIMITED);
+        let _b = acquire_from(&UNLIMITED);
+    }
+}
@@ -0,0 +1,207 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{AsPath, Path, PathBuf};
+
+use journal;
+use super::{Action, Error, TempDir, resolve_relative};
+
+/// A file created inside a `TempDir` by `TempDir::create_file` or
+/// `TempDir::write`, which remembers where it lives relative to the
+/// directory.
+///
+/// The handle borrows the directory, so the file cannot outlive it by
+/// accident; use `persist_to` to keep the file past the directory's cleanup.
+pub struct TrackedFile<'a> {
+    dir: &'a TempDir,
+    rel: PathBuf,
+    path: PathBuf,
+    file: File,
+}
+
+impl<'a> TrackedFile<'a> {
+
+    /// Returns the directory containing the file.
+    pub fn dir(&self) -> &'a TempDir {
+        self.dir
+    }
+
+    /// Returns the path of the file relative to the root of its directory.
+    pub fn rel_path(&self) -> &Path {
+        &self.rel
+    }
+
+    /// Returns the full path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Access the open file handle.
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// Mutably access the open file handle.
+    pub fn file_mut(&mut self) -> &mut File {
+        &mut self.file
+    }
+
+    /// Returns the current size of the file in bytes.
+    pub fn len(&self) -> io::Result<u64> {
+        self.file.metadata().map(|m| m.len())
+    }
+
+    /// Checks that the file contains exactly `expected`, returning an
+    /// `InvalidInput` error describing the difference otherwise.
+    pub fn expect_contents(&self, expected: &[u8]) -> io::Result<()> {
+        let mut found = Vec::new();
+        try!(try!(File::open(&self.path)).read_to_end(&mut found));
+        if &found[..] == expected {
+            return Ok(());
+        }
+        Err(Error::Mismatch(format!("{}: expected {} bytes, found {} bytes",
+                                    self.rel.display(), expected.len(), found.len()))
+                  .into_io_error())
+    }
+
+    /// Moves the file to `dest`, outside of the temporary directory, so that
+    /// it survives the directory's cleanup. Returns the new path.
+    ///
+    /// If the file cannot be renamed, e.g. because `dest` is on another file
+    /// system, it is copied instead and the original is removed.
+    pub fn persist_to<P: ?Sized>(self, dest: &P) -> io::Result<PathBuf>
+        where P: AsPath
+    {
+        let dest = dest.as_path().to_path_buf();
+        if fs::rename(&self.path, &dest).is_err() {
+            try!(fs::copy(&self.path, &dest));
+            try!(fs::remove_file(&self.path));
+        }
+        Ok(dest)
+    }
+}
+
+impl<'a> Read for TrackedFile<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl<'a> Write for TrackedFile<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl<'a> Seek for TrackedFile<'a> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+impl TempDir {
+
+    /// Creates (or truncates) the file at `rel`, relative to the temporary
+    /// directory, along with any missing parent directories, and opens it for
+    /// reading and writing. `rel` may not be absolute or contain `..`
+    /// components.
+    pub fn create_file<P: ?Sized>(&self, rel: &P) -> io::Result<TrackedFile>
+        where P: AsPath
+    {
+        let rel = rel.as_path();
+        let path = try!(resolve_relative(self.path(), rel));
+        let created = create_parent(&path).and_then(|_| {
+            OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path)
+        });
+        let file = try!(journal::record(self, Action::Create, &path, created));
+        Ok(TrackedFile { dir: self, rel: rel.to_path_buf(), path: path, file: file })
+    }
+
+    /// Creates the file at `rel` like `create_file` and writes `contents` to
+    /// it. The returned handle is positioned at the start of the file.
+    pub fn write<P: ?Sized>(&self, rel: &P, contents: &[u8]) -> io::Result<TrackedFile>
+        where P: AsPath
+    {
+        let mut file = try!(self.create_file(rel));
+        let written = file.write_all(contents);
+        try!(journal::record(self, Action::Write, file.path(), written));
+        try!(file.seek(SeekFrom::Start(0)));
+        Ok(file)
+    }
+
+    /// Creates the directory at `rel`, relative to the temporary directory,
+    /// along with any missing parents, and returns its full path. `rel` may
+    /// not be absolute or contain `..` components.
+    pub fn mkdir_all<P: ?Sized>(&self, rel: &P) -> io::Result<PathBuf> where P: AsPath {
+        let path = try!(resolve_relative(self.path(), rel.as_path()));
+        try!(journal::record(self, Action::CreateDir, &path, fs::create_dir_all(&path)));
+        Ok(path)
+    }
+}
+
+/// Creates the missing parent directories of `path`.
+pub fn create_parent(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) => fs::create_dir_all(parent),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs::{File, PathExt};
+    use std::io::Read;
+    use std::path::Path;
+
+    use super::super::*;
+
+    #[test]
+    fn test_tracked_file() {
+        let temp_dir = TempDir::new("test_tracked_file").unwrap();
+        let file = temp_dir.write("a.txt", b"hello").unwrap();
+        assert_eq!(file.rel_path(), Path::new("a.txt"));
+        assert_eq!(file.path(), &*temp_dir.path().join("a.txt"));
+        assert_eq!(file.len().unwrap(), 5);
+        assert!(file.expect_contents(b"hello").is_ok());
+        assert!(file.expect_contents(b"bye").is_err());
+        assert!(temp_dir.create_file("../escape").is_err());
+    }
+
+    #[test]
+    fn test_tracked_file_parents() {
+        let temp_dir = TempDir::new("test_tracked_file_parents").unwrap();
+        temp_dir.write("a/b/c.txt", b"hello").unwrap();
+        assert!(temp_dir.path().join("a/b/c.txt").is_file());
+        assert_eq!(temp_dir.mkdir_all("x/y").unwrap(), temp_dir.path().join("x/y"));
+        assert!(temp_dir.path().join("x/y").is_dir());
+        assert!(temp_dir.mkdir_all("/x").is_err());
+        assert!(temp_dir.mkdir_all("x/../../y").is_err());
+    }
+
+    #[test]
+    fn test_tracked_file_persist_to() {
+        let temp_dir = TempDir::new("test_tracked_file_persist_to").unwrap();
+        let dest_dir = TempDir::new("test_tracked_file_persist_to").unwrap();
+        let dest = dest_dir.path().join("kept.txt");
+
+        let file = temp_dir.write("out.txt", b"result").unwrap();
+        assert_eq!(file.persist_to(&dest).unwrap(), dest);
+        assert!(!temp_dir.path().join("out.txt").exists());
+
+        let mut s = String::new();
+        File::open(&dest).unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "result");
+    }
+}
@@ -0,0 +1,209 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fs::{self, File, PathExt};
+use std::io::{self, Read, Write};
+use std::path::{AsPath, Path, PathBuf};
+
+use super::{TempDir, resolve_relative};
+
+/// A single queued operation.
+enum Op {
+    CreateDir(PathBuf),
+    Write(PathBuf, Vec<u8>),
+    Symlink(PathBuf, PathBuf),
+}
+
+/// Something that was changed while applying a transaction, and how to undo
+/// it.
+enum Undo {
+    RemoveDir(PathBuf),
+    RemoveFile(PathBuf),
+    Restore(PathBuf, Vec<u8>),
+}
+
+/// A batch of entries to create inside a `TempDir`, applied all at once.
+///
+/// Operations are queued with `create_dir`, `write` and `symlink`, and are
+/// only performed when `commit` is called. If any of them fails, everything
+/// the transaction created so far is removed again (and overwritten files
+/// get their previous contents back), so a failed fixture setup never leaves
+/// a half-built tree behind.
+///
+/// All paths are relative to the root of the temporary directory, and may
+/// not be absolute or contain `..` components.
+///
+/// # Examples
+///
+/// ```no_run
+/// use tempdir::TempDir;
+///
+/// let temp_dir = TempDir::new("fixture").unwrap();
+/// let mut tx = temp_dir.transaction();
+/// tx.create_dir("src")
+///   .write("src/lib.rs", b"pub fn answer() -> u32 { 42 }")
+///   .symlink("src/lib.rs", "lib.rs");
+/// tx.commit().unwrap();
+/// ```
+pub struct Transaction<'a> {
+    dir: &'a TempDir,
+    ops: Vec<Op>,
+}
+
+impl<'a> Transaction<'a> {
+
+    /// Queues the creation of a directory, along with any missing parents.
+    pub fn create_dir<P: ?Sized>(&mut self, path: &P) -> &mut Transaction<'a>
+        where P: AsPath
+    {
+        self.ops.push(Op::CreateDir(path.as_path().to_path_buf()));
+        self
+    }
+
+    /// Queues writing `contents` to the file at `path`. The parent directory
+    /// must exist by the time the operation is applied.
+    pub fn write<P: ?Sized>(&mut self, path: &P, contents: &[u8]) -> &mut Transaction<'a>
+        where P: AsPath
+    {
+        self.ops.push(Op::Write(path.as_path().to_path_buf(), contents.to_vec()));
+        self
+    }
+
+    /// Queues the creation of a symbolic link at `dst` pointing to `src`.
+    /// `src` is stored in the link unchanged, so relative targets are
+    /// resolved relative to the link's parent directory.
+    pub fn symlink<P: ?Sized, Q: ?Sized>(&mut self, src: &P, dst: &Q) -> &mut Transaction<'a>
+        where P: AsPath, Q: AsPath
+    {
+        self.ops.push(Op::Symlink(src.as_path().to_path_buf(), dst.as_path().to_path_buf()));
+        self
+    }
+
+    /// Returns the number of queued operations.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Applies every queued operation in order.
+    ///
+    /// On the first failure, the changes made so far are reverted in reverse
+    /// order and the original error is returned.
+    pub fn commit(self) -> io::Result<()> {
+        let root = self.dir.path();
+        let mut undo = Vec::new();
+        for op in self.ops.iter() {
+            if let Err(e) = apply(root, op, &mut undo) {
+                rollback(undo);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn apply(root: &Path, op: &Op, undo: &mut Vec<Undo>) -> io::Result<()> {
+    match *op {
+        Op::CreateDir(ref rel) => {
+            let path = try!(resolve_relative(root, rel));
+            // Create the missing ancestors one by one so that each of them
+            // can be removed again on rollback.
+            let mut missing = Vec::new();
+            let mut cur = Some(&*path);
+            while let Some(p) = cur {
+                if p.exists() { break; }
+                missing.push(p.to_path_buf());
+                cur = p.parent();
+            }
+            for p in missing.into_iter().rev() {
+                try!(fs::create_dir(&p));
+                undo.push(Undo::RemoveDir(p));
+            }
+            Ok(())
+        }
+        Op::Write(ref rel, ref contents) => {
+            let path = try!(resolve_relative(root, rel));
+            let previous = if path.exists() {
+                let mut buf = Vec::new();
+                try!(try!(File::open(&path)).read_to_end(&mut buf));
+                Some(buf)
+            } else {
+                None
+            };
+            let result = File::create(&path).and_then(|mut f| f.write_all(contents));
+            match previous {
+                Some(buf) => undo.push(Undo::Restore(path, buf)),
+                None => if path.exists() { undo.push(Undo::RemoveFile(path)) },
+            }
+            result
+        }
+        Op::Symlink(ref src, ref rel) => {
+            let dst = try!(resolve_relative(root, rel));
+            try!(fs::soft_link(src, &dst));
+            undo.push(Undo::RemoveFile(dst));
+            Ok(())
+        }
+    }
+}
+
+fn rollback(undo: Vec<Undo>) {
+    for u in undo.into_iter().rev() {
+        let _ = match u {
+            Undo::RemoveDir(p) => fs::remove_dir(&p),
+            Undo::RemoveFile(p) => fs::remove_file(&p),
+            Undo::Restore(p, buf) => File::create(&p).and_then(|mut f| f.write_all(&buf)),
+        };
+    }
+}
+
+impl TempDir {
+
+    /// Starts a new, empty `Transaction` against this temporary directory.
+    pub fn transaction(&self) -> Transaction {
+        Transaction { dir: self, ops: Vec::new() }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs::{File, PathExt};
+    use std::io::Read;
+
+    use super::super::*;
+
+    #[test]
+    fn test_transaction_commit() {
+        let temp_dir = TempDir::new("test_transaction_commit").unwrap();
+        let mut tx = temp_dir.transaction();
+        tx.create_dir("a/b").write("a/b/c.txt", b"hello");
+        tx.commit().unwrap();
+
+        let mut s = String::new();
+        File::open(&temp_dir.path().join("a/b/c.txt")).unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "hello");
+    }
+
+    #[test]
+    fn test_transaction_rollback() {
+        let temp_dir = TempDir::new("test_transaction_rollback").unwrap();
+        let mut tx = temp_dir.transaction();
+        tx.create_dir("a/b")
+          .write("a/b/c.txt", b"hello")
+          .write("missing/d.txt", b"fails");
+        assert!(tx.commit().is_err());
+        assert!(!temp_dir.path().join("a").exists());
+    }
+
+    #[test]
+    fn test_transaction_rejects_escape() {
+        let temp_dir = TempDir::new("test_transaction_rejects_escape").unwrap();
+        let mut tx = temp_dir.transaction();
+        tx.write("../escaped.txt", b"nope");
+        assert!(tx.commit().is_err());
+    }
+}
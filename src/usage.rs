@@ -0,0 +1,186 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, Metadata};
+use std::io;
+use std::path::Path;
+
+use super::TempDir;
+use walk::walk;
+
+/// Returns the total size in bytes of the regular files below `root`.
+///
+/// Files with several hard links below `root` are counted once, so that
+/// fixture trees built from hard links are not double-counted.
+pub fn size(root: &Path) -> io::Result<u64> {
+    let mut seen = HashSet::new();
+    let mut total = 0;
+    for entry in try!(walk(root)).iter() {
+        if !entry.metadata.is_file() {
+            continue;
+        }
+        if let Some(key) = link_key(&entry.metadata) {
+            if !seen.insert(key) {
+                continue;
+            }
+        }
+        total += entry.metadata.len();
+    }
+    Ok(total)
+}
+
+/// Returns a key identifying the file behind `metadata` if it may have other
+/// hard links.
+#[cfg(unix)]
+fn link_key(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    if metadata.nlink() > 1 {
+        Some((metadata.dev() as u64, metadata.ino() as u64))
+    } else {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+fn link_key(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// The number of entries of each type below a directory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EntryCount {
+    /// Regular files. Every hard link is counted.
+    pub files: u64,
+    /// Directories, not including the root.
+    pub dirs: u64,
+    /// Symbolic links, which are not followed.
+    pub symlinks: u64,
+    /// Anything else, like sockets and FIFOs.
+    pub other: u64,
+}
+
+impl EntryCount {
+
+    /// Returns the number of entries of all types.
+    pub fn total(&self) -> u64 {
+        self.files + self.dirs + self.symlinks + self.other
+    }
+}
+
+/// Counts the entries below `dir` into `count`. Entry types come from the
+/// directory listing where the platform provides them, so no entry has to
+/// be `stat`ed.
+fn count_into(dir: &Path, count: &mut EntryCount) -> io::Result<()> {
+    for entry in try!(fs::read_dir(dir)) {
+        let entry = try!(entry);
+        let file_type = try!(entry.file_type());
+        if file_type.is_dir() {
+            count.dirs += 1;
+            try!(count_into(&entry.path(), count));
+        } else if file_type.is_file() {
+            count.files += 1;
+        } else if file_type.is_symlink() {
+            count.symlinks += 1;
+        } else {
+            count.other += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Formats `bytes` with a binary unit, e.g. `1.5 KiB`.
+fn human_size(bytes: u64) -> String {
+    const UNITS: &'static [&'static str] = &["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+impl TempDir {
+
+    /// Returns the total size in bytes of the files inside the temporary
+    /// directory. Hard links to the same file are counted once.
+    pub fn size(&self) -> io::Result<u64> {
+        size(self.path())
+    }
+
+    /// Counts the files, directories and symbolic links inside the temporary
+    /// directory, e.g. to keep an eye on inode quotas.
+    pub fn entry_count(&self) -> io::Result<EntryCount> {
+        let mut count = EntryCount::default();
+        try!(count_into(self.path(), &mut count));
+        Ok(count)
+    }
+
+    /// Returns a compact description of the temporary directory for failure
+    /// messages and debug output, e.g.
+    /// `/tmp/build.x8Hq2Lw0aBcD (3 entries, 1.5 KiB, 42s old)`.
+    pub fn summary(&self) -> io::Result<String> {
+        let entries = try!(walk(self.path())).len();
+        let size = try!(self.size());
+        Ok(format!("{} ({} entries, {}, {}s old)",
+                   self.path().display(), entries, human_size(size), self.age().as_secs()))
+    }
+}
+
+/// Formats the directory with `summary`, or just its path if the summary
+/// cannot be computed.
+impl fmt::Display for TempDir {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.summary() {
+            Ok(summary) => write!(f, "{}", summary),
+            Err(_) => write!(f, "{}", self.path().display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs;
+
+    use super::super::*;
+
+    #[test]
+    fn test_size_hard_links() {
+        let temp_dir = TempDir::new("test_size_hard_links").unwrap();
+        temp_dir.write("a", b"hello").unwrap();
+        temp_dir.write("b", b"abc").unwrap();
+        fs::hard_link(&temp_dir.path().join("a"), &temp_dir.path().join("c")).unwrap();
+        assert_eq!(temp_dir.size().unwrap(), 8);
+    }
+
+    #[test]
+    fn test_summary() {
+        let temp_dir = TempDir::new("test_summary").unwrap();
+        temp_dir.write("a", &[0; 1536]).unwrap();
+        let summary = format!("{}", temp_dir);
+        assert!(summary.starts_with(&format!("{} (1 entries, 1.5 KiB, ", temp_dir.path().display())));
+    }
+
+    #[test]
+    fn test_entry_count() {
+        let temp_dir = TempDir::new("test_entry_count").unwrap();
+        fs::create_dir(&temp_dir.path().join("d")).unwrap();
+        temp_dir.write("d/a", b"a").unwrap();
+        temp_dir.write("b", b"b").unwrap();
+        let count = temp_dir.entry_count().unwrap();
+        assert_eq!((count.files, count.dirs), (2, 1));
+        assert_eq!(count.total(), 3);
+    }
+}
@@ -0,0 +1,84 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Private per-user directories below a shared base directory.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Returns the per-user directory below `base`, creating it if necessary.
+///
+/// On Unix the directory is named `user-$UID` and created with mode 0700. An
+/// existing directory is only used if it is a real directory (not a symbolic
+/// link) owned by the current user and inaccessible to anybody else, since
+/// anyone can pre-create names in a shared `/tmp`.
+#[cfg(unix)]
+pub fn user_dir(base: &Path) -> io::Result<PathBuf> {
+    use libc;
+    use std::os::unix::fs::MetadataExt;
+    use Error;
+    use builder;
+
+    let uid = unsafe { libc::getuid() };
+    let path = base.join(&format!("user-{}", uid));
+    // Never accessible to others, not even between `mkdir` and `chmod`.
+    match builder::create_dir(&path, 0o700) {
+        Ok(()) => (),
+        Err(ref e) if e.kind() == io::ErrorKind::PathAlreadyExists => (),
+        Err(e) => return Err(e),
+    }
+
+    // Whether we created it or found it, make sure nobody else can get in.
+    let metadata = try!(fs::symlink_metadata(&path));
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        return Err(Error::NotPrivate(path).into_io_error());
+    }
+    Ok(path)
+}
+
+/// Returns the per-user directory below `base`, creating it if necessary.
+///
+/// On Windows the directory is named after the `USERNAME` environment
+/// variable; the base directory is usually per-user already.
+#[cfg(windows)]
+pub fn user_dir(base: &Path) -> io::Result<PathBuf> {
+    use std::env;
+
+    let name = env::var("USERNAME").unwrap_or("unknown".to_string());
+    let path = base.join(&format!("user-{}", name));
+    match fs::create_dir(&path) {
+        Ok(()) => Ok(path),
+        Err(ref e) if e.kind() == io::ErrorKind::PathAlreadyExists => Ok(path),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+
+    use std::fs;
+    use std::io;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    use super::super::*;
+    use super::user_dir;
+
+    #[test]
+    fn test_user_dir_is_private() {
+        let base = TempDir::new("test_user_dir_is_private").unwrap();
+        let path = user_dir(base.path()).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().mode() & 0o777, 0o700);
+        assert_eq!(user_dir(base.path()).unwrap(), path);
+
+        // An existing directory others can get into is refused.
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        let err = user_dir(base.path()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    }
+}
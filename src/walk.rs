@@ -0,0 +1,52 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Recursive directory traversal shared by the inspection and cleanup helpers.
+
+use std::fs::{self, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// An entry found below a traversal root.
+pub struct Entry {
+    /// The full path of the entry.
+    pub path: PathBuf,
+    /// The metadata of the entry itself; symbolic links are not followed.
+    pub metadata: Metadata,
+}
+
+/// Returns every entry below `root` (not including `root` itself) in
+/// pre-order, with the entries of each directory sorted by name. Symbolic
+/// links are reported but never followed.
+pub fn walk(root: &Path) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    try!(walk_into(root, &mut entries));
+    Ok(entries)
+}
+
+fn walk_into(dir: &Path, entries: &mut Vec<Entry>) -> io::Result<()> {
+    let mut children = Vec::new();
+    for child in try!(fs::read_dir(dir)) {
+        children.push(try!(child).path());
+    }
+    children.sort();
+    for path in children.into_iter() {
+        let metadata = try!(fs::symlink_metadata(&path));
+        let is_dir = metadata.is_dir();
+        entries.push(Entry { path: path.clone(), metadata: metadata });
+        if is_dir {
+            try!(walk_into(&path, entries));
+        }
+    }
+    Ok(())
+}
+
+/// Returns `path` relative to `root`. `path` must be below `root`.
+pub fn relative<'a>(root: &Path, path: &'a Path) -> &'a Path {
+    path.relative_from(root).unwrap()
+}
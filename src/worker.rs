@@ -0,0 +1,132 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::HashMap;
+use std::ffi::{AsOsStr, OsString};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
+
+use super::{TempDir, temp_dir};
+
+static NEXT_THREAD: AtomicUsize = ATOMIC_USIZE_INIT;
+
+thread_local!(static THREAD: usize = NEXT_THREAD.fetch_add(1, Ordering::SeqCst));
+
+/// One temporary directory per worker thread, created lazily on first use
+/// and removed when the `WorkerDirs` is dropped.
+///
+/// Share it between the workers of a thread pool (e.g. in an `Arc`) and drop
+/// it once the pool has shut down; every worker gets its own scratch area
+/// without any contention on the directories themselves.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use std::thread;
+/// use tempdir::WorkerDirs;
+///
+/// let dirs = Arc::new(WorkerDirs::new("worker"));
+/// let handles: Vec<_> = (0..4).map(|_| {
+///     let dirs = dirs.clone();
+///     thread::spawn(move || {
+///         let scratch = dirs.get().unwrap();
+///         // ... use `scratch` ...
+///     })
+/// }).collect();
+/// for h in handles { h.join().unwrap(); }
+/// // The four directories are removed here.
+/// drop(dirs);
+/// ```
+pub struct WorkerDirs {
+    root: PathBuf,
+    prefix: OsString,
+    dirs: Mutex<HashMap<usize, TempDir>>,
+}
+
+impl WorkerDirs {
+
+    /// Creates an empty set of per-thread directories, which will be created
+    /// inside of `temp_dir()` with the prefix `prefix`.
+    pub fn new<P: ?Sized>(prefix: &P) -> WorkerDirs
+        where P: AsOsStr
+    {
+        WorkerDirs::new_in(&temp_dir(), prefix)
+    }
+
+    /// Creates an empty set of per-thread directories, which will be created
+    /// inside of `root` with the prefix `prefix`.
+    pub fn new_in<P: ?Sized>(root: &Path, prefix: &P) -> WorkerDirs
+        where P: AsOsStr
+    {
+        WorkerDirs {
+            root: root.to_path_buf(),
+            prefix: prefix.as_os_str().to_os_string(),
+            dirs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the path of the calling thread's directory, creating it if
+    /// this is the thread's first call.
+    pub fn get(&self) -> io::Result<PathBuf> {
+        let id = THREAD.with(|id| *id);
+        let mut dirs = self.dirs.lock().unwrap();
+        if let Some(dir) = dirs.get(&id) {
+            return Ok(dir.path().to_path_buf());
+        }
+        let dir = try!(TempDir::new_in(&self.root, &self.prefix));
+        let path = dir.path().to_path_buf();
+        dirs.insert(id, dir);
+        Ok(path)
+    }
+
+    /// Removes the calling thread's directory, if it has one, e.g. from a
+    /// thread pool's per-worker exit handler.
+    pub fn release_current(&self) -> io::Result<()> {
+        let id = THREAD.with(|id| *id);
+        let dir = self.dirs.lock().unwrap().remove(&id);
+        match dir {
+            Some(dir) => Ok(try!(dir.close())),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the number of directories created so far and not yet
+    /// released.
+    pub fn len(&self) -> usize {
+        self.dirs.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs::PathExt;
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::super::*;
+
+    #[test]
+    fn test_worker_dirs() {
+        let dirs = Arc::new(WorkerDirs::new("test_worker_dirs"));
+        let mine = dirs.get().unwrap();
+        assert_eq!(dirs.get().unwrap(), mine);
+
+        let other = dirs.clone();
+        let theirs = thread::spawn(move || other.get().unwrap()).join().unwrap();
+        assert!(theirs != mine);
+        assert_eq!(dirs.len(), 2);
+
+        drop(dirs);
+        assert!(!mine.exists());
+        assert!(!theirs.exists());
+    }
+}
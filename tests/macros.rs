@@ -0,0 +1,67 @@
+// Copyright 2015 The Rust Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Tests of `tempdir_test!`. The tests it defines are ignored and run in a
+//! child process, so that what happens to their directories once they
+//! finish can be observed.
+
+#[macro_use]
+extern crate tempdir;
+
+use std::env;
+use std::fs::{self, File, PathExt};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+tempdir_test! {
+    #[ignore]
+    fn child_passes(dir: &Path) {
+        println!("path={}", dir.display());
+        assert!(dir.is_dir());
+        File::create(&dir.join("output")).unwrap();
+    }
+
+    #[ignore]
+    #[should_panic(expected = "failed on purpose")]
+    fn child_panics(dir: &Path) {
+        println!("path={}", dir.display());
+        File::create(&dir.join("evidence")).unwrap();
+        panic!("failed on purpose");
+    }
+}
+
+/// Runs the ignored test `name` in a child process, returning whether it
+/// passed, the path of its directory and what it printed to stderr.
+fn run_child(name: &str) -> (bool, PathBuf, String) {
+    let output = Command::new(&env::current_exe().unwrap())
+                         .args(&[name, "--exact", "--ignored", "--nocapture", "--test-threads=1"])
+                         .output()
+                         .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    let path = stdout.lines().filter(|line| line.starts_with("path=")).next().unwrap()[5..]
+                     .to_string();
+    (output.status.success(), PathBuf::new(&path),
+     String::from_utf8_lossy(&output.stderr).into_owned())
+}
+
+#[test]
+fn test_tempdir_test_passing() {
+    let (passed, path, _) = run_child("child_passes");
+    assert!(passed);
+    assert!(path.file_name().unwrap().to_str().unwrap().contains("child_passes"));
+    assert!(!path.exists());
+}
+
+#[test]
+fn test_tempdir_test_should_panic() {
+    let (passed, path, stderr) = run_child("child_panics");
+    assert!(passed);
+    assert!(path.join("evidence").exists());
+    assert!(stderr.contains(&format!("tempdir: keeping {}", path.display())));
+    fs::remove_dir_all(&path).unwrap();
+}